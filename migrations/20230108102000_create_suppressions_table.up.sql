@@ -0,0 +1,6 @@
+create table
+  suppressions (
+    email text primary key,
+    reason text not null,
+    created_at timestamptz not null
+  );
@@ -0,0 +1,8 @@
+drop trigger subscriptions_set_updated_at on subscriptions;
+
+drop function set_updated_at;
+
+alter table
+  subscriptions
+drop column
+  updated_at;
@@ -0,0 +1,15 @@
+alter table
+  subscriptions
+add column
+  updated_at timestamptz not null default now();
+
+create function set_updated_at() returns trigger as $$
+begin
+  new.updated_at = now();
+  return new;
+end;
+$$ language plpgsql;
+
+create trigger subscriptions_set_updated_at before
+update
+  on subscriptions for each row execute procedure set_updated_at();
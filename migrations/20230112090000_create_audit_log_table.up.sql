@@ -0,0 +1,11 @@
+create table
+  audit_log (
+    id uuid primary key,
+    user_id uuid not null references users (user_id),
+    action text not null,
+    target text not null,
+    metadata jsonb not null default '{}',
+    created_at timestamptz not null default now()
+  );
+
+create index audit_log_created_at_idx on audit_log (created_at);
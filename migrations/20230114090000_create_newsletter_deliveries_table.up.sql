@@ -0,0 +1,8 @@
+create table
+  newsletter_deliveries (
+    newsletter_issue_id uuid not null,
+    subscriber_email text not null,
+    status text not null,
+    updated_at timestamptz not null default now(),
+    primary key (newsletter_issue_id, subscriber_email)
+  );
@@ -0,0 +1,2 @@
+drop table
+  email_queue;
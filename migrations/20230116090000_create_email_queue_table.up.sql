@@ -0,0 +1,11 @@
+create table
+  email_queue (
+    id uuid primary key,
+    recipient text not null,
+    subject text not null,
+    html_body text not null,
+    text_body text not null,
+    n_retries smallint not null default 0,
+    execute_after timestamptz not null default now(),
+    created_at timestamptz not null default now()
+  );
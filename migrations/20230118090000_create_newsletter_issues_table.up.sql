@@ -0,0 +1,7 @@
+create table
+  newsletter_issues (
+    newsletter_issue_id uuid primary key,
+    title text not null,
+    published_by uuid not null references users (user_id),
+    created_at timestamptz not null default now()
+  );
@@ -0,0 +1,6 @@
+drop table
+  issue_delivery_queue;
+
+alter table newsletter_issues
+  drop column html_content,
+  drop column text_content;
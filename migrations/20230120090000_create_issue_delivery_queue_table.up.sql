@@ -0,0 +1,16 @@
+alter table newsletter_issues
+  add column html_content text not null default '',
+  add column text_content text not null default '';
+
+alter table newsletter_issues
+  alter column html_content drop default,
+  alter column text_content drop default;
+
+create table
+  issue_delivery_queue (
+    newsletter_issue_id uuid not null references newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid not null references subscriptions (id) on delete cascade,
+    n_retries smallint not null default 0,
+    execute_after timestamptz not null default now(),
+    primary key (newsletter_issue_id, subscriber_id)
+  );
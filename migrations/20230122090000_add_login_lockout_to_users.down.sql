@@ -0,0 +1,3 @@
+alter table users
+  drop column failed_login_attempts,
+  drop column locked_until;
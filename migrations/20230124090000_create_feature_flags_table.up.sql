@@ -0,0 +1,6 @@
+create table
+  feature_flags (
+    name text primary key,
+    enabled boolean not null,
+    updated_at timestamptz not null
+  );
@@ -0,0 +1,4 @@
+drop index subscriptions_confirmation_token_hash_idx;
+
+alter table subscriptions
+  drop column confirmation_token_hash;
@@ -0,0 +1,4 @@
+alter table subscriptions
+  add column confirmation_token_hash text;
+
+create index subscriptions_confirmation_token_hash_idx on subscriptions (confirmation_token_hash);
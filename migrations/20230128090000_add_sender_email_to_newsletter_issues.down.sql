@@ -0,0 +1,2 @@
+alter table newsletter_issues
+  drop column sender_email;
@@ -0,0 +1,2 @@
+alter table newsletter_issues
+  add column sender_email text;
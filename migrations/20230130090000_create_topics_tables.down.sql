@@ -0,0 +1,8 @@
+alter table newsletter_issues
+  drop column topic;
+
+drop table
+  subscriber_topics;
+
+drop table
+  topics;
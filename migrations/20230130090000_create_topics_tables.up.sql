@@ -0,0 +1,21 @@
+create table
+  topics (
+    name text primary key,
+    created_at timestamptz not null default now()
+  );
+
+create table
+  subscriber_topics (
+    subscriber_id uuid not null references subscriptions (id) on delete cascade,
+    topic text not null references topics (name),
+    primary key (subscriber_id, topic)
+  );
+
+insert into
+  topics (name)
+values
+  ('weekly'),
+  ('product');
+
+alter table newsletter_issues
+  add column topic text references topics (name);
@@ -0,0 +1,4 @@
+drop index topics_is_default_idx;
+
+alter table topics
+  drop column is_default;
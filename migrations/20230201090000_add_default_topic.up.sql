@@ -0,0 +1,8 @@
+alter table topics
+  add column is_default boolean not null default false;
+
+-- At most one topic is the default: the one subscriptions without topics join.
+-- The application applies the configured one on start.
+create unique index topics_is_default_idx on topics (is_default)
+where
+  is_default;
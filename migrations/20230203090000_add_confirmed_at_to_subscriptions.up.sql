@@ -0,0 +1,10 @@
+alter table subscriptions
+  add column confirmed_at timestamptz;
+
+-- The last update of a confirmed subscriber is the closest we have to when
+-- they confirmed.
+update subscriptions
+set
+  confirmed_at = updated_at
+where
+  status = 'confirmed';
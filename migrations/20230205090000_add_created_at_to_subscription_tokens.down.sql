@@ -0,0 +1,2 @@
+alter table subscription_tokens
+  drop column created_at;
@@ -0,0 +1,3 @@
+-- Tokens issued before this migration start their lifetime now.
+alter table subscription_tokens
+  add column created_at timestamptz not null default now();
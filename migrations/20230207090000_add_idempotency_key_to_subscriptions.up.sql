@@ -0,0 +1,3 @@
+-- Set by clients retrying a subscription; several rows may leave it null.
+alter table subscriptions
+  add column idempotency_key text unique;
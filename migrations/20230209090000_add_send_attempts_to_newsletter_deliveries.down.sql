@@ -0,0 +1,2 @@
+alter table newsletter_deliveries
+  drop column send_attempts;
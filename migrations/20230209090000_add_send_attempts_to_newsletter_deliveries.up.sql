@@ -0,0 +1,3 @@
+-- Every attempt at sending the issue to the subscriber, failed ones included.
+alter table newsletter_deliveries
+  add column send_attempts integer not null default 0;
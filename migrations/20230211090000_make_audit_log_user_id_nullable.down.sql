@@ -0,0 +1,6 @@
+delete from audit_log
+where
+  user_id is null;
+
+alter table audit_log
+  alter column user_id set not null;
@@ -0,0 +1,4 @@
+-- Subscribers act too, e.g. re-subscribing lifts the suppression of their
+-- address: their entries have no user.
+alter table audit_log
+  alter column user_id drop not null;
@@ -0,0 +1,2 @@
+drop table
+  management_tokens;
@@ -0,0 +1,9 @@
+-- The token in the links letting a subscriber see and manage their
+-- subscription. Unlike a confirmation token it is not consumed on use.
+create table
+  management_tokens (
+    management_token text not null,
+    subscriber_id uuid not null unique references subscriptions (id) on delete cascade,
+    created_at timestamptz not null default now(),
+    primary key (management_token)
+  );
@@ -0,0 +1,3 @@
+-- The backfilled tokens cannot be told apart from the others: keep them all.
+select
+  1;
@@ -0,0 +1,18 @@
+-- Subscribers who signed up before management tokens existed get one, so
+-- that every email can link to the status of the subscription.
+insert into
+  management_tokens (management_token, subscriber_id)
+select
+  replace(gen_random_uuid()::text, '-', ''),
+  s.id
+from
+  subscriptions s
+where
+  not exists (
+    select
+      1
+    from
+      management_tokens m
+    where
+      m.subscriber_id = s.id
+  );
@@ -0,0 +1,201 @@
+//! Apache-style access logs, for log pipelines that expect them, written
+//! next to the structured logs rather than instead of them.
+use std::io::Write;
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest};
+use chrono::{DateTime, Utc};
+
+use crate::client_ip::ClientIp;
+use crate::telemetry::redact_subscription_token;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// `host ident user [time] "request" status bytes`
+    Common,
+    /// The common format followed by `"referer" "user-agent"`.
+    Combined,
+}
+
+/// Writes one line per request in `format`, shared as app data; requests go
+/// unlogged without a format.
+pub struct AccessLog {
+    format: Option<AccessLogFormat>,
+    write: Box<dyn Fn(&str) + Send + Sync>,
+}
+
+impl AccessLog {
+    /// Write the lines to stdout.
+    pub fn stdout(format: Option<AccessLogFormat>) -> Self {
+        Self::new(format, |line| {
+            let _ = writeln!(std::io::stdout().lock(), "{}", line);
+        })
+    }
+
+    pub fn new(
+        format: Option<AccessLogFormat>,
+        write: impl Fn(&str) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            format,
+            write: Box::new(write),
+        }
+    }
+}
+
+/// What an access log line says about a request and its response.
+struct Entry<'a> {
+    host: String,
+    time: DateTime<Utc>,
+    request_line: String,
+    status: u16,
+    bytes: Option<u64>,
+    referer: Option<&'a str>,
+    user_agent: Option<&'a str>,
+}
+
+impl Entry<'_> {
+    fn format(&self, format: AccessLogFormat) -> String {
+        let bytes = match self.bytes {
+            Some(bytes) if bytes > 0 => bytes.to_string(),
+            _ => "-".into(),
+        };
+        let common = format!(
+            r#"{} - - [{}] "{}" {} {}"#,
+            self.host,
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.request_line,
+            self.status,
+            bytes
+        );
+        match format {
+            AccessLogFormat::Common => common,
+            AccessLogFormat::Combined => format!(
+                r#"{} "{}" "{}""#,
+                common,
+                self.referer.unwrap_or("-"),
+                self.user_agent.unwrap_or("-")
+            ),
+        }
+    }
+}
+
+/// Write an access log line once the response is ready.
+pub async fn log_access<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let access_log = req.app_data::<web::Data<AccessLog>>().cloned();
+    let Some((access_log, format)) = access_log.and_then(|l| l.format.map(|f| (l, f))) else {
+        return next.call(req).await;
+    };
+    let time = Utc::now();
+    let host = match ClientIp::extract(req.request()).await {
+        Ok(ClientIp(Some(ip))) => ip.to_string(),
+        _ => "-".into(),
+    };
+    let target = redact_subscription_token(req.uri()).unwrap_or_else(|| req.uri().to_string());
+    let request_line = format!("{} {} {:?}", req.method(), target, req.version());
+
+    let response = next.call(req).await?;
+    let headers = response.request().headers();
+    let entry = Entry {
+        host,
+        time,
+        request_line,
+        status: response.status().as_u16(),
+        bytes: match response.response().body().size() {
+            BodySize::Sized(bytes) => Some(bytes),
+            BodySize::None | BodySize::Stream => None,
+        },
+        referer: headers.get(header::REFERER).and_then(|h| h.to_str().ok()),
+        user_agent: headers
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok()),
+    };
+    (access_log.write)(&entry.format(format));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::middleware::from_fn;
+    use actix_web::{test as actix_test, web, App, HttpResponse};
+    use chrono::TimeZone;
+
+    use super::{log_access, AccessLog, AccessLogFormat, Entry};
+
+    fn entry() -> Entry<'static> {
+        Entry {
+            host: "127.0.0.1".into(),
+            time: chrono::Utc
+                .with_ymd_and_hms(2023, 2, 5, 13, 55, 36)
+                .unwrap(),
+            request_line: "GET /health_check HTTP/1.1".into(),
+            status: 200,
+            bytes: Some(2326),
+            referer: Some("https://example.com/"),
+            user_agent: None,
+        }
+    }
+
+    #[test]
+    fn the_common_format_matches_apaches() {
+        assert_eq!(
+            entry().format(AccessLogFormat::Common),
+            r#"127.0.0.1 - - [05/Feb/2023:13:55:36 +0000] "GET /health_check HTTP/1.1" 200 2326"#
+        );
+    }
+
+    #[test]
+    fn the_combined_format_adds_the_referer_and_user_agent() {
+        assert_eq!(
+            entry().format(AccessLogFormat::Combined),
+            r#"127.0.0.1 - - [05/Feb/2023:13:55:36 +0000] "GET /health_check HTTP/1.1" 200 2326 "https://example.com/" "-""#
+        );
+    }
+
+    #[actix_web::test]
+    async fn a_request_is_logged_with_its_method_and_status() {
+        let lines = Arc::new(Mutex::new(Vec::<String>::new()));
+        let access_log = {
+            let lines = lines.clone();
+            AccessLog::new(Some(AccessLogFormat::Combined), move |line| {
+                lines.lock().unwrap().push(line.to_owned())
+            })
+        };
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(access_log))
+                .wrap(from_fn(log_access))
+                .route(
+                    "/subscriptions/confirm",
+                    web::get().to(|| async { HttpResponse::Unauthorized().body("nope") }),
+                ),
+        )
+        .await;
+
+        let request = actix_test::TestRequest::get()
+            .uri("/subscriptions/confirm?subscription_token=s3cr3t")
+            .insert_header(("User-Agent", "curl/8.0"))
+            .to_request();
+        actix_test::call_service(&app, request).await;
+
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 1);
+        let line = &lines[0];
+        assert!(
+            line.contains(
+                r#""GET /subscriptions/confirm?subscription_token=[redacted] HTTP/1.1" 401 4 "-" "curl/8.0""#
+            ),
+            "{}",
+            line
+        );
+    }
+}
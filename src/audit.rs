@@ -0,0 +1,74 @@
+//! A trail of the actions taken by admins: who published which newsletter
+//! issue, who changed their password, who deleted which subscriber. Some
+//! actions of subscribers are recorded too, e.g. lifting a suppression by
+//! re-subscribing.
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+pub const CONFIRMATIONS_RESENT: &str = "subscriptions.resend_pending";
+pub const FEATURE_FLAG_CHANGED: &str = "feature_flag.change";
+pub const NEWSLETTER_PUBLISHED: &str = "newsletter.publish";
+pub const PASSWORD_CHANGED: &str = "password.change";
+pub const SUBSCRIBER_DELETED: &str = "subscriber.delete";
+pub const SUBSCRIBER_RESUBSCRIBED: &str = "subscriber.resubscribe";
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    /// `None` for the actions of subscribers.
+    pub user_id: Option<Uuid>,
+    pub action: String,
+    pub target: String,
+    pub metadata: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Record that `user_id`, or a subscriber when `None`, performed `action` on
+/// `target`. Pass a transaction to make the entry part of the action it
+/// describes.
+#[tracing::instrument(name = "Recording an audit entry", skip(executor, metadata))]
+pub async fn record_audit_entry(
+    executor: impl PgExecutor<'_>,
+    user_id: Option<Uuid>,
+    action: &str,
+    target: &str,
+    metadata: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (id, user_id, action, target, metadata, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        action,
+        target,
+        metadata
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// The most recent entries first.
+#[tracing::instrument(name = "Listing audit entries", skip(pool))]
+pub async fn list_audit_entries(
+    pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AuditEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        AuditEntry,
+        r#"
+        SELECT id, user_id, action, target, metadata, created_at
+        FROM audit_log
+        ORDER BY created_at DESC, id
+        LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,103 @@
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::db_pool::acquire;
+use crate::metrics::Metrics;
+
+/// Lock an account for `duration` once `max_failed_attempts` logins in a row
+/// have failed, slowing down password guessing against it.
+#[derive(Clone, Copy, Debug)]
+pub struct LoginLockout {
+    pub max_failed_attempts: u32,
+    pub duration: Duration,
+}
+
+/// When the account's lockout ends; `None` if it is not locked (or does not
+/// exist).
+#[tracing::instrument(name = "Get account lockout", skip(username, pool, metrics))]
+pub async fn locked_until(
+    username: &str,
+    now: DateTime<Utc>,
+    pool: &PgPool,
+    metrics: &Metrics,
+) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let mut connection = acquire(pool, metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let locked_until = sqlx::query_scalar!(
+        r#"
+        SELECT locked_until
+        FROM users
+        WHERE username = $1 AND locked_until > $2
+        "#,
+        username,
+        now,
+    )
+    .fetch_optional(&mut connection)
+    .await
+    .context("Failed to check whether the account is locked.")?
+    .flatten();
+    Ok(locked_until)
+}
+
+/// Count a failed login; reaching the threshold locks the account and starts
+/// a fresh count for when the lockout ends.
+#[tracing::instrument(name = "Record failed login", skip(username, pool, metrics))]
+pub async fn record_failed_login(
+    username: &str,
+    now: DateTime<Utc>,
+    lockout: &LoginLockout,
+    pool: &PgPool,
+    metrics: &Metrics,
+) -> Result<(), anyhow::Error> {
+    let mut connection = acquire(pool, metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET
+            failed_login_attempts = CASE
+                WHEN failed_login_attempts + 1 >= $2 THEN 0
+                ELSE failed_login_attempts + 1
+            END,
+            locked_until = CASE
+                WHEN failed_login_attempts + 1 >= $2 THEN $3
+                ELSE locked_until
+            END
+        WHERE username = $1
+        "#,
+        username,
+        lockout.max_failed_attempts as i32,
+        now + lockout.duration,
+    )
+    .execute(&mut connection)
+    .await
+    .context("Failed to record a failed login.")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Reset failed logins", skip(pool, metrics))]
+pub async fn reset_failed_logins(
+    user_id: Uuid,
+    pool: &PgPool,
+    metrics: &Metrics,
+) -> Result<(), anyhow::Error> {
+    let mut connection = acquire(pool, metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET failed_login_attempts = 0, locked_until = NULL
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .execute(&mut connection)
+    .await
+    .context("Failed to reset the failed login count.")?;
+    Ok(())
+}
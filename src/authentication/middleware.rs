@@ -0,0 +1,287 @@
+use std::ops::Deref;
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpMessage};
+use chrono::{DateTime, Duration, Utc};
+use tracing_actix_web::RootSpan;
+use uuid::Uuid;
+
+use crate::clock::Clock;
+use crate::error::{e500, see_other};
+use crate::session_state::TypedSession;
+use crate::startup::ApplicationBasePath;
+
+/// The id of the authenticated admin, available as a request extension
+/// (`web::ReqData<UserId>`) on routes behind [`reject_anonymous_users`].
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// How long an admin session may last.
+#[derive(Copy, Clone, Debug)]
+pub struct SessionTimeouts {
+    /// Maximum time between two requests; every request slides the window.
+    pub idle: Duration,
+    /// Maximum lifetime of a session, however active it is.
+    pub absolute: Duration,
+}
+
+impl SessionTimeouts {
+    /// Sessions without timestamps predate the timeouts and are expired.
+    pub fn is_expired(
+        &self,
+        created_at: Option<DateTime<Utc>>,
+        last_seen_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let Some(created_at) = created_at else {
+            return true;
+        };
+        let last_seen_at = last_seen_at.unwrap_or(created_at);
+        now - last_seen_at > self.idle || now - created_at > self.absolute
+    }
+}
+
+pub async fn reject_anonymous_users<B: MessageBody>(
+    mut req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let timeouts = *req
+        .app_data::<web::Data<SessionTimeouts>>()
+        .ok_or_else(|| e500("Session timeouts are not configured"))?
+        .get_ref();
+    let now = req
+        .app_data::<web::Data<dyn Clock>>()
+        .ok_or_else(|| e500("No clock is configured"))?
+        .now();
+
+    match session.get_user_id().map_err(e500)? {
+        Some(user_id) => {
+            let created_at = session.get_created_at().map_err(e500)?;
+            let last_seen_at = session.get_last_seen_at().map_err(e500)?;
+            if timeouts.is_expired(created_at, last_seen_at, now) {
+                session.purge();
+                return Err(redirect_to_login(&req, "The session has expired"));
+            }
+            session.touch(now).map_err(e500)?;
+
+            if let Some(root_span) = req.extensions().get::<RootSpan>() {
+                root_span.record("user_id", tracing::field::display(user_id));
+            }
+            req.extensions_mut().insert(UserId(user_id));
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+        None => Err(redirect_to_login(&req, "The user has not logged in")),
+    }
+}
+
+fn redirect_to_login(req: &ServiceRequest, reason: &'static str) -> actix_web::Error {
+    let base_path = req
+        .app_data::<web::Data<ApplicationBasePath>>()
+        .map_or("", |base_path| base_path.0.as_str());
+    let response = see_other(&format!("{}/login", base_path));
+    InternalError::from_response(anyhow::anyhow!(reason), response).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use actix_session::storage::CookieSessionStore;
+    use actix_session::SessionMiddleware;
+    use actix_web::body::MessageBody;
+    use actix_web::cookie::{Cookie, Key};
+    use actix_web::dev::ServiceResponse;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App, HttpResponse};
+    use chrono::{Duration, Utc};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::Subscriber;
+    use tracing_actix_web::TracingLogger;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+    use uuid::Uuid;
+
+    use super::{reject_anonymous_users, SessionTimeouts};
+    use crate::clock::{Clock, MockClock};
+    use crate::session_state::TypedSession;
+    use crate::telemetry::AppRootSpanBuilder;
+
+    const USER_ID: Uuid = Uuid::from_u128(0x2a);
+
+    /// An app with a `/login` route that starts a session for `USER_ID` and
+    /// an `/admin/` route behind `reject_anonymous_users`.
+    macro_rules! admin_app {
+        ($clock:expr) => {{
+            let clock: Arc<MockClock> = $clock;
+            let timeouts = SessionTimeouts {
+                idle: Duration::minutes(10),
+                absolute: Duration::hours(1),
+            };
+            let login_clock = clock.clone();
+            test::init_service(
+                App::new()
+                    .wrap(SessionMiddleware::new(
+                        CookieSessionStore::default(),
+                        Key::from(&[0; 64]),
+                    ))
+                    .wrap(TracingLogger::<AppRootSpanBuilder>::new())
+                    .app_data(web::Data::new(timeouts))
+                    .app_data(web::Data::<dyn Clock>::from(clock as Arc<dyn Clock>))
+                    .route(
+                        "/login",
+                        web::post().to(move |session: TypedSession| {
+                            let now = login_clock.now();
+                            async move {
+                                session.log_in(USER_ID, now).unwrap();
+                                HttpResponse::Ok().finish()
+                            }
+                        }),
+                    )
+                    .service(
+                        web::scope("/admin")
+                            .wrap(from_fn(reject_anonymous_users))
+                            .route("/", web::get().to(HttpResponse::Ok)),
+                    ),
+            )
+            .await
+        }};
+    }
+
+    fn session_cookie<B: MessageBody>(response: &ServiceResponse<B>) -> Cookie<'static> {
+        response.response().cookies().next().unwrap().into_owned()
+    }
+
+    /// Collects every value recorded into a `user_id` span field.
+    #[derive(Clone, Default)]
+    struct UserIdCapture(Arc<Mutex<Vec<String>>>);
+
+    impl Visit for UserIdCapture {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "user_id" {
+                self.0.lock().unwrap().push(format!("{:?}", value));
+            }
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for UserIdCapture {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            attrs.record(&mut self.clone());
+        }
+
+        fn on_record(&self, _id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+            values.record(&mut self.clone());
+        }
+    }
+
+    #[actix_web::test]
+    async fn the_user_id_is_recorded_on_the_request_span() {
+        let capture = UserIdCapture::default();
+        let subscriber = tracing_subscriber::registry().with(capture.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let app = admin_app!(Arc::new(MockClock::new(Utc::now())));
+
+        let anonymous = test::TestRequest::get().uri("/admin/").to_request();
+        assert!(test::try_call_service(&app, anonymous).await.is_err());
+        assert!(capture.0.lock().unwrap().is_empty());
+
+        let login = test::TestRequest::post().uri("/login").to_request();
+        let cookie = session_cookie(&test::call_service(&app, login).await);
+
+        let request = test::TestRequest::get()
+            .uri("/admin/")
+            .cookie(cookie)
+            .to_request();
+        let response = test::call_service(&app, request).await;
+
+        assert!(response.status().is_success());
+        assert_eq!(*capture.0.lock().unwrap(), vec![USER_ID.to_string()]);
+    }
+
+    #[actix_web::test]
+    async fn a_session_used_within_the_idle_window_stays_valid() {
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let app = admin_app!(clock.clone());
+        let login = test::TestRequest::post().uri("/login").to_request();
+        let mut cookie = session_cookie(&test::call_service(&app, login).await);
+
+        // 32 minutes in total, more than the idle timeout, but never more
+        // than 8 minutes between two requests.
+        for _ in 0..4 {
+            clock.advance(Duration::minutes(8));
+            let request = test::TestRequest::get()
+                .uri("/admin/")
+                .cookie(cookie)
+                .to_request();
+            let response = test::call_service(&app, request).await;
+
+            assert!(response.status().is_success());
+            cookie = session_cookie(&response);
+        }
+    }
+
+    #[actix_web::test]
+    async fn an_idle_session_is_rejected() {
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let app = admin_app!(clock.clone());
+        let login = test::TestRequest::post().uri("/login").to_request();
+        let cookie = session_cookie(&test::call_service(&app, login).await);
+
+        clock.advance(Duration::minutes(11));
+        let request = test::TestRequest::get()
+            .uri("/admin/")
+            .cookie(cookie)
+            .to_request();
+        let error = test::try_call_service(&app, request).await.unwrap_err();
+
+        assert_eq!(error.error_response().status().as_u16(), 303);
+    }
+
+    #[actix_web::test]
+    async fn a_session_past_the_absolute_timeout_is_rejected() {
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let app = admin_app!(clock.clone());
+        let login = test::TestRequest::post().uri("/login").to_request();
+        let mut cookie = session_cookie(&test::call_service(&app, login).await);
+
+        for _ in 0..7 {
+            clock.advance(Duration::minutes(8));
+            let request = test::TestRequest::get()
+                .uri("/admin/")
+                .cookie(cookie)
+                .to_request();
+            cookie = session_cookie(&test::call_service(&app, request).await);
+        }
+        clock.advance(Duration::minutes(8));
+        let request = test::TestRequest::get()
+            .uri("/admin/")
+            .cookie(cookie)
+            .to_request();
+        let error = test::try_call_service(&app, request).await.unwrap_err();
+
+        assert_eq!(error.error_response().status().as_u16(), 303);
+    }
+}
@@ -0,0 +1,14 @@
+mod lockout;
+mod middleware;
+mod password;
+mod password_policy;
+mod seed;
+
+pub use lockout::{locked_until, record_failed_login, reset_failed_logins, LoginLockout};
+pub use middleware::{reject_anonymous_users, SessionTimeouts, UserId};
+pub use password::{
+    change_password, compute_password_hash, get_username, validate_credentials, AuthError,
+    Credentials,
+};
+pub use password_policy::{PasswordPolicy, PasswordViolation};
+pub use seed::{seed_admin, SeedAdminError};
@@ -0,0 +1,196 @@
+use secrecy::{ExposeSecret, Secret};
+
+/// Passwords that satisfy the character rules but top every breach list.
+const COMMON_PASSWORDS: &[&str] = &[
+    "password",
+    "password123!",
+    "p@ssw0rd",
+    "p@ssw0rd1234",
+    "123456789012",
+    "qwerty123456!",
+    "welcome123!",
+    "admin123456!",
+    "letmein12345!",
+    "iloveyou123!",
+    "changeme123!",
+];
+
+/// The rules an admin password must follow.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_mixed_case: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+}
+
+impl Default for PasswordPolicy {
+    /// OWASP's length bounds, with every character class required.
+    fn default() -> Self {
+        Self {
+            min_length: 12,
+            max_length: 128,
+            require_mixed_case: true,
+            require_digit: true,
+            require_symbol: true,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasswordViolation {
+    TooShort(usize),
+    TooLong(usize),
+    MissingMixedCase,
+    MissingDigit,
+    MissingSymbol,
+    TooCommon,
+}
+
+impl std::fmt::Display for PasswordViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort(min) => {
+                write!(f, "The password must be at least {} characters long.", min)
+            }
+            Self::TooLong(max) => {
+                write!(f, "The password must be at most {} characters long.", max)
+            }
+            Self::MissingMixedCase => write!(
+                f,
+                "The password must contain both lowercase and uppercase letters."
+            ),
+            Self::MissingDigit => write!(f, "The password must contain a digit."),
+            Self::MissingSymbol => write!(f, "The password must contain a symbol."),
+            Self::TooCommon => write!(f, "The password is too common."),
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Every rule `password` breaks, in a stable order.
+    pub fn check(&self, password: &Secret<String>) -> Result<(), Vec<PasswordViolation>> {
+        let password = password.expose_secret();
+        let mut violations = Vec::new();
+
+        let length = password.chars().count();
+        if length < self.min_length {
+            violations.push(PasswordViolation::TooShort(self.min_length));
+        }
+        if length > self.max_length {
+            violations.push(PasswordViolation::TooLong(self.max_length));
+        }
+        if self.require_mixed_case
+            && !(password.chars().any(char::is_lowercase)
+                && password.chars().any(char::is_uppercase))
+        {
+            violations.push(PasswordViolation::MissingMixedCase);
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violations.push(PasswordViolation::MissingDigit);
+        }
+        if self.require_symbol && !password.chars().any(|c| !c.is_alphanumeric()) {
+            violations.push(PasswordViolation::MissingSymbol);
+        }
+        if COMMON_PASSWORDS.contains(&password.to_lowercase().as_str()) {
+            violations.push(PasswordViolation::TooCommon);
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PasswordPolicy, PasswordViolation};
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    fn check(password: &str) -> Result<(), Vec<PasswordViolation>> {
+        PasswordPolicy::default().check(&Secret::new(password.to_owned()))
+    }
+
+    #[test]
+    fn a_password_following_every_rule_is_accepted() {
+        assert_ok!(check("Correct-horse-battery-9"));
+    }
+
+    #[test]
+    fn a_short_password_is_rejected() {
+        assert_eq!(
+            check("Sh0rt!pass").unwrap_err(),
+            vec![PasswordViolation::TooShort(12)]
+        );
+    }
+
+    #[test]
+    fn a_long_password_is_rejected() {
+        let password = format!("Aa1!{}", "a".repeat(125));
+        assert_eq!(
+            check(&password).unwrap_err(),
+            vec![PasswordViolation::TooLong(128)]
+        );
+    }
+
+    #[test]
+    fn a_password_without_mixed_case_is_rejected() {
+        assert_eq!(
+            check("correct-horse-battery-9").unwrap_err(),
+            vec![PasswordViolation::MissingMixedCase]
+        );
+        assert_err!(check("CORRECT-HORSE-BATTERY-9"));
+    }
+
+    #[test]
+    fn a_password_without_a_digit_is_rejected() {
+        assert_eq!(
+            check("Correct-horse-battery").unwrap_err(),
+            vec![PasswordViolation::MissingDigit]
+        );
+    }
+
+    #[test]
+    fn a_password_without_a_symbol_is_rejected() {
+        assert_eq!(
+            check("Correcthorsebattery9").unwrap_err(),
+            vec![PasswordViolation::MissingSymbol]
+        );
+    }
+
+    #[test]
+    fn a_common_password_is_rejected() {
+        assert_eq!(
+            check("Password123!").unwrap_err(),
+            vec![PasswordViolation::TooCommon]
+        );
+    }
+
+    #[test]
+    fn every_violated_rule_is_reported() {
+        assert_eq!(
+            check("short").unwrap_err(),
+            vec![
+                PasswordViolation::TooShort(12),
+                PasswordViolation::MissingMixedCase,
+                PasswordViolation::MissingDigit,
+                PasswordViolation::MissingSymbol,
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_rules_are_not_enforced() {
+        let policy = PasswordPolicy {
+            require_mixed_case: false,
+            require_digit: false,
+            require_symbol: false,
+            ..PasswordPolicy::default()
+        };
+        assert_ok!(policy.check(&Secret::new("correcthorsebattery".to_owned())));
+    }
+}
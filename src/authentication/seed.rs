@@ -0,0 +1,59 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::password::compute_password_hash;
+use super::password_policy::{PasswordPolicy, PasswordViolation};
+use crate::error::error_chain_fmt;
+use crate::telemetry::spawn_blocking_with_tracing;
+
+#[derive(thiserror::Error)]
+pub enum SeedAdminError {
+    #[error("The password does not follow the password policy.")]
+    WeakPassword(Vec<PasswordViolation>),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SeedAdminError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Create the admin `username`, or reset its password (and any lockout) if
+/// it already exists.
+#[tracing::instrument(name = "Seed an admin user", skip(password, policy, pool))]
+pub async fn seed_admin(
+    username: &str,
+    password: Secret<String>,
+    policy: &PasswordPolicy,
+    pool: &PgPool,
+) -> Result<Uuid, SeedAdminError> {
+    policy
+        .check(&password)
+        .map_err(SeedAdminError::WeakPassword)?;
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task.")?
+        .context("Failed to hash password")?;
+    let user_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (username) DO UPDATE
+        SET password_hash = EXCLUDED.password_hash,
+            failed_login_attempts = 0,
+            locked_until = NULL
+        RETURNING user_id
+        "#,
+        Uuid::new_v4(),
+        username,
+        password_hash.expose_secret(),
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to store the admin user.")?;
+    Ok(user_id)
+}
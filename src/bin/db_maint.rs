@@ -0,0 +1,51 @@
+//! Drop the databases left behind by test runs:
+//!
+//! ```text
+//! cargo run --bin db-maint -- --drop-test-dbs
+//! ```
+//!
+//! Only lists the databases it would drop unless `--execute` is passed.
+use sqlx::{Connection, PgConnection};
+use zero2prod::configuration::get_configuration;
+use zero2prod::test_databases::{drop_test_database, list_test_databases};
+
+const USAGE: &str = "Usage: db-maint --drop-test-dbs [--dry-run | --execute]";
+
+/// Whether to actually drop the databases rather than list them.
+fn parse_execute(mut args: impl Iterator<Item = String>) -> Result<bool, String> {
+    match (args.next().as_deref(), args.next().as_deref(), args.next()) {
+        (Some("--drop-test-dbs"), None | Some("--dry-run"), None) => Ok(false),
+        (Some("--drop-test-dbs"), Some("--execute"), None) => Ok(true),
+        _ => Err(USAGE.into()),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let execute = match parse_execute(std::env::args().skip(1)) {
+        Ok(execute) => execute,
+        Err(usage) => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
+    let config = get_configuration()?;
+    let mut connection = PgConnection::connect_with(&config.database.without_db()).await?;
+
+    let databases = list_test_databases(&mut connection).await?;
+    for name in &databases {
+        if execute {
+            drop_test_database(&mut connection, name).await?;
+            println!("Dropped {}", name);
+        } else {
+            println!("Would drop {}", name);
+        }
+    }
+    if !execute && !databases.is_empty() {
+        println!(
+            "Dry run: pass --execute to drop these {} databases.",
+            databases.len()
+        );
+    }
+    Ok(())
+}
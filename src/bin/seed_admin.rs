@@ -0,0 +1,65 @@
+//! Create the first admin user, or reset an admin's password:
+//!
+//! ```text
+//! cargo run --bin seed-admin -- --username admin
+//! ```
+//!
+//! The password is read from `SEED_ADMIN_PASSWORD` when set, otherwise from
+//! the first line of stdin; it is never echoed back.
+use std::io::{BufRead, Write};
+
+use secrecy::Secret;
+use zero2prod::authentication::{seed_admin, SeedAdminError};
+use zero2prod::configuration::get_configuration;
+use zero2prod::startup::get_connection_pool;
+
+const PASSWORD_VARIABLE: &str = "SEED_ADMIN_PASSWORD";
+const USAGE: &str = "Usage: seed-admin --username <username>";
+
+fn parse_username(mut args: impl Iterator<Item = String>) -> Result<String, String> {
+    match (args.next().as_deref(), args.next(), args.next()) {
+        (Some("--username"), Some(username), None) if !username.trim().is_empty() => Ok(username),
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn read_password() -> Result<Secret<String>, std::io::Error> {
+    if let Ok(password) = std::env::var(PASSWORD_VARIABLE) {
+        return Ok(Secret::new(password));
+    }
+    eprint!("Password: ");
+    std::io::stderr().flush()?;
+    let mut password = String::new();
+    std::io::stdin().lock().read_line(&mut password)?;
+    Ok(Secret::new(
+        password.trim_end_matches(['\r', '\n']).to_owned(),
+    ))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let username = match parse_username(std::env::args().skip(1)) {
+        Ok(username) => username,
+        Err(usage) => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
+    let config = get_configuration()?;
+    let password = read_password()?;
+    let pool = get_connection_pool(&config.database);
+
+    match seed_admin(&username, password, &config.password_policy, &pool).await {
+        Ok(user_id) => {
+            println!("Admin user `{}` is ready (user id {}).", username, user_id);
+            Ok(())
+        }
+        Err(SeedAdminError::WeakPassword(violations)) => {
+            for violation in violations {
+                eprintln!("{}", violation);
+            }
+            std::process::exit(1);
+        }
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,170 @@
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use ipnetwork::IpNetwork;
+
+pub const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// The proxies whose `X-Forwarded-For` header we are willing to believe.
+#[derive(Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpNetwork>);
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+}
+
+/// The IP address of the client that issued the request.
+///
+/// `X-Forwarded-For` is only honoured when the socket peer is a trusted
+/// proxy; otherwise anybody could spoof their address by setting the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl std::fmt::Display for ClientIp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(ip) => write!(f, "{}", ip),
+            None => write!(f, "unknown"),
+        }
+    }
+}
+
+impl FromRequest for ClientIp {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let trusted_proxies = req
+            .app_data::<web::Data<TrustedProxies>>()
+            .map(|t| t.get_ref().clone())
+            .unwrap_or_default();
+        // A proxy may append its own field line rather than extend the
+        // first one: the chain is all of them, in order. A line that is not
+        // valid UTF-8 becomes an empty hop, which stops the walk.
+        let forwarded_for: Vec<&str> = req
+            .headers()
+            .get_all(FORWARDED_FOR_HEADER)
+            .map(|h| h.to_str().unwrap_or_default())
+            .collect();
+        let forwarded_for = (!forwarded_for.is_empty()).then(|| forwarded_for.join(","));
+        let peer = req.peer_addr().map(|addr| addr.ip());
+
+        ready(Ok(Self(resolve_client_ip(
+            peer,
+            forwarded_for.as_deref(),
+            &trusted_proxies,
+        ))))
+    }
+}
+
+/// Walk the forwarded chain from the closest hop backwards, skipping trusted
+/// proxies: the first untrusted address is the client. The walk also stops
+/// at a hop that does not parse, settling for the last proxy that could be
+/// believed: anything further left may have been written by the client.
+pub fn resolve_client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &TrustedProxies,
+) -> Option<IpAddr> {
+    let mut client = peer?;
+    if !trusted_proxies.contains(client) {
+        return Some(client);
+    }
+
+    for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+        let Ok(ip) = hop.trim().parse() else {
+            break;
+        };
+        client = ip;
+        if !trusted_proxies.contains(ip) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientIp, TrustedProxies, FORWARDED_FOR_HEADER};
+    use actix_web::test::TestRequest;
+    use actix_web::{web, FromRequest};
+
+    fn trusted_proxies() -> web::Data<TrustedProxies> {
+        web::Data::new(TrustedProxies(vec!["10.0.0.0/8".parse().unwrap()]))
+    }
+
+    async fn extract(req: TestRequest) -> ClientIp {
+        let req = req.app_data(trusted_proxies()).to_http_request();
+        ClientIp::extract(&req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_trusted_proxy_forwards_the_client_ip() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:443".parse().unwrap())
+            .insert_header((FORWARDED_FOR_HEADER, "203.0.113.7, 10.0.0.2"));
+
+        let client_ip = extract(req).await;
+
+        assert_eq!(client_ip, ClientIp(Some("203.0.113.7".parse().unwrap())));
+    }
+
+    #[tokio::test]
+    async fn an_untrusted_peer_cannot_spoof_its_ip() {
+        let req = TestRequest::default()
+            .peer_addr("198.51.100.4:443".parse().unwrap())
+            .insert_header((FORWARDED_FOR_HEADER, "203.0.113.7"));
+
+        let client_ip = extract(req).await;
+
+        assert_eq!(client_ip, ClientIp(Some("198.51.100.4".parse().unwrap())));
+    }
+
+    #[tokio::test]
+    async fn a_trusted_proxy_without_the_header_is_the_client() {
+        let req = TestRequest::default().peer_addr("10.0.0.1:443".parse().unwrap());
+
+        let client_ip = extract(req).await;
+
+        assert_eq!(client_ip, ClientIp(Some("10.0.0.1".parse().unwrap())));
+    }
+
+    #[tokio::test]
+    async fn junk_left_of_the_client_does_not_hide_it() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:443".parse().unwrap())
+            .insert_header((FORWARDED_FOR_HEADER, "not-an-ip, 203.0.113.7, 10.0.0.2"));
+
+        let client_ip = extract(req).await;
+
+        assert_eq!(client_ip, ClientIp(Some("203.0.113.7".parse().unwrap())));
+    }
+
+    #[tokio::test]
+    async fn the_walk_stops_at_a_hop_that_does_not_parse() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:443".parse().unwrap())
+            .insert_header((FORWARDED_FOR_HEADER, "203.0.113.7, junk, 10.0.0.2"));
+
+        let client_ip = extract(req).await;
+
+        assert_eq!(client_ip, ClientIp(Some("10.0.0.2".parse().unwrap())));
+    }
+
+    #[tokio::test]
+    async fn the_chain_spans_every_header_line() {
+        // The trusted proxy appended its own line after the client's.
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:443".parse().unwrap())
+            .append_header((FORWARDED_FOR_HEADER, "198.51.100.9"))
+            .append_header((FORWARDED_FOR_HEADER, "203.0.113.7, 10.0.0.2"));
+
+        let client_ip = extract(req).await;
+
+        assert_eq!(client_ip, ClientIp(Some("203.0.113.7".parse().unwrap())));
+    }
+}
@@ -0,0 +1,192 @@
+//! Bounding how many requests a route handles at once, so that a signup spike
+//! queues up in front of the database instead of piling onto it.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web;
+use prometheus::IntGauge;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::error::json_error;
+use crate::metrics::Metrics;
+
+/// How many requests run at once, and how many more may wait for a slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+pub struct ConcurrencyLimit {
+    pub max_in_flight: usize,
+    pub max_queued: usize,
+}
+
+pub struct ConcurrencyLimiter {
+    in_flight: Semaphore,
+    queued: AtomicUsize,
+    max_queued: usize,
+}
+
+/// Takes a request out of the queue when dropped, including when the client
+/// gives up while waiting.
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(limit: ConcurrencyLimit) -> Self {
+        Self {
+            in_flight: Semaphore::new(limit.max_in_flight),
+            queued: AtomicUsize::new(0),
+            max_queued: limit.max_queued,
+        }
+    }
+
+    /// A slot to run in, after waiting for one if they are all taken; `None`
+    /// when the queue is full as well.
+    pub async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        if let Ok(permit) = self.in_flight.try_acquire() {
+            return Some(permit);
+        }
+        let _slot = QueueSlot(&self.queued);
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            return None;
+        }
+        self.in_flight.acquire().await.ok()
+    }
+}
+
+/// The limiters of the concurrency-limited routes, shared as app data; a
+/// route without a configured limit is not limited.
+#[derive(Default)]
+pub struct ConcurrencyLimiters {
+    pub subscribe: Option<ConcurrencyLimiter>,
+    /// How many requests the whole process handles at once, every route
+    /// included.
+    pub max_in_flight_requests: Option<usize>,
+}
+
+/// Takes a request out of the in-flight gauge when dropped, including when
+/// the client disconnects before getting its response.
+struct InFlight(IntGauge);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Count the requests in flight, and shed those beyond
+/// `max_in_flight_requests` with a 503.
+pub async fn track_in_flight_requests<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let Some(metrics) = req.app_data::<web::Data<Metrics>>().cloned() else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+    let max_in_flight = req
+        .app_data::<web::Data<ConcurrencyLimiters>>()
+        .and_then(|l| l.max_in_flight_requests);
+
+    let gauge = metrics.http_requests_in_flight.clone();
+    gauge.inc();
+    let _in_flight = InFlight(gauge.clone());
+    if max_in_flight.is_some_and(|max| gauge.get() > max as i64) {
+        tracing::warn!("Shedding a request: too many are in flight");
+        let response = json_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "overloaded",
+            "The server is handling too many requests, please try again shortly.",
+        );
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// Shed subscriptions beyond the in-flight and queued limits with a 503.
+pub async fn limit_concurrent_subscriptions<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let limiters = req.app_data::<web::Data<ConcurrencyLimiters>>().cloned();
+    let Some(limiter) = limiters.as_ref().and_then(|l| l.subscribe.as_ref()) else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+
+    let Some(_permit) = limiter.acquire().await else {
+        tracing::warn!("Shedding a subscription: too many are in progress");
+        let response = json_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "overloaded",
+            "Too many subscriptions are being processed, please try again shortly.",
+        );
+        return Ok(req.into_response(response).map_into_right_body());
+    };
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConcurrencyLimit, ConcurrencyLimiter};
+    use std::time::Duration;
+
+    fn limiter() -> ConcurrencyLimiter {
+        ConcurrencyLimiter::new(ConcurrencyLimit {
+            max_in_flight: 1,
+            max_queued: 1,
+        })
+    }
+
+    #[tokio::test]
+    async fn requests_beyond_the_slots_and_the_queue_are_shed() {
+        let limiter = limiter();
+        let _running = limiter.acquire().await.unwrap();
+        let queued = limiter.acquire();
+        tokio::pin!(queued);
+        // Poll the second request once, so that it takes its place in the queue.
+        let waiting = tokio::time::timeout(Duration::from_millis(10), queued.as_mut()).await;
+        assert!(waiting.is_err());
+
+        assert!(limiter.acquire().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn a_queued_request_runs_once_a_slot_frees_up() {
+        let limiter = limiter();
+        let running = limiter.acquire().await.unwrap();
+
+        let (queued, ()) = tokio::join!(limiter.acquire(), async { drop(running) });
+
+        assert!(queued.is_some());
+    }
+
+    #[tokio::test]
+    async fn a_request_giving_up_leaves_the_queue() {
+        let limiter = limiter();
+        let _running = limiter.acquire().await.unwrap();
+
+        let gave_up = tokio::time::timeout(Duration::from_millis(10), limiter.acquire()).await;
+        assert!(gave_up.is_err());
+
+        // The abandoned request's place in the queue is free again.
+        let queued = tokio::time::timeout(Duration::from_millis(10), limiter.acquire()).await;
+        assert!(
+            queued.is_err(),
+            "A queued request waits rather than being shed"
+        );
+    }
+}
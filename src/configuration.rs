@@ -1,9 +1,24 @@
-use crate::domain::SubscriberEmail;
+use crate::access_log::AccessLogFormat;
+use crate::authentication::{LoginLockout, PasswordPolicy, SessionTimeouts};
+use crate::concurrency_limit::{ConcurrencyLimit, ConcurrencyLimiter, ConcurrencyLimiters};
+use crate::domain::{NamePolicy, SubscriberEmail, TopicName};
+use crate::email_client::{EmailClient, EmailDelivery, MessageStreams};
+use crate::email_templates::EmailContent;
+use crate::events::{EventSink, HttpEventSink, NoopEventSink};
+use crate::newsletter_content::TextBodyCheck;
+use crate::pii::PiiLogging;
+use crate::rate_limit::{RateLimit, RateLimiter, RateLimiters};
+use crate::routes::MxCheck;
+use crate::smtp::SmtpTransport;
+use actix_web::http::header::HeaderName;
+use actix_web::http::KeepAlive;
+use ipnetwork::IpNetwork;
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::PgConnectOptions;
 use sqlx::postgres::PgSslMode;
 use sqlx::ConnectOptions;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub enum Environment {
@@ -40,6 +55,32 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub email_queue: EmailQueueSettings,
+    pub webhooks: WebhookSettings,
+    pub cors: CorsSettings,
+    pub trusted_sources: TrustedSourceSettings,
+    pub rate_limits: RateLimitSettings,
+    pub concurrency_limits: ConcurrencyLimitSettings,
+    pub password_policy: PasswordPolicy,
+    #[serde(default)]
+    pub name_policy: NamePolicy,
+    #[serde(default)]
+    pub text_body_check: TextBodyCheck,
+    pub feature_flags: FeatureFlagSettings,
+    pub analytics: AnalyticsSettings,
+    pub welcome_email: WelcomeEmailSettings,
+}
+
+impl Settings {
+    /// Reject combinations of settings that deserialize fine but cannot work.
+    pub fn validate(&self) -> Result<(), String> {
+        self.cors.validate()?;
+        self.application.confirmation_redirect()?;
+        self.application.validate_base_url()?;
+        self.application.default_topic()?;
+        self.application.health_check_path()?;
+        self.email_client.validate()
+    }
 }
 
 #[derive(Clone, serde::Deserialize)]
@@ -48,6 +89,219 @@ pub struct ApplicationSettings {
 
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
+
+    /// When set, listen on this unix domain socket instead of `host:port`.
+    pub socket_path: Option<String>,
+
+    /// How many connections may wait to be accepted before new ones are
+    /// refused; unset keeps the operating system's default.
+    #[serde(default)]
+    pub backlog: Option<u32>,
+
+    /// Public URL of the application, used to build links in emails. It
+    /// includes the scheme, since behind a TLS-terminating proxy the
+    /// requests the application sees are plain HTTP.
+    pub base_url: String,
+    /// Reject a `base_url` that is not HTTPS.
+    #[serde(default)]
+    pub require_https_base_url: bool,
+    /// Mount every route under this prefix, e.g. `/api/v1`.
+    #[serde(default)]
+    pub base_path: String,
+    /// Where the health check is served, under `base_path`, for load
+    /// balancers that expect a fixed path such as `/healthz`.
+    pub health_check_path: String,
+    pub hmac_secret: Secret<String>,
+    pub maintenance_mode: bool,
+
+    /// Networks of the proxies allowed to set `X-Forwarded-For`,
+    /// e.g. `10.0.0.0/8`.
+    pub trusted_proxies: Vec<IpNetwork>,
+
+    /// How long an idle connection is kept open; `0` disables keep-alive.
+    pub keep_alive_seconds: u64,
+    pub client_request_timeout_millis: u64,
+    pub client_disconnect_timeout_millis: u64,
+    /// How long in-flight requests get to complete on shutdown before their
+    /// connections are force-closed.
+    pub shutdown_timeout_seconds: u64,
+
+    /// How many subscription tokens to generate before giving up on finding
+    /// one that is not taken.
+    pub token_generation_attempts: u32,
+    /// Confirmation links stop working this long after being sent.
+    pub confirmation_token_ttl_seconds: u64,
+    /// How many recently issued tokens are kept in memory to confirm them
+    /// without looking them up; `0` disables the cache.
+    pub confirmation_token_cache_capacity: usize,
+    /// Reject new subscriptions once this many subscribers are confirmed or
+    /// pending confirmation; unset leaves them unlimited.
+    #[serde(default)]
+    pub max_subscribers: Option<u64>,
+
+    /// Admin sessions expire after this long without a request...
+    pub session_idle_timeout_seconds: u64,
+    /// ...or this long after logging in, whichever comes first.
+    pub session_absolute_timeout_seconds: u64,
+
+    /// Lock an admin account after this many failed logins in a row...
+    pub login_max_failed_attempts: u32,
+    /// ...for this long, even against the right password.
+    pub login_lockout_seconds: u64,
+
+    /// How often tokens left behind by confirmed subscribers are deleted.
+    pub token_sweep_interval_seconds: u64,
+
+    /// The topic subscriptions that pick no topics join, created on start if
+    /// it does not exist.
+    pub default_topic: String,
+
+    /// Answer successful subscriptions with the subscriber's id and status
+    /// as JSON even when the client does not ask for it; the body is empty
+    /// otherwise.
+    #[serde(default)]
+    pub json_subscribe_response: bool,
+
+    /// Look up the MX records of new subscribers' email domains, to warn
+    /// about or reject addresses that cannot receive email.
+    #[serde(default)]
+    pub email_mx_check: MxCheck,
+
+    /// How subscribers' emails and names appear in logs.
+    #[serde(default)]
+    pub pii_logging: PiiLogging,
+
+    /// Also write an access log line per request to stdout in this format,
+    /// for pipelines expecting Apache/NGINX-style logs.
+    #[serde(default)]
+    pub access_log_format: Option<AccessLogFormat>,
+
+    /// Answer requests that are well-formed but fail validation, e.g. an
+    /// invalid email address, with a 400 rather than a 422.
+    #[serde(default)]
+    pub legacy_validation_status: bool,
+
+    /// Send subscribers to this page (e.g. a "thanks" page on the marketing
+    /// site) once confirmed, instead of answering with a bare 200...
+    #[serde(default)]
+    pub confirmation_redirect_url: Option<String>,
+    /// ...provided its origin is listed here, e.g. `https://example.com`.
+    #[serde(default)]
+    pub confirmation_redirect_allowlist: Vec<String>,
+}
+
+impl ApplicationSettings {
+    /// The route prefix with a leading slash and without a trailing one;
+    /// empty when routes are mounted at the root.
+    pub fn base_path(&self) -> String {
+        let path = self.base_path.trim_matches('/');
+        if path.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", path)
+        }
+    }
+
+    /// The base of the links in emails, which point at the prefixed routes.
+    pub fn links_base_url(&self) -> String {
+        format!("{}{}", self.base_url, self.base_path())
+    }
+
+    /// Check that `base_url` is absolute, and HTTPS when required.
+    pub fn validate_base_url(&self) -> Result<(), String> {
+        let url = reqwest::Url::parse(&self.base_url).map_err(|e| {
+            format!(
+                "The base URL must be absolute, e.g. https://example.com: {}.",
+                e
+            )
+        })?;
+        if self.require_https_base_url && url.scheme() != "https" {
+            return Err(format!(
+                "The base URL must use HTTPS, not {}.",
+                url.scheme()
+            ));
+        }
+        Ok(())
+    }
+
+    /// The health check path with a leading slash and without a trailing
+    /// one.
+    pub fn health_check_path(&self) -> Result<String, String> {
+        let path = self.health_check_path.trim_matches('/');
+        if path.is_empty() {
+            return Err("The health check path cannot be empty.".into());
+        }
+        Ok(format!("/{}", path))
+    }
+
+    pub fn keep_alive(&self) -> KeepAlive {
+        match self.keep_alive_seconds {
+            0 => KeepAlive::Disabled,
+            seconds => KeepAlive::Timeout(std::time::Duration::from_secs(seconds)),
+        }
+    }
+
+    pub fn client_request_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.client_request_timeout_millis)
+    }
+
+    pub fn client_disconnect_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.client_disconnect_timeout_millis)
+    }
+
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_timeout_seconds)
+    }
+
+    pub fn session_timeouts(&self) -> SessionTimeouts {
+        SessionTimeouts {
+            idle: chrono::Duration::seconds(self.session_idle_timeout_seconds as i64),
+            absolute: chrono::Duration::seconds(self.session_absolute_timeout_seconds as i64),
+        }
+    }
+
+    pub fn confirmation_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.confirmation_token_ttl_seconds as i64)
+    }
+
+    pub fn token_sweep_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.token_sweep_interval_seconds)
+    }
+
+    pub fn default_topic(&self) -> Result<TopicName, String> {
+        TopicName::parse(self.default_topic.clone())
+    }
+
+    /// The confirmation redirect, checked against the allowlist so that a
+    /// misconfiguration cannot turn confirmation links into open redirects.
+    pub fn confirmation_redirect(&self) -> Result<Option<String>, String> {
+        let Some(url) = &self.confirmation_redirect_url else {
+            return Ok(None);
+        };
+        let origin = reqwest::Url::parse(url)
+            .map_err(|e| format!("The confirmation redirect URL is invalid: {}.", e))?
+            .origin()
+            .ascii_serialization();
+        if self
+            .confirmation_redirect_allowlist
+            .iter()
+            .any(|allowed| allowed.trim_end_matches('/') == origin)
+        {
+            Ok(Some(url.clone()))
+        } else {
+            Err(format!(
+                "The confirmation redirect URL {} is not in the allowlist.",
+                url
+            ))
+        }
+    }
+
+    pub fn login_lockout(&self) -> LoginLockout {
+        LoginLockout {
+            max_failed_attempts: self.login_max_failed_attempts,
+            duration: chrono::Duration::seconds(self.login_lockout_seconds as i64),
+        }
+    }
 }
 
 #[derive(Clone, serde::Deserialize)]
@@ -61,14 +315,306 @@ pub struct DatabaseSettings {
     pub host: String,
     pub database_name: String,
     pub require_ssl: bool,
+    /// How the application's connections show up in `pg_stat_activity`.
+    pub application_name: String,
+
+    /// Apply pending migrations while building the application.
+    #[serde(default)]
+    pub migrate_on_start: bool,
+    /// Apply the `.sql` files of this directory while building the
+    /// application, after the embedded migrations, e.g. to ship hotfix SQL
+    /// without a new build; `--migrations-path` sets it too.
+    #[serde(default)]
+    pub migrations_path: Option<String>,
+
+    /// Postgres aborts any statement running longer than this; zero disables
+    /// the limit.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub statement_timeout_millis: u64,
+
+    /// Connections the pool keeps open even when idle.
+    #[serde(default)]
+    pub min_connections: u32,
+    /// Open `min_connections` before serving, so that the first requests do
+    /// not pay for connection setup.
+    #[serde(default)]
+    pub warmup: bool,
+    /// Refuse to start when the warmup fails instead of logging a warning.
+    #[serde(default)]
+    pub warmup_strict: bool,
 }
 
 #[derive(Clone, serde::Deserialize)]
 pub struct EmailClientSettings {
-    pub base_url: String,
     pub sender_email: String,
-    pub authorization_token: Secret<String>,
     pub timeout_millis: u64,
+    /// How long connecting to the provider may take, within `timeout_millis`.
+    pub connect_timeout_millis: u64,
+    /// Log one in every N successfully sent emails; failures are always
+    /// logged.
+    pub sent_log_sample_rate: u64,
+    /// The longest a rate-limited send waits on the provider's `Retry-After`
+    /// before its single retry.
+    pub max_retry_after_seconds: u64,
+    /// Put in front of every subject, e.g. `[MyNewsletter]`.
+    pub subject_prefix: Option<String>,
+    /// Skip an email with the same recipient and subject as one sent this
+    /// many seconds ago; unset sends every email.
+    pub double_send_window_seconds: Option<u64>,
+    #[serde(default)]
+    pub sender_domain_check: SenderDomainCheckSettings,
+    #[serde(flatten)]
+    pub provider: EmailProviderSettings,
+}
+
+/// Looking up the sender domain's SPF and DKIM records on start.
+#[derive(Clone, Default, serde::Deserialize)]
+pub struct SenderDomainCheckSettings {
+    pub enabled: bool,
+    /// The DKIM selector whose key to look for; unset only checks SPF.
+    pub dkim_selector: Option<String>,
+    /// Report not ready while a record is missing, rather than only warning.
+    pub fail_readiness: bool,
+}
+
+/// Where emails are sent, chosen by the `provider` key next to the
+/// provider's own settings.
+#[derive(Clone, serde::Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum EmailProviderSettings {
+    Postmark {
+        base_url: String,
+        authorization_token: Secret<String>,
+        /// The Postmark message stream confirmations and other one-off
+        /// emails go through; unset uses `outbound`...
+        #[serde(default)]
+        transactional_stream: Option<String>,
+        /// ...and the one newsletters go through; unset uses `broadcast`.
+        #[serde(default)]
+        broadcast_stream: Option<String>,
+    },
+    Smtp {
+        host: String,
+        #[serde(deserialize_with = "deserialize_number_from_string")]
+        port: u16,
+        #[serde(default)]
+        tls: SmtpTls,
+        /// Authenticate with `AUTH PLAIN` when set, along with `password`;
+        /// only allowed over STARTTLS.
+        username: Option<String>,
+        password: Option<Secret<String>>,
+    },
+    Memory,
+}
+
+/// How the connection to the SMTP relay is secured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade the connection with STARTTLS, and give up if the relay
+    /// cannot.
+    #[default]
+    Starttls,
+    /// Plain text, for a relay on a trusted network; no credentials then.
+    None,
+}
+
+/// The background worker sending queued emails.
+#[derive(Clone, serde::Deserialize)]
+pub struct EmailQueueSettings {
+    /// Run the worker next to the HTTP server; when disabled emails stay in
+    /// the queue until another instance sends them.
+    pub worker_enabled: bool,
+    /// How long the worker waits before looking again at an empty queue.
+    pub poll_interval_millis: u64,
+    /// Drop an email once sending it has failed this many times.
+    pub max_retries: u16,
+    /// Never send an issue to the same subscriber more than this many times,
+    /// failed attempts included, whatever keeps queueing it: the delivery is
+    /// marked as failed instead. Keep it above `max_retries`.
+    pub max_sends_per_subscriber: u16,
+    pub retry_delay_seconds: u64,
+    /// How many emails the worker sends before pausing for
+    /// `inter_batch_delay_millis`, to protect our sender reputation.
+    pub batch_size: usize,
+    /// Zero disables the pause.
+    #[serde(default)]
+    pub inter_batch_delay_millis: u64,
+    /// Bulk re-sends (e.g. of pending confirmations) are scheduled at most
+    /// this many per second, so as not to trip the provider's limits.
+    pub resend_per_second: u32,
+}
+
+impl EmailQueueSettings {
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_millis)
+    }
+
+    /// How far apart the emails of a bulk re-send are scheduled.
+    pub fn resend_spacing(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(1) / self.resend_per_second.max(1)
+    }
+
+    pub fn retry_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.retry_delay_seconds)
+    }
+
+    pub fn inter_batch_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.inter_batch_delay_millis)
+    }
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct WebhookSettings {
+    pub postmark: WebhookCredentials,
+}
+
+/// The basic auth credentials Postmark must present when calling us back.
+#[derive(Clone, serde::Deserialize)]
+pub struct WebhookCredentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// Partners allowed to create confirmed subscribers directly.
+#[derive(Clone, serde::Deserialize)]
+pub struct TrustedSourceSettings {
+    /// The bearer token partners authenticate with; unset disables the
+    /// trusted subscription endpoint.
+    pub api_key: Option<Secret<String>>,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct FeatureFlagSettings {
+    /// How often each instance reloads the flags from the database.
+    pub refresh_interval_millis: u64,
+}
+
+impl FeatureFlagSettings {
+    pub fn refresh_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.refresh_interval_millis)
+    }
+}
+
+/// Per-route request limits; `~` leaves a route unlimited.
+#[derive(Clone, serde::Deserialize)]
+pub struct RateLimitSettings {
+    /// Counted per client IP.
+    pub subscribe: Option<RateLimit>,
+    /// Counted per username and client IP.
+    pub login: Option<RateLimit>,
+    /// Counted per email address, whether or not it is subscribed.
+    #[serde(default)]
+    pub confirmation_resend: Option<RateLimit>,
+}
+
+impl RateLimitSettings {
+    pub fn limiters(&self) -> RateLimiters {
+        RateLimiters {
+            subscribe: self.subscribe.map(RateLimiter::new),
+            login: self.login.map(RateLimiter::new),
+            confirmation_resend: self.confirmation_resend.map(RateLimiter::new),
+        }
+    }
+}
+
+/// The email sent once to each subscriber when they confirm.
+#[derive(Clone, serde::Deserialize)]
+pub struct WelcomeEmailSettings {
+    pub enabled: bool,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl WelcomeEmailSettings {
+    /// The welcome email to send, unless disabled.
+    pub fn template(&self) -> Option<EmailContent> {
+        self.enabled.then(|| EmailContent {
+            subject: self.subject.clone(),
+            html_body: self.html_body.clone(),
+            text_body: self.text_body.clone(),
+        })
+    }
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct AnalyticsSettings {
+    /// Where to POST subscriber events; unset drops them.
+    pub event_sink_url: Option<String>,
+    pub timeout_millis: u64,
+}
+
+impl AnalyticsSettings {
+    pub fn event_sink(&self) -> Arc<dyn EventSink> {
+        match &self.event_sink_url {
+            Some(url) => Arc::new(HttpEventSink::new(
+                url.clone(),
+                std::time::Duration::from_millis(self.timeout_millis),
+            )),
+            None => Arc::new(NoopEventSink),
+        }
+    }
+}
+
+/// Per-route limits on requests handled at once; `~` leaves a route
+/// unlimited.
+#[derive(Clone, serde::Deserialize)]
+pub struct ConcurrencyLimitSettings {
+    pub subscribe: Option<ConcurrencyLimit>,
+    /// Answer with a 503 rather than handle more requests than this at once,
+    /// across every route; unset only counts them.
+    #[serde(default)]
+    pub max_in_flight_requests: Option<usize>,
+}
+
+impl ConcurrencyLimitSettings {
+    pub fn limiters(&self) -> ConcurrencyLimiters {
+        ConcurrencyLimiters {
+            subscribe: self.subscribe.map(ConcurrencyLimiter::new),
+            max_in_flight_requests: self.max_in_flight_requests,
+        }
+    }
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct CorsSettings {
+    /// Origins allowed to call the API from a browser; `*` allows any.
+    pub allowed_origins: Vec<String>,
+    /// Send `Access-Control-Allow-Credentials: true`, letting the browser
+    /// attach the session cookie.
+    pub allow_credentials: bool,
+    /// How long a browser may cache a preflight response.
+    pub max_age_seconds: Option<usize>,
+    /// Response headers the browser lets scripts read, on top of the
+    /// CORS-safelisted ones.
+    #[serde(default = "default_expose_headers")]
+    pub expose_headers: Vec<String>,
+}
+
+fn default_expose_headers() -> Vec<String> {
+    vec!["X-Request-Id".into()]
+}
+
+impl CorsSettings {
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.allow_credentials && self.allows_any_origin() {
+            return Err(
+                "CORS credentials cannot be allowed together with a wildcard origin. \
+                List the allowed origins explicitly."
+                    .into(),
+            );
+        }
+        for header in &self.expose_headers {
+            HeaderName::from_bytes(header.as_bytes())
+                .map_err(|_| format!("{} is not a valid CORS-exposed header name.", header))?;
+        }
+        Ok(())
+    }
 }
 
 impl EmailClientSettings {
@@ -76,29 +622,125 @@ impl EmailClientSettings {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
+    /// The domain part of the sender address.
+    pub fn sender_domain(&self) -> Option<&str> {
+        self.sender_email.rsplit_once('@').map(|(_, domain)| domain)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.sender()?;
+        match &self.provider {
+            EmailProviderSettings::Smtp {
+                username, password, ..
+            } if username.is_some() != password.is_some() => {
+                Err("SMTP credentials need both a username and a password.".into())
+            }
+            EmailProviderSettings::Smtp {
+                tls: SmtpTls::None,
+                username: Some(_),
+                ..
+            } => Err("SMTP credentials are only sent over STARTTLS.".into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// A client for the configured provider.
+    pub fn client(&self) -> Result<EmailClient, String> {
+        let sender = self.sender()?;
+        let client = match &self.provider {
+            EmailProviderSettings::Postmark {
+                base_url,
+                authorization_token,
+                transactional_stream,
+                broadcast_stream,
+            } => EmailClient::new(
+                base_url.clone(),
+                sender,
+                authorization_token.clone(),
+                self.timeout(),
+            )
+            .with_connect_timeout(self.connect_timeout())
+            .with_message_streams({
+                let defaults = MessageStreams::default();
+                MessageStreams {
+                    transactional: transactional_stream
+                        .clone()
+                        .unwrap_or(defaults.transactional),
+                    broadcast: broadcast_stream.clone().unwrap_or(defaults.broadcast),
+                }
+            }),
+            EmailProviderSettings::Smtp {
+                host,
+                port,
+                tls,
+                username,
+                password,
+            } => {
+                let credentials = username.clone().zip(password.clone());
+                let transport = SmtpTransport::new(host.clone(), *port, *tls, credentials)
+                    .map_err(|e| format!("Failed to set up the SMTP transport: {}", e))?;
+                EmailClient::with_delivery(EmailDelivery::Smtp(transport), sender, self.timeout())
+            }
+            EmailProviderSettings::Memory => EmailClient::with_delivery(
+                EmailDelivery::Memory(Default::default()),
+                sender,
+                self.timeout(),
+            ),
+        };
+        Ok(client
+            .with_sent_log_sample_rate(self.sent_log_sample_rate)
+            .with_subject_prefix(self.subject_prefix.clone())
+            .with_double_send_guard(
+                self.double_send_window_seconds
+                    .map(std::time::Duration::from_secs),
+            )
+            .with_max_retry_after(self.max_retry_after()))
+    }
+
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_millis)
     }
+
+    pub fn connect_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.connect_timeout_millis)
+    }
+
+    pub fn max_retry_after(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.max_retry_after_seconds)
+    }
 }
 
 impl DatabaseSettings {
+    /// Options for the server itself, e.g. to create the database.
     pub fn without_db(&self) -> PgConnectOptions {
+        self.connect_options()
+    }
+
+    /// Options for the application's database.
+    pub fn with_db(&self) -> PgConnectOptions {
+        self.connect_options().database(&self.database_name)
+    }
+
+    /// Everything but the database to connect to, shared by both variants so
+    /// that their sessions cannot drift apart.
+    fn connect_options(&self) -> PgConnectOptions {
         let ssl_mode = if self.require_ssl {
             PgSslMode::Require
         } else {
             PgSslMode::Prefer
         };
 
-        PgConnectOptions::new()
+        let mut options = PgConnectOptions::new()
             .username(&self.username)
             .password(self.password.expose_secret())
             .host(&self.host)
             .port(self.port)
             .ssl_mode(ssl_mode)
-    }
-
-    pub fn with_db(&self) -> PgConnectOptions {
-        let mut options = self.without_db().database(&self.database_name);
+            .application_name(&self.application_name)
+            .options([(
+                "statement_timeout",
+                format!("{}ms", self.statement_timeout_millis),
+            )]);
         options.log_statements(tracing::log::LevelFilter::Trace);
         options
     }
@@ -125,5 +767,302 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         )
         .build()?;
 
-    settings.try_deserialize::<Settings>()
+    let settings = settings.try_deserialize::<Settings>()?;
+    settings.validate().map_err(config::ConfigError::Message)?;
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_configuration, CorsSettings, DatabaseSettings, EmailClientSettings,
+        EmailProviderSettings, SmtpTls,
+    };
+    use actix_web::http::KeepAlive;
+    use std::time::Duration;
+
+    #[test]
+    fn the_configured_keep_alive_is_used() {
+        let mut config = get_configuration().unwrap().application;
+        config.keep_alive_seconds = 42;
+
+        assert_eq!(
+            config.keep_alive(),
+            KeepAlive::Timeout(Duration::from_secs(42))
+        );
+    }
+
+    #[test]
+    fn a_zero_keep_alive_disables_it() {
+        let mut config = get_configuration().unwrap().application;
+        config.keep_alive_seconds = 0;
+
+        assert_eq!(config.keep_alive(), KeepAlive::Disabled);
+    }
+
+    #[test]
+    fn defaults_match_actix() {
+        let config = get_configuration().unwrap().application;
+
+        assert_eq!(
+            config.keep_alive(),
+            KeepAlive::Timeout(Duration::from_secs(5))
+        );
+        assert_eq!(config.client_request_timeout(), Duration::from_secs(5));
+        assert_eq!(config.client_disconnect_timeout(), Duration::from_secs(1));
+        assert_eq!(config.shutdown_timeout(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn the_configured_shutdown_timeout_is_used() {
+        let mut config = get_configuration().unwrap().application;
+        config.shutdown_timeout_seconds = 7;
+
+        assert_eq!(config.shutdown_timeout(), Duration::from_secs(7));
+    }
+
+    #[test]
+    fn a_confirmation_redirect_to_an_allowed_origin_is_accepted() {
+        let mut config = get_configuration().unwrap().application;
+        config.confirmation_redirect_url = Some("https://example.com/thanks".into());
+        config.confirmation_redirect_allowlist = vec!["https://example.com/".into()];
+
+        assert_eq!(
+            config.confirmation_redirect().unwrap().as_deref(),
+            Some("https://example.com/thanks")
+        );
+    }
+
+    #[test]
+    fn a_confirmation_redirect_to_another_origin_is_rejected() {
+        let mut config = get_configuration().unwrap().application;
+        config.confirmation_redirect_allowlist = vec!["https://example.com".into()];
+
+        for url in [
+            "https://example.com.evil.com/thanks",
+            "http://example.com/thanks",
+            "not a url",
+        ] {
+            config.confirmation_redirect_url = Some(url.into());
+            assert!(config.confirmation_redirect().is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn credentials_with_a_wildcard_origin_are_rejected() {
+        let config = CorsSettings {
+            allowed_origins: vec!["*".into()],
+            allow_credentials: true,
+            max_age_seconds: None,
+            expose_headers: vec![],
+        };
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn credentials_with_explicit_origins_are_accepted() {
+        let config = CorsSettings {
+            allowed_origins: vec!["https://app.example.com".into()],
+            allow_credentials: true,
+            max_age_seconds: Some(600),
+            expose_headers: vec![],
+        };
+
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn an_invalid_exposed_header_is_rejected() {
+        let config = CorsSettings {
+            allowed_origins: vec!["https://app.example.com".into()],
+            allow_credentials: false,
+            max_age_seconds: None,
+            expose_headers: vec!["X Request Id".into()],
+        };
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn the_base_path_is_normalised() {
+        let mut config = get_configuration().unwrap().application;
+
+        for (base_path, expected) in [
+            ("", ""),
+            ("/", ""),
+            ("api/v1", "/api/v1"),
+            ("/api/v1/", "/api/v1"),
+        ] {
+            config.base_path = base_path.into();
+            assert_eq!(config.base_path(), expected);
+        }
+    }
+
+    #[test]
+    fn the_base_url_must_be_absolute() {
+        let mut config = get_configuration().unwrap().application;
+
+        config.base_url = "127.0.0.1".into();
+        assert!(config.validate_base_url().is_err());
+        config.base_url = "http://127.0.0.1".into();
+        assert!(config.validate_base_url().is_ok());
+    }
+
+    #[test]
+    fn an_http_base_url_is_rejected_when_https_is_required() {
+        let mut config = get_configuration().unwrap().application;
+        config.require_https_base_url = true;
+
+        config.base_url = "http://zero2prod.example.com".into();
+        assert!(config.validate_base_url().is_err());
+        config.base_url = "https://zero2prod.example.com".into();
+        assert!(config.validate_base_url().is_ok());
+    }
+
+    #[test]
+    fn the_health_check_path_is_normalised() {
+        let mut config = get_configuration().unwrap().application;
+
+        config.health_check_path = "healthz/".into();
+        assert_eq!(config.health_check_path().unwrap(), "/healthz");
+        config.health_check_path = "/".into();
+        assert!(config.health_check_path().is_err());
+    }
+
+    fn email_client_settings(yaml: &str) -> Result<EmailClientSettings, config::ConfigError> {
+        config::Config::builder()
+            .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+            .build()?
+            .try_deserialize()
+    }
+
+    const COMMON: &str = "sender_email: sender@example.com\n\
+        timeout_millis: 1000\n\
+        connect_timeout_millis: 500\n\
+        sent_log_sample_rate: 1\n\
+        max_retry_after_seconds: 30\n";
+
+    #[test]
+    fn the_postmark_provider_is_deserialized() {
+        let yaml = format!(
+            "{}provider: postmark\nbase_url: https://api.postmarkapp.com\nauthorization_token: t\n",
+            COMMON
+        );
+
+        let settings = email_client_settings(&yaml).unwrap();
+
+        assert!(matches!(
+            &settings.provider,
+            EmailProviderSettings::Postmark { base_url, .. } if base_url == "https://api.postmarkapp.com"
+        ));
+        assert_eq!(settings.client().unwrap().provider(), "postmark");
+    }
+
+    #[test]
+    fn the_smtp_provider_is_deserialized() {
+        let yaml = format!(
+            "{}provider: smtp\nhost: mail.example.com\nport: \"2525\"\nusername: me\npassword: secret\n",
+            COMMON
+        );
+
+        let settings = email_client_settings(&yaml).unwrap();
+
+        assert!(matches!(
+            &settings.provider,
+            EmailProviderSettings::Smtp { host, port: 2525, tls: SmtpTls::Starttls, username: Some(username), password: Some(_) }
+                if host == "mail.example.com" && username == "me"
+        ));
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.client().unwrap().provider(), "smtp");
+    }
+
+    #[test]
+    fn the_memory_provider_is_deserialized() {
+        let yaml = format!("{}provider: memory\n", COMMON);
+
+        let settings = email_client_settings(&yaml).unwrap();
+
+        assert!(matches!(settings.provider, EmailProviderSettings::Memory));
+        assert_eq!(settings.client().unwrap().provider(), "memory");
+    }
+
+    #[test]
+    fn a_provider_missing_a_required_field_is_rejected() {
+        let yaml = format!("{}provider: smtp\nport: 25\n", COMMON);
+
+        let error = email_client_settings(&yaml).err().unwrap();
+
+        assert!(error.to_string().contains("host"), "{}", error);
+    }
+
+    #[test]
+    fn an_unknown_provider_is_rejected() {
+        let yaml = format!("{}provider: carrier_pigeon\n", COMMON);
+
+        assert!(email_client_settings(&yaml).is_err());
+    }
+
+    #[test]
+    fn smtp_credentials_are_rejected_without_starttls() {
+        let yaml = format!(
+            "{}provider: smtp\nhost: mail.example.com\nport: 25\ntls: none\nusername: me\npassword: secret\n",
+            COMMON
+        );
+
+        let settings = email_client_settings(&yaml).unwrap();
+
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn smtp_credentials_must_come_in_pairs() {
+        let yaml = format!(
+            "{}provider: smtp\nhost: mail.example.com\nport: 25\nusername: me\n",
+            COMMON
+        );
+
+        let settings = email_client_settings(&yaml).unwrap();
+
+        assert!(settings.validate().is_err());
+    }
+
+    fn database_settings() -> DatabaseSettings {
+        let mut settings = get_configuration().unwrap().database;
+        settings.require_ssl = true;
+        settings.statement_timeout_millis = 1500;
+        settings.application_name = "zero2prod-test".into();
+        settings
+    }
+
+    #[test]
+    fn connecting_with_the_database_only_adds_the_database() {
+        let settings = database_settings();
+
+        // `PgConnectOptions` has no getters for most options, but lists them
+        // all in its `Debug` output.
+        assert_eq!(
+            format!("{:?}", settings.with_db()),
+            format!(
+                "{:?}",
+                settings.without_db().database(&settings.database_name)
+            ),
+        );
+    }
+
+    #[test]
+    fn both_variants_share_the_session_options() {
+        let settings = database_settings();
+
+        for options in [settings.with_db(), settings.without_db()] {
+            let options = format!("{:?}", options);
+            assert!(options.contains("ssl_mode: Require"), "{}", options);
+            assert!(
+                options.contains(r#"application_name: Some("zero2prod-test")"#),
+                "{}",
+                options
+            );
+            assert!(options.contains("statement_timeout=1500ms"), "{}", options);
+        }
+    }
 }
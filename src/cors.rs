@@ -0,0 +1,31 @@
+use actix_cors::Cors;
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE};
+
+use crate::configuration::CorsSettings;
+use crate::routes::IDEMPOTENCY_KEY_HEADER;
+
+/// Build the CORS middleware from the configured origins.
+///
+/// With no origins configured every cross-origin request is refused.
+pub fn cors(config: &CorsSettings) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(["GET", "POST", "PUT", "DELETE"])
+        .allowed_headers([ACCEPT, CONTENT_TYPE])
+        .allowed_header(IDEMPOTENCY_KEY_HEADER)
+        .max_age(config.max_age_seconds);
+    if !config.expose_headers.is_empty() {
+        cors = cors.expose_headers(config.expose_headers.iter().map(String::as_str));
+    }
+
+    if config.allows_any_origin() {
+        cors = cors.allow_any_origin().send_wildcard();
+    } else {
+        for origin in &config.allowed_origins {
+            cors = cors.allowed_origin(origin);
+        }
+    }
+    if config.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
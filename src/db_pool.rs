@@ -0,0 +1,31 @@
+//! Taking connections from the pool on behalf of request handlers, timing how
+//! long each one waits so that pool contention shows up in the metrics.
+use sqlx::pool::PoolConnection;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::metrics::Metrics;
+
+/// Take a connection from `pool` for reads outside of a transaction,
+/// recording the wait in `db_pool_acquire_duration`.
+pub async fn acquire(
+    pool: &PgPool,
+    metrics: &Metrics,
+) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+    let timer = metrics.db_pool_acquire_duration.start_timer();
+    let connection = pool.acquire().await;
+    timer.observe_duration();
+    connection
+}
+
+/// Begin a transaction on a connection from `pool`, recording the wait in
+/// `db_pool_acquire_duration`. The timing includes the `BEGIN` round trip,
+/// which is negligible next to waiting for a connection.
+pub async fn begin_transaction(
+    pool: &PgPool,
+    metrics: &Metrics,
+) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+    let timer = metrics.db_pool_acquire_duration.start_timer();
+    let transaction = pool.begin().await;
+    timer.observe_duration();
+    transaction
+}
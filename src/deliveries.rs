@@ -0,0 +1,160 @@
+//! The fate of a newsletter issue for each of its recipients.
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryStatus {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "pending" => Ok(Self::Pending),
+            "delivered" => Ok(Self::Delivered),
+            "failed" => Ok(Self::Failed),
+            other => Err(format!("{} is not a valid delivery status.", other)),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Delivery {
+    pub subscriber_email: String,
+    pub status: DeliveryStatus,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct DeliveryCounts {
+    pub pending: i64,
+    pub delivered: i64,
+    pub failed: i64,
+}
+
+#[tracing::instrument(name = "Recording a delivery", skip(executor, subscriber_email))]
+pub async fn record_delivery(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+    status: DeliveryStatus,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_email, status, updated_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email)
+        DO UPDATE SET status = EXCLUDED.status, updated_at = EXCLUDED.updated_at
+        "#,
+        newsletter_issue_id,
+        subscriber_email,
+        status.as_str()
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Count an attempt at sending the issue to the subscriber, returning how many
+/// there have been. It is committed on its own, before sending, so that an
+/// attempt cut short by a crash still counts.
+#[tracing::instrument(name = "Counting a send attempt", skip(executor, subscriber_email))]
+pub async fn record_send_attempt(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+) -> Result<i32, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_email, status, send_attempts)
+        VALUES ($1, $2, 'pending', 1)
+        ON CONFLICT (newsletter_issue_id, subscriber_email)
+        DO UPDATE SET send_attempts = newsletter_deliveries.send_attempts + 1
+        RETURNING send_attempts
+        "#,
+        newsletter_issue_id,
+        subscriber_email,
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(row.send_attempts)
+}
+
+/// The deliveries of an issue, optionally only those with `status`.
+#[tracing::instrument(name = "Listing deliveries", skip(pool))]
+pub async fn list_deliveries(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    status: Option<DeliveryStatus>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Delivery>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT subscriber_email, status, updated_at
+        FROM newsletter_deliveries
+        WHERE newsletter_issue_id = $1
+        AND ($2::text IS NULL OR status = $2)
+        ORDER BY subscriber_email
+        LIMIT $3 OFFSET $4
+        "#,
+        newsletter_issue_id,
+        status.map(|s| s.as_str()),
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(Delivery {
+                subscriber_email: r.subscriber_email,
+                status: r.status.try_into().map_err(anyhow::Error::msg)?,
+                updated_at: r.updated_at,
+            })
+        })
+        .collect()
+}
+
+#[tracing::instrument(name = "Counting deliveries", skip(pool))]
+pub async fn count_deliveries(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<DeliveryCounts, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT status, COUNT(*) AS "count!"
+        FROM newsletter_deliveries
+        WHERE newsletter_issue_id = $1
+        GROUP BY status
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut counts = DeliveryCounts::default();
+    for row in rows {
+        match DeliveryStatus::try_from(row.status).map_err(anyhow::Error::msg)? {
+            DeliveryStatus::Pending => counts.pending = row.count,
+            DeliveryStatus::Delivered => counts.delivered = row.count,
+            DeliveryStatus::Failed => counts.failed = row.count,
+        }
+    }
+    Ok(counts)
+}
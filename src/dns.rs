@@ -0,0 +1,68 @@
+//! DNS lookups behind traits, so that the checks relying on them can be
+//! tested against canned records.
+use std::future::Future;
+
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// Looks up the TXT records of a name.
+pub trait TxtResolver {
+    /// The TXT records of `name`, each with its strings concatenated; empty
+    /// when there are none.
+    fn txt_records(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = Result<Vec<String>, ResolveError>> + Send;
+}
+
+/// Looks up the MX records of a domain.
+pub trait MxResolver {
+    /// The mail exchanges of `domain`; empty when there are none.
+    fn mx_records(
+        &self,
+        domain: &str,
+    ) -> impl Future<Output = Result<Vec<String>, ResolveError>> + Send;
+}
+
+/// Resolves through the system's DNS configuration.
+pub struct DnsResolver(TokioAsyncResolver);
+
+impl DnsResolver {
+    pub fn from_system_conf() -> Result<Self, ResolveError> {
+        TokioAsyncResolver::tokio_from_system_conf().map(Self)
+    }
+}
+
+/// A name without records of the requested type is not an error.
+fn none_found<T>(e: ResolveError) -> Result<Vec<T>, ResolveError> {
+    match e.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => Ok(Vec::new()),
+        _ => Err(e),
+    }
+}
+
+impl TxtResolver for DnsResolver {
+    async fn txt_records(&self, name: &str) -> Result<Vec<String>, ResolveError> {
+        match self.0.txt_lookup(name).await {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|txt| {
+                    txt.txt_data()
+                        .iter()
+                        .map(|data| String::from_utf8_lossy(data))
+                        .collect()
+                })
+                .collect()),
+            Err(e) => none_found(e),
+        }
+    }
+}
+
+impl MxResolver for DnsResolver {
+    async fn mx_records(&self, domain: &str) -> Result<Vec<String>, ResolveError> {
+        match self.0.mx_lookup(domain).await {
+            Ok(lookup) => Ok(lookup.iter().map(|mx| mx.exchange().to_utf8()).collect()),
+            Err(e) => none_found(e),
+        }
+    }
+}
@@ -1,7 +1,11 @@
 mod new_subscriber;
+mod newsletter_title;
 mod subscriber_email;
 mod subscriber_name;
+mod topic_name;
 
 pub use new_subscriber::NewSubscriber;
+pub use newsletter_title::NewsletterTitle;
 pub use subscriber_email::SubscriberEmail;
-pub use subscriber_name::SubscriberName;
+pub use subscriber_name::{LengthUnit, NamePolicy, SubscriberName};
+pub use topic_name::TopicName;
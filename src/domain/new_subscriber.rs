@@ -1,7 +1,10 @@
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
+use crate::domain::topic_name::TopicName;
 
 pub struct NewSubscriber {
     pub name: SubscriberName,
     pub email: SubscriberEmail,
+    /// The topics picked, without duplicates; possibly none.
+    pub topics: Vec<TopicName>,
 }
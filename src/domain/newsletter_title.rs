@@ -0,0 +1,69 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The title of a newsletter issue, used as the subject of its emails.
+#[derive(Debug)]
+pub struct NewsletterTitle(String);
+
+impl NewsletterTitle {
+    pub const MAX_LENGTH: usize = 200;
+
+    /// Control characters, newlines included, would corrupt the subject
+    /// header: they are replaced with spaces.
+    pub fn parse(title: String) -> Result<Self, String> {
+        let title: String = title
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        let title = title.trim();
+
+        if title.is_empty() {
+            Err("The newsletter title cannot be empty.".into())
+        } else if title.graphemes(true).count() > Self::MAX_LENGTH {
+            Err(format!(
+                "The newsletter title cannot be longer than {} characters.",
+                Self::MAX_LENGTH
+            ))
+        } else {
+            Ok(Self(title.to_owned()))
+        }
+    }
+}
+
+impl AsRef<str> for NewsletterTitle {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::NewsletterTitle;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn an_empty_title_is_rejected() {
+        assert_err!(NewsletterTitle::parse("".into()));
+    }
+
+    #[test]
+    fn a_whitespace_only_title_is_rejected() {
+        assert_err!(NewsletterTitle::parse(" \n\t ".into()));
+    }
+
+    #[test]
+    fn a_200_grapheme_long_title_is_valid() {
+        assert_ok!(NewsletterTitle::parse("ë".repeat(200)));
+    }
+
+    #[test]
+    fn a_title_longer_than_200_graphemes_is_rejected() {
+        assert_err!(NewsletterTitle::parse("ë".repeat(201)));
+    }
+
+    #[test]
+    fn newlines_and_control_characters_are_replaced() {
+        let title = NewsletterTitle::parse("Issue #1\r\nBcc: everyone\u{0}".into()).unwrap();
+
+        assert_eq!(title.as_ref(), "Issue #1  Bcc: everyone");
+    }
+}
@@ -1,5 +1,7 @@
 use validator::validate_email;
 
+use crate::dns::MxResolver;
+
 #[derive(Debug)]
 pub struct SubscriberEmail(String);
 
@@ -11,6 +13,24 @@ impl SubscriberEmail {
             Err(format!("{} is not a valid email", &email))
         }
     }
+
+    /// Whether the domain accepts email at all, i.e. publishes an MX record
+    /// other than a null MX, which `parse` cannot tell from the syntax. A
+    /// failed lookup gives the address the benefit of the doubt.
+    pub async fn verify_deliverable(&self, resolver: &impl MxResolver) -> bool {
+        let Some((_, domain)) = self.0.rsplit_once('@') else {
+            return false;
+        };
+        match resolver.mx_records(domain).await {
+            Ok(exchanges) => exchanges
+                .iter()
+                .any(|e| !e.trim_end_matches('.').is_empty()),
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to look up the MX records of an email domain");
+                true
+            }
+        }
+    }
 }
 
 impl AsRef<str> for SubscriberEmail {
@@ -19,13 +39,21 @@ impl AsRef<str> for SubscriberEmail {
     }
 }
 
+impl std::fmt::Display for SubscriberEmail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::SubscriberEmail;
+    use crate::dns::MxResolver;
     // use claim::assert_err;
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
     use quickcheck::Arbitrary;
+    use trust_dns_resolver::error::ResolveError;
 
     // Both `Clone` and `Debug` are required by `quickcheck`
     #[derive(Debug, Clone)]
@@ -42,4 +70,49 @@ mod tests {
     fn works_for_valid_emails(valid_email: ValidEmailFixture) -> bool {
         SubscriberEmail::parse(valid_email.0).is_ok()
     }
+
+    /// Answers with the mail exchanges of `gmail.com` and `nomx.example`
+    /// only; the latter being a null MX.
+    struct MockResolver;
+
+    impl MxResolver for MockResolver {
+        async fn mx_records(&self, domain: &str) -> Result<Vec<String>, ResolveError> {
+            Ok(match domain {
+                "gmail.com" => vec!["gmail-smtp-in.l.google.com.".into()],
+                "nomx.example" => vec![".".into()],
+                _ => vec![],
+            })
+        }
+    }
+
+    fn email(email: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(email.into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_domain_with_mx_records_is_deliverable() {
+        assert!(
+            email("ursula@gmail.com")
+                .verify_deliverable(&MockResolver)
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn a_domain_without_mx_records_is_not_deliverable() {
+        assert!(
+            !email("ursula@gmial.com")
+                .verify_deliverable(&MockResolver)
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn a_domain_with_a_null_mx_is_not_deliverable() {
+        assert!(
+            !email("ursula@nomx.example")
+                .verify_deliverable(&MockResolver)
+                .await
+        );
+    }
 }
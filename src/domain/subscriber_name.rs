@@ -1,13 +1,57 @@
 use unicode_segmentation::UnicodeSegmentation;
 
+/// How the length of a name is measured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LengthUnit {
+    /// What a reader perceives as characters, e.g. `ë` or a flag emoji.
+    #[default]
+    Graphemes,
+    /// Unicode scalar values, as in a `varchar(n)` column.
+    ScalarValues,
+    /// UTF-8 bytes, as in a byte-sized column.
+    Bytes,
+}
+
+impl LengthUnit {
+    pub fn measure(&self, value: &str) -> usize {
+        match self {
+            Self::Graphemes => value.graphemes(true).count(),
+            Self::ScalarValues => value.chars().count(),
+            Self::Bytes => value.len(),
+        }
+    }
+}
+
+/// How long a subscriber name may be, measured so that the limit can match
+/// the storage constraint.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize)]
+pub struct NamePolicy {
+    pub max_length: usize,
+    pub length_unit: LengthUnit,
+}
+
+impl Default for NamePolicy {
+    fn default() -> Self {
+        Self {
+            max_length: 256,
+            length_unit: LengthUnit::Graphemes,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
 impl SubscriberName {
     pub fn parse(name: String) -> Result<Self, String> {
+        Self::parse_with(name, &NamePolicy::default())
+    }
+
+    pub fn parse_with(name: String, policy: &NamePolicy) -> Result<Self, String> {
         let is_empty_or_whitespace = name.trim().is_empty();
 
-        let is_too_long = name.graphemes(true).count() > 256;
+        let is_too_long = policy.length_unit.measure(&name) > policy.max_length;
         let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
         let contains_forbidden_characters = name.chars().any(|g| forbidden_characters.contains(&g));
 
@@ -27,9 +71,41 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::SubscriberName;
+    use crate::domain::{LengthUnit, NamePolicy, SubscriberName};
     use claim::{assert_err, assert_ok};
 
+    fn policy(length_unit: LengthUnit) -> NamePolicy {
+        NamePolicy {
+            max_length: 256,
+            length_unit,
+        }
+    }
+
+    #[test]
+    fn a_name_within_the_graphemes_limit_can_exceed_a_bytes_limit() {
+        // Two bytes per `ë`.
+        let name = "ë".repeat(200);
+        assert_ok!(SubscriberName::parse_with(
+            name.clone(),
+            &policy(LengthUnit::Graphemes)
+        ));
+        assert_err!(SubscriberName::parse_with(name, &policy(LengthUnit::Bytes)));
+    }
+
+    #[test]
+    fn scalar_values_count_combining_marks_separately() {
+        // `e` followed by a combining diaeresis: one grapheme, two scalars.
+        let name = "e\u{308}".repeat(200);
+        assert_ok!(SubscriberName::parse_with(
+            name.clone(),
+            &policy(LengthUnit::Graphemes)
+        ));
+        assert_err!(SubscriberName::parse_with(
+            name,
+            &policy(LengthUnit::ScalarValues)
+        ));
+    }
+
     #[test]
     fn a_256_grapheme_long_name_is_valid() {
         let name = "ë".repeat(256);
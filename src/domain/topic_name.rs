@@ -0,0 +1,62 @@
+/// The name of a newsletter topic subscribers pick, e.g. `weekly`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopicName(String);
+
+impl TopicName {
+    const MAX_LENGTH: usize = 64;
+
+    /// Names are matched case-insensitively: they are stored lowercase.
+    pub fn parse(name: String) -> Result<Self, String> {
+        let name = name.trim().to_lowercase();
+        if name.is_empty() {
+            Err("A topic name cannot be empty.".into())
+        } else if name.len() > Self::MAX_LENGTH {
+            Err(format!(
+                "A topic name cannot be longer than {} characters.",
+                Self::MAX_LENGTH
+            ))
+        } else if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            Err(format!("{} is not a valid topic name.", name))
+        } else {
+            Ok(Self(name))
+        }
+    }
+}
+
+impl AsRef<str> for TopicName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::TopicName;
+    use claim::assert_err;
+
+    #[test]
+    fn topic_names_are_trimmed_and_lowercased() {
+        let name = TopicName::parse(" Weekly ".into()).unwrap();
+
+        assert_eq!(name.as_ref(), "weekly");
+    }
+
+    #[test]
+    fn an_empty_topic_name_is_rejected() {
+        assert_err!(TopicName::parse(" ".into()));
+    }
+
+    #[test]
+    fn a_topic_name_with_other_characters_is_rejected() {
+        assert_err!(TopicName::parse("weekly news".into()));
+        assert_err!(TopicName::parse("weekly,product".into()));
+    }
+
+    #[test]
+    fn a_topic_name_longer_than_64_characters_is_rejected() {
+        assert_err!(TopicName::parse("a".repeat(65)));
+    }
+}
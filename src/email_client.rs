@@ -1,16 +1,175 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
 use crate::domain::SubscriberEmail;
+use crate::pii::PiiLogging;
+use crate::smtp::{Message, SmtpError, SmtpTransport};
+use crate::telemetry::LogSampler;
+use prometheus::{Histogram, HistogramOpts};
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
 
 pub const SERVER_TOKEN_HEADER_KEY: &str = "X-Postmark-Server-Token";
 
+/// The `ErrorCode` of Postmark's 422 for a recipient on its inactive list.
+const INACTIVE_RECIPIENT_ERROR_CODE: i64 = 406;
+
+const DEFAULT_MAX_RETRY_AFTER: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How emails leave the application.
+pub enum EmailDelivery {
+    /// Through Postmark's HTTP API.
+    Postmark {
+        http_client: Client,
+        base_url: String,
+        authorization_token: Secret<String>,
+        message_streams: MessageStreams,
+    },
+    /// Through an SMTP relay.
+    Smtp(SmtpTransport),
+    /// Nowhere: the emails are kept in memory, e.g. for local development.
+    Memory(Mutex<Vec<SentEmail>>),
+}
+
+/// What an email is, which decides the Postmark message stream it goes
+/// through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageStream {
+    /// One-off emails triggered by the recipient, e.g. confirmations.
+    Transactional,
+    /// Newsletters, sent to every subscriber at once.
+    Broadcast,
+}
+
+/// The IDs of the Postmark message streams. Postmark wants bulk emails kept
+/// apart from transactional ones, so that a newsletter drawing complaints
+/// does not hurt the deliverability of confirmations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageStreams {
+    pub transactional: String,
+    pub broadcast: String,
+}
+
+impl Default for MessageStreams {
+    /// The streams every Postmark server starts with.
+    fn default() -> Self {
+        Self {
+            transactional: "outbound".into(),
+            broadcast: "broadcast".into(),
+        }
+    }
+}
+
+impl MessageStreams {
+    fn id(&self, stream: MessageStream) -> &str {
+        match stream {
+            MessageStream::Transactional => &self.transactional,
+            MessageStream::Broadcast => &self.broadcast,
+        }
+    }
+}
+
+/// An email kept by the in-memory delivery.
+#[derive(Clone, Debug)]
+pub struct SentEmail {
+    pub sender: String,
+    pub recipient: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+    #[error(transparent)]
+    Postmark(#[from] reqwest::Error),
+    /// A 429 from Postmark, with how long its `Retry-After` asked us to wait.
+    #[error("Postmark is rate limiting our requests.")]
+    RateLimited(Option<std::time::Duration>),
+    /// Postmark refuses to email a recipient on its inactive list, e.g.
+    /// after a hard bounce: retrying is pointless.
+    #[error("The recipient is on Postmark's inactive list.")]
+    InactiveRecipient,
+    #[error(transparent)]
+    Smtp(#[from] SmtpError),
+}
+
+/// Above this many remembered sends, those past the window are dropped on
+/// the next claim, rather than on every send.
+const DOUBLE_SEND_PRUNE_THRESHOLD: usize = 10_000;
+
+/// Remembers recent sends, so that the same email to the same recipient
+/// within `window`, e.g. from a bug or a rapid retry, is only sent once.
+struct DoubleSendGuard {
+    window: Duration,
+    /// When each (recipient, hash of the subject) was last sent.
+    sent: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl DoubleSendGuard {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn key(recipient: &SubscriberEmail, subject: &str) -> (String, String) {
+        let subject_hash = format!("{:x}", Sha256::digest(subject.as_bytes()));
+        (recipient.as_ref().to_owned(), subject_hash)
+    }
+
+    /// Claim the send of `subject` to `recipient` at `now`; `false` if it
+    /// was sent (or is being sent) within the window.
+    fn claim(&self, recipient: &SubscriberEmail, subject: &str, now: Instant) -> bool {
+        let mut sent = self.sent.lock().unwrap();
+        if sent.len() > DOUBLE_SEND_PRUNE_THRESHOLD {
+            sent.retain(|_, at| now.duration_since(*at) < self.window);
+        }
+        match sent.entry(Self::key(recipient, subject)) {
+            // A send past the window may not have been pruned yet.
+            std::collections::hash_map::Entry::Occupied(mut entry) => {
+                if now.duration_since(*entry.get()) < self.window {
+                    return false;
+                }
+                entry.insert(now);
+                true
+            }
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(now);
+                true
+            }
+        }
+    }
+
+    /// Forget a claimed send that failed, so that it can be retried.
+    fn release(&self, recipient: &SubscriberEmail, subject: &str) {
+        self.sent
+            .lock()
+            .unwrap()
+            .remove(&Self::key(recipient, subject));
+    }
+}
+
 pub struct EmailClient {
-    http_client: Client,
-    base_url: String,
+    delivery: EmailDelivery,
     sender: SubscriberEmail,
-    authorization_token: Secret<String>,
+    timeout: std::time::Duration,
+    send_duration: Histogram,
+    sent_log_sampler: LogSampler,
+    pii_logging: PiiLogging,
+    max_retry_after: std::time::Duration,
+    subject_prefix: Option<String>,
+    double_send_guard: Option<DoubleSendGuard>,
 }
 
+/// Key-value pairs attached to an email for the provider's analytics, e.g.
+/// `{ "campaign": "spring" }`; only Postmark keeps them.
+pub type EmailMetadata = BTreeMap<String, String>;
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
@@ -19,61 +178,465 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    message_stream: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a EmailMetadata>,
 }
 
 impl EmailClient {
+    /// A client sending emails through Postmark.
     pub fn new(
         base_url: String,
         sender: SubscriberEmail,
         authorization_token: Secret<String>,
         timeout: std::time::Duration,
     ) -> Self {
-        Self {
+        let delivery = EmailDelivery::Postmark {
             http_client: Client::builder().timeout(timeout).build().unwrap(),
             base_url,
-            sender,
             authorization_token,
+            message_streams: MessageStreams::default(),
+        };
+        Self::with_delivery(delivery, sender, timeout)
+    }
+
+    pub fn with_delivery(
+        delivery: EmailDelivery,
+        sender: SubscriberEmail,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self {
+            delivery,
+            sender,
+            timeout,
+            // Not exported anywhere until `with_send_duration` is called.
+            send_duration: Histogram::with_opts(HistogramOpts::new(
+                "email_send_duration_seconds",
+                "Time spent sending an email through the provider's API.",
+            ))
+            .unwrap(),
+            sent_log_sampler: LogSampler::new(1),
+            pii_logging: PiiLogging::default(),
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+            subject_prefix: None,
+            double_send_guard: None,
+        }
+    }
+
+    /// Only log one in every `rate` successful sends; failures are always
+    /// logged.
+    pub fn with_sent_log_sample_rate(mut self, rate: u64) -> Self {
+        self.sent_log_sampler = LogSampler::new(rate);
+        self
+    }
+
+    /// Send through the given Postmark message streams; only meaningful for
+    /// the Postmark delivery.
+    pub fn with_message_streams(mut self, streams: MessageStreams) -> Self {
+        if let EmailDelivery::Postmark {
+            message_streams, ..
+        } = &mut self.delivery
+        {
+            *message_streams = streams;
+        }
+        self
+    }
+
+    /// Give up on connecting to the provider after `connect_timeout`, well
+    /// before the overall timeout when the connection stalls; only
+    /// meaningful for the Postmark delivery.
+    pub fn with_connect_timeout(mut self, connect_timeout: std::time::Duration) -> Self {
+        if let EmailDelivery::Postmark { http_client, .. } = &mut self.delivery {
+            *http_client = Client::builder()
+                .timeout(self.timeout)
+                .connect_timeout(connect_timeout)
+                .build()
+                .unwrap();
+        }
+        self
+    }
+
+    /// Record how long each request to the provider takes in `histogram`.
+    pub fn with_send_duration(mut self, histogram: Histogram) -> Self {
+        self.send_duration = histogram;
+        self
+    }
+
+    /// How recipients are written into the client's logs.
+    pub fn with_pii_logging(mut self, pii_logging: PiiLogging) -> Self {
+        self.pii_logging = pii_logging;
+        self
+    }
+
+    /// Wait at most this long when the provider asks us to back off with a
+    /// `Retry-After`.
+    pub fn with_max_retry_after(mut self, max_retry_after: std::time::Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// Skip an email with the same recipient and subject as one sent less
+    /// than `window` ago; `None` sends every email.
+    pub fn with_double_send_guard(mut self, window: Option<Duration>) -> Self {
+        self.double_send_guard = window.map(DoubleSendGuard::new);
+        self
+    }
+
+    /// Start every subject with `prefix`, e.g. `[MyNewsletter]`.
+    pub fn with_subject_prefix(mut self, prefix: Option<String>) -> Self {
+        self.subject_prefix = prefix;
+        self
+    }
+
+    /// `subject` after the configured prefix and a space, unless it already
+    /// starts with it.
+    fn prefixed_subject<'a>(&self, subject: &'a str) -> Cow<'a, str> {
+        match &self.subject_prefix {
+            Some(prefix) if !subject.starts_with(prefix.as_str()) => {
+                Cow::Owned(format!("{} {}", prefix, subject))
+            }
+            _ => Cow::Borrowed(subject),
+        }
+    }
+
+    pub fn pii_logging(&self) -> PiiLogging {
+        self.pii_logging
+    }
+
+    /// The `provider` label of the client's metrics.
+    pub fn provider(&self) -> &'static str {
+        match self.delivery {
+            EmailDelivery::Postmark { .. } => "postmark",
+            EmailDelivery::Smtp(_) => "smtp",
+            EmailDelivery::Memory(_) => "memory",
+        }
+    }
+
+    /// The emails kept by the in-memory delivery; always empty for the
+    /// others.
+    pub fn sent_emails(&self) -> Vec<SentEmail> {
+        match &self.delivery {
+            EmailDelivery::Memory(sent) => sent.lock().unwrap().clone(),
+            _ => Vec::new(),
         }
     }
 
+    /// Send an email from the configured sender.
     pub async fn send_email(
         &self,
-        recipient: SubscriberEmail,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        stream: MessageStream,
+        metadata: Option<&EmailMetadata>,
+    ) -> Result<(), SendEmailError> {
+        self.send_email_from(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            stream,
+            metadata,
+        )
+        .await
+    }
+
+    /// Send an email; when the provider rate limits us, wait as long as its
+    /// `Retry-After` asks (up to `max_retry_after`) and try once more.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn send_email_from(
+        &self,
+        sender: &SubscriberEmail,
+        recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        let url = reqwest::Url::parse(&self.base_url)
-            .expect("Failed to parse URL")
-            .join("/email")
-            .expect("Failed to join URL");
-
-        let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
+        stream: MessageStream,
+        metadata: Option<&EmailMetadata>,
+    ) -> Result<(), SendEmailError> {
+        self.send_email_within(
+            self.timeout,
+            sender,
+            recipient,
             subject,
-            html_body: html_content,
-            text_body: text_content,
+            html_content,
+            text_content,
+            stream,
+            metadata,
+        )
+        .await
+    }
+
+    /// Give the sends made through the returned handle `timeout` instead of
+    /// the client's default, e.g. for calls known to be slow.
+    pub fn override_timeout(&self, timeout: std::time::Duration) -> TimeoutOverride<'_> {
+        TimeoutOverride {
+            client: self,
+            timeout,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn send_email_within(
+        &self,
+        timeout: std::time::Duration,
+        sender: &SubscriberEmail,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        stream: MessageStream,
+        metadata: Option<&EmailMetadata>,
+    ) -> Result<(), SendEmailError> {
+        let subject = &self.prefixed_subject(subject);
+        if let Some(guard) = &self.double_send_guard {
+            if !guard.claim(recipient, subject, Instant::now()) {
+                tracing::warn!(
+                    recipient = %self.pii_logging.email(recipient.as_ref()),
+                    "Skipping an email identical to one sent moments ago"
+                );
+                return Ok(());
+            }
+        }
+        let mut result = self
+            .try_send_email(
+                timeout,
+                sender,
+                recipient,
+                subject,
+                html_content,
+                text_content,
+                stream,
+                metadata,
+            )
+            .await;
+        if let Err(SendEmailError::RateLimited(Some(retry_after))) = result {
+            let wait = retry_after.min(self.max_retry_after);
+            tracing::info!(
+                wait_millis = wait.as_millis() as u64,
+                "Rate limited by the email provider, waiting before retrying"
+            );
+            tokio::time::sleep(wait).await;
+            result = self
+                .try_send_email(
+                    timeout,
+                    sender,
+                    recipient,
+                    subject,
+                    html_content,
+                    text_content,
+                    stream,
+                    metadata,
+                )
+                .await;
+        }
+
+        match result {
+            Ok(()) => {
+                if self.sent_log_sampler.sample() {
+                    tracing::info!(
+                        recipient = %self.pii_logging.email(recipient.as_ref()),
+                        "Email sent"
+                    );
+                }
+                Ok(())
+            }
+            Err(e) => {
+                if let Some(guard) = &self.double_send_guard {
+                    guard.release(recipient, subject);
+                }
+                tracing::warn!(
+                    recipient = %self.pii_logging.email(recipient.as_ref()),
+                    error = %e,
+                    "Failed to send an email"
+                );
+                Err(e)
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn try_send_email(
+        &self,
+        timeout: std::time::Duration,
+        sender: &SubscriberEmail,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        stream: MessageStream,
+        metadata: Option<&EmailMetadata>,
+    ) -> Result<(), SendEmailError> {
+        let timer = self.send_duration.start_timer();
+        let result = match &self.delivery {
+            EmailDelivery::Postmark {
+                http_client,
+                base_url,
+                authorization_token,
+                message_streams,
+            } => {
+                let url = reqwest::Url::parse(base_url)
+                    .expect("Failed to parse URL")
+                    .join("/email")
+                    .expect("Failed to join URL");
+                let request_body = SendEmailRequest {
+                    from: sender.as_ref(),
+                    to: recipient.as_ref(),
+                    subject,
+                    html_body: html_content,
+                    text_body: text_content,
+                    message_stream: message_streams.id(stream),
+                    metadata,
+                };
+                let response = http_client
+                    .post(url)
+                    .header(SERVER_TOKEN_HEADER_KEY, authorization_token.expose_secret())
+                    .timeout(timeout)
+                    .json(&request_body)
+                    .send()
+                    .await;
+                match response {
+                    Ok(response) => postmark_outcome(response).await,
+                    Err(e) => Err(e.into()),
+                }
+            }
+            EmailDelivery::Smtp(transport) => {
+                let message = Message {
+                    from: sender,
+                    to: recipient,
+                    subject,
+                    html_body: html_content,
+                    text_body: text_content,
+                };
+                transport
+                    .send(&message, timeout)
+                    .await
+                    .map_err(SendEmailError::from)
+            }
+            EmailDelivery::Memory(sent) => {
+                sent.lock().unwrap().push(SentEmail {
+                    sender: sender.as_ref().to_owned(),
+                    recipient: recipient.as_ref().to_owned(),
+                    subject: subject.to_owned(),
+                    html_body: html_content.to_owned(),
+                    text_body: text_content.to_owned(),
+                });
+                Ok(())
+            }
         };
+        timer.observe_duration();
+        result
+    }
+}
+
+/// Sends through an `EmailClient` with another timeout than its default.
+pub struct TimeoutOverride<'a> {
+    client: &'a EmailClient,
+    timeout: std::time::Duration,
+}
+
+impl TimeoutOverride<'_> {
+    /// Send an email from the configured sender.
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        stream: MessageStream,
+        metadata: Option<&EmailMetadata>,
+    ) -> Result<(), SendEmailError> {
+        self.send_email_from(
+            &self.client.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            stream,
+            metadata,
+        )
+        .await
+    }
 
-        let _builder = self
-            .http_client
-            .post(url)
-            .header(
-                SERVER_TOKEN_HEADER_KEY,
-                self.authorization_token.expose_secret(),
+    #[allow(clippy::too_many_arguments)]
+    pub async fn send_email_from(
+        &self,
+        sender: &SubscriberEmail,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        stream: MessageStream,
+        metadata: Option<&EmailMetadata>,
+    ) -> Result<(), SendEmailError> {
+        self.client
+            .send_email_within(
+                self.timeout,
+                sender,
+                recipient,
+                subject,
+                html_content,
+                text_content,
+                stream,
+                metadata,
             )
-            .json(&request_body)
-            .send()
-            .await?;
+            .await
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkErrorBody {
+    error_code: i64,
+}
 
-        Ok(())
+/// Tell the errors Postmark answered with apart: those to back off from,
+/// those not to retry, and the rest.
+async fn postmark_outcome(response: reqwest::Response) -> Result<(), SendEmailError> {
+    let status = response.status();
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Err(SendEmailError::RateLimited(retry_after(&response)));
     }
+    let Err(e) = response.error_for_status_ref() else {
+        return Ok(());
+    };
+    if status == reqwest::StatusCode::UNPROCESSABLE_ENTITY {
+        if let Ok(body) = response.json::<PostmarkErrorBody>().await {
+            if body.error_code == INACTIVE_RECIPIENT_ERROR_CODE {
+                return Err(SendEmailError::InactiveRecipient);
+            }
+        }
+    }
+    Err(e.into())
+}
+
+/// The wait a `Retry-After` header asks for, given either in seconds or as an
+/// HTTP date.
+fn retry_after(response: &reqwest::Response) -> Option<std::time::Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+        .or(Some(std::time::Duration::ZERO))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::SERVER_TOKEN_HEADER_KEY;
+    use super::{
+        DoubleSendGuard, EmailMetadata, MessageStream, MessageStreams, SendEmailError,
+        SERVER_TOKEN_HEADER_KEY,
+    };
     use crate::domain::SubscriberEmail;
     use crate::email_client::EmailClient;
     use claim::{assert_err, assert_ok};
@@ -81,7 +644,13 @@ mod tests {
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::Secret;
-    use wiremock::matchers::{header, header_exists, method, path};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
     struct SendEmailBodyMatcher;
@@ -137,9 +706,13 @@ mod tests {
             .await
     }
 
-    async fn make_request(email_client: EmailClient) -> Result<(), reqwest::Error> {
+    async fn make_request(email_client: EmailClient) -> Result<(), SendEmailError> {
+        send(email_client, MessageStream::Transactional).await
+    }
+
+    async fn send(email_client: EmailClient, stream: MessageStream) -> Result<(), SendEmailError> {
         email_client
-            .send_email(email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), stream, None)
             .await
     }
 
@@ -154,6 +727,17 @@ mod tests {
         assert_ok!(response);
     }
 
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        mock_response(&mock_server, ResponseTemplate::new(500)).await;
+
+        let response = make_request(email_client).await;
+
+        assert_err!(response);
+    }
+
     #[tokio::test]
     async fn send_email_times_out() {
         let mock_server = MockServer::start().await;
@@ -163,10 +747,398 @@ mod tests {
 
         mock_response(&mock_server, response).await;
 
+        let response = make_request(email_client).await;
+
+        assert_err!(response);
+    }
+
+    #[tokio::test]
+    async fn an_inactive_recipient_is_told_apart_from_other_422s() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let body = serde_json::json!({
+            "ErrorCode": 406,
+            "Message": "You tried to send to a recipient that has been marked as inactive."
+        });
+        mock_response(&mock_server, ResponseTemplate::new(422).set_body_json(body)).await;
+
+        let response = make_request(email_client).await;
+
+        assert!(matches!(response, Err(SendEmailError::InactiveRecipient)));
+    }
+
+    #[tokio::test]
+    async fn a_timeout_override_lets_a_slow_send_succeed() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        // Beyond the client's 200ms, within the override.
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(500));
+        mock_response(&mock_server, response).await;
+
         let response = email_client
-            .send_email(email(), &subject(), &content(), &content())
+            .override_timeout(std::time::Duration::from_secs(5))
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+                None,
+            )
             .await;
 
-        assert_err!(response);
+        assert_ok!(response);
+    }
+
+    #[tokio::test]
+    async fn a_stalled_connect_fails_after_the_connect_timeout() {
+        // Not routable: connecting hangs rather than being refused.
+        let email_client = EmailClient::new(
+            "http://10.255.255.1".into(),
+            email(),
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_secs(10),
+        )
+        .with_connect_timeout(std::time::Duration::from_millis(100));
+
+        let started = std::time::Instant::now();
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+                None,
+            )
+            .await;
+
+        assert_err!(outcome);
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    /// Send an email titled `subject` and return the subject the provider
+    /// received.
+    async fn sent_subject(
+        email_client: EmailClient,
+        mock_server: &MockServer,
+        subject: &str,
+    ) -> String {
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(mock_server)
+            .await;
+        email_client
+            .send_email(
+                &email(),
+                subject,
+                &content(),
+                &content(),
+                MessageStream::Transactional,
+                None,
+            )
+            .await
+            .unwrap();
+        let request = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        body["Subject"].as_str().unwrap().to_owned()
+    }
+
+    #[tokio::test]
+    async fn send_email_passes_the_metadata_to_postmark() {
+        let mock_server = MockServer::start().await;
+        Mock::given(body_partial_json(serde_json::json!({
+            "Metadata": { "campaign": "spring", "subscriber_id": "42" }
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+        let metadata = EmailMetadata::from([
+            ("campaign".to_owned(), "spring".to_owned()),
+            ("subscriber_id".to_owned(), "42".to_owned()),
+        ]);
+
+        let outcome = email_client(mock_server.uri())
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+                Some(&metadata),
+            )
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn the_same_email_is_sent_once_within_the_double_send_window() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        let email_client = email_client(mock_server.uri())
+            .with_double_send_guard(Some(std::time::Duration::from_secs(60)));
+        let recipient = email();
+        let send = |subject: &'static str| {
+            email_client.send_email(
+                &recipient,
+                subject,
+                "html",
+                "text",
+                MessageStream::Transactional,
+                None,
+            )
+        };
+
+        assert_ok!(send("Welcome!").await);
+        assert_ok!(send("Welcome!").await);
+        // Another subject is another email.
+        assert_ok!(send("Your newsletter").await);
+    }
+
+    #[tokio::test]
+    async fn a_failed_send_can_be_retried_within_the_double_send_window() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let email_client = email_client(mock_server.uri())
+            .with_double_send_guard(Some(std::time::Duration::from_secs(60)));
+        let recipient = email();
+        let send = || {
+            email_client.send_email(
+                &recipient,
+                "Welcome!",
+                "html",
+                "text",
+                MessageStream::Transactional,
+                None,
+            )
+        };
+
+        assert_err!(send().await);
+        assert_ok!(send().await);
+    }
+
+    #[test]
+    fn a_send_past_the_double_send_window_can_be_claimed_again() {
+        let window = std::time::Duration::from_secs(60);
+        let guard = DoubleSendGuard::new(window);
+        let recipient = email();
+        let start = std::time::Instant::now();
+
+        assert!(guard.claim(&recipient, "Welcome!", start));
+        assert!(!guard.claim(&recipient, "Welcome!", start + window / 2));
+        // Below the pruning threshold, the expired send is still remembered.
+        assert!(guard.claim(&recipient, "Welcome!", start + window));
+        assert_eq!(guard.sent.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn the_subject_prefix_is_prepended_once() {
+        let mock_server = MockServer::start().await;
+        let email_client =
+            || email_client(mock_server.uri()).with_subject_prefix(Some("[MyNewsletter]".into()));
+
+        assert_eq!(
+            sent_subject(email_client(), &mock_server, "Welcome!").await,
+            "[MyNewsletter] Welcome!"
+        );
+        assert_eq!(
+            sent_subject(email_client(), &mock_server, "[MyNewsletter] Welcome!").await,
+            "[MyNewsletter] Welcome!"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_subject_is_unchanged_without_a_prefix() {
+        let mock_server = MockServer::start().await;
+
+        assert_eq!(
+            sent_subject(email_client(mock_server.uri()), &mock_server, "Welcome!").await,
+            "Welcome!"
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_uses_the_configured_message_streams() {
+        let mock_server = MockServer::start().await;
+        let streams = MessageStreams {
+            transactional: "confirmations".into(),
+            broadcast: "newsletters".into(),
+        };
+        for stream in ["confirmations", "newsletters"] {
+            Mock::given(body_partial_json(
+                serde_json::json!({ "MessageStream": stream }),
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        }
+
+        let email_client = || email_client(mock_server.uri()).with_message_streams(streams.clone());
+        assert_ok!(send(email_client(), MessageStream::Transactional).await);
+        assert_ok!(send(email_client(), MessageStream::Broadcast).await);
+    }
+
+    #[tokio::test]
+    async fn send_email_uses_postmarks_default_streams_by_default() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        send(
+            email_client(mock_server.uri()),
+            MessageStream::Transactional,
+        )
+        .await
+        .unwrap();
+        send(email_client(mock_server.uri()), MessageStream::Broadcast)
+            .await
+            .unwrap();
+
+        let streams: Vec<serde_json::Value> = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+            .map(|body| body["MessageStream"].clone())
+            .collect();
+        assert_eq!(streams, vec!["outbound", "broadcast"]);
+    }
+
+    #[tokio::test]
+    async fn send_email_waits_for_retry_after_on_a_429_then_retries() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let started = std::time::Instant::now();
+        let response = make_request(email_client).await;
+
+        assert_ok!(response);
+        assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn the_retry_after_wait_is_capped() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri())
+            .with_max_retry_after(std::time::Duration::from_millis(100));
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let started = std::time::Instant::now();
+        let response = make_request(email_client).await;
+
+        assert_ok!(response);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn a_429_without_retry_after_is_not_retried() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = make_request(email_client).await;
+
+        assert!(matches!(response, Err(SendEmailError::RateLimited(None))));
+    }
+
+    /// Counts the "Email sent" events.
+    #[derive(Clone, Default)]
+    struct SentEvents(Arc<AtomicUsize>);
+
+    impl<S: Subscriber> Layer<S> for SentEvents {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            struct Message(bool);
+            impl Visit for Message {
+                fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                    if field.name() == "message" && format!("{:?}", value) == "Email sent" {
+                        self.0 = true;
+                    }
+                }
+            }
+            let mut message = Message(false);
+            event.record(&mut message);
+            if message.0 {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn sent_events_are_sampled() {
+        let sent_events = SentEvents::default();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::registry().with(sent_events.clone()),
+        );
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_sent_log_sample_rate(10);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        for _ in 0..50 {
+            email_client
+                .send_email(
+                    &email(),
+                    &subject(),
+                    &content(),
+                    &content(),
+                    MessageStream::Transactional,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(sent_events.0.load(Ordering::SeqCst), 5);
     }
 }
@@ -0,0 +1,57 @@
+//! The emails we send, rendered from their parameters.
+
+#[derive(Clone)]
+pub struct EmailContent {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl EmailContent {
+    /// Append a footer pointing the subscriber to `manage_link`; without a
+    /// link the email is left as is.
+    pub fn with_manage_link(mut self, manage_link: Option<&str>) -> Self {
+        if let Some(link) = manage_link {
+            self.html_body.push_str(&format!(
+                "<p><a href=\"{}\">Manage your subscription</a></p>",
+                link
+            ));
+            self.text_body
+                .push_str(&format!("\n\nManage your subscription: {}", link));
+        }
+        self
+    }
+}
+
+/// The link to the status of a subscription, keyed by its management token.
+pub fn manage_link(base_url: &str, management_token: &str) -> String {
+    format!(
+        "{}/subscriptions/status?token={}",
+        base_url, management_token
+    )
+}
+
+/// Ask a new subscriber to visit `confirmation_link`.
+pub fn confirmation_email(confirmation_link: &str) -> EmailContent {
+    EmailContent {
+        subject: "Welcome!".into(),
+        html_body: format!(
+            "Welcome to our newsletter!<br />\
+            Click <a href=\"{}\">here</a> to confirm your subscription.",
+            confirmation_link
+        ),
+        text_body: format!(
+            "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
+            confirmation_link
+        ),
+    }
+}
+
+/// A newsletter issue, sent as written by its author.
+pub fn newsletter_email(title: &str, html_content: &str, text_content: &str) -> EmailContent {
+    EmailContent {
+        subject: title.into(),
+        html_body: html_content.into(),
+        text_body: text_content.into(),
+    }
+}
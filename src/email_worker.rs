@@ -0,0 +1,524 @@
+//! Emails waiting to be sent, and the background worker sending them, so that
+//! a slow or unavailable provider does not fail the request that queued them.
+//!
+//! One-off emails (e.g. confirmations) are queued with their content; a
+//! newsletter delivery only refers to its issue and subscriber, the content
+//! being stored once in `newsletter_issues`.
+//!
+//! Both are checked against the suppression list right before sending: an
+//! address may well have been suppressed since its email was queued.
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use prometheus::Counter;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::configuration::EmailQueueSettings;
+use crate::deliveries::{record_delivery, record_send_attempt, DeliveryStatus};
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, EmailMetadata, MessageStream, SendEmailError};
+use crate::email_templates::{manage_link, newsletter_email};
+use crate::suppressions::{is_suppressed, suppress, suppress_subscriber};
+
+/// Queue an email to be sent once `execute_after` has passed.
+#[tracing::instrument(name = "Queueing an email", skip_all)]
+pub async fn enqueue_email(
+    executor: impl PgExecutor<'_>,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_body: &str,
+    text_body: &str,
+    execute_after: DateTime<Utc>,
+) -> Result<Uuid, sqlx::Error> {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_queue (id, recipient, subject, html_body, text_body, execute_after)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        id,
+        recipient.as_ref(),
+        subject,
+        html_body,
+        text_body,
+        execute_after,
+    )
+    .execute(executor)
+    .await?;
+    Ok(id)
+}
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+struct EmailTask {
+    id: Uuid,
+    recipient: String,
+    subject: String,
+    html_body: String,
+    text_body: String,
+    n_retries: i16,
+}
+
+/// Send the oldest due email if any, confirmations and other one-off emails
+/// before newsletter deliveries.
+///
+/// A failed send is retried after `retry_delay_seconds`; once it has failed
+/// `max_retries` times the email is dropped. Newsletters link to
+/// `base_url` for subscribers to manage their subscription.
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    settings: &EmailQueueSettings,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    match try_send_queued_email(pool, email_client, settings).await? {
+        ExecutionOutcome::TaskCompleted => Ok(ExecutionOutcome::TaskCompleted),
+        ExecutionOutcome::EmptyQueue => {
+            try_deliver_issue(pool, email_client, settings, base_url).await
+        }
+    }
+}
+
+fn gives_up(n_retries: i16, settings: &EmailQueueSettings) -> bool {
+    i32::from(n_retries) + 1 >= i32::from(settings.max_retries)
+}
+
+/// The provider will never deliver to this recipient: retrying is pointless.
+fn is_inactive_recipient(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<SendEmailError>(),
+        Some(SendEmailError::InactiveRecipient)
+    )
+}
+
+/// Stop emailing a recipient the provider has marked as inactive.
+async fn suppress_inactive_recipient(
+    transaction: &mut Transaction<'static, Postgres>,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    suppress_subscriber(transaction, email)
+        .await
+        .context("Failed to suppress the subscriber")?;
+    suppress(&mut *transaction, email, "inactive_recipient")
+        .await
+        .context("Failed to add the address to the suppression list")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(email_id = tracing::field::Empty, recipient = tracing::field::Empty),
+    err
+)]
+async fn try_send_queued_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    settings: &EmailQueueSettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current()
+        .record("email_id", tracing::field::display(task.id))
+        .record(
+            "recipient",
+            tracing::field::display(email_client.pii_logging().email(&task.recipient)),
+        );
+
+    if is_suppressed(&mut transaction, &task.recipient)
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!("Dropping a queued email to a suppressed address.");
+        delete_task(&mut transaction, task.id).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the email queue transaction.")?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+
+    let outcome = send(
+        email_client,
+        None,
+        MessageStream::Transactional,
+        &task.recipient,
+        &task.subject,
+        &task.html_body,
+        &task.text_body,
+        None,
+    )
+    .await;
+    match outcome {
+        Ok(()) => delete_task(&mut transaction, task.id).await?,
+        Err(e) if is_inactive_recipient(&e) => {
+            tracing::warn!("Suppressing the inactive recipient of a queued email.");
+            suppress_inactive_recipient(&mut transaction, &task.recipient).await?;
+            delete_task(&mut transaction, task.id).await?;
+        }
+        Err(e) if gives_up(task.n_retries, settings) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                n_retries = task.n_retries,
+                "Giving up on a queued email."
+            );
+            delete_task(&mut transaction, task.id).await?;
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                n_retries = task.n_retries,
+                "Failed to send a queued email, retrying later."
+            );
+            reschedule_task(&mut transaction, task.id, settings.retry_delay()).await?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the email queue transaction.")?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Send from `sender` when given, otherwise from the configured sender.
+#[allow(clippy::too_many_arguments)]
+async fn send(
+    email_client: &EmailClient,
+    sender: Option<&str>,
+    stream: MessageStream,
+    recipient: &str,
+    subject: &str,
+    html_body: &str,
+    text_body: &str,
+    metadata: Option<&EmailMetadata>,
+) -> Result<(), anyhow::Error> {
+    let recipient = SubscriberEmail::parse(recipient.to_owned()).map_err(anyhow::Error::msg)?;
+    match sender {
+        Some(sender) => {
+            let sender = SubscriberEmail::parse(sender.to_owned()).map_err(anyhow::Error::msg)?;
+            email_client
+                .send_email_from(
+                    &sender, &recipient, subject, html_body, text_body, stream, metadata,
+                )
+                .await?
+        }
+        None => {
+            email_client
+                .send_email(&recipient, subject, html_body, text_body, stream, metadata)
+                .await?
+        }
+    }
+    Ok(())
+}
+
+/// Lock the oldest due email; concurrent workers skip it until the returned
+/// transaction ends.
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(Transaction<'static, Postgres>, EmailTask)>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let task = sqlx::query_as!(
+        EmailTask,
+        r#"
+        SELECT id, recipient, subject, html_body, text_body, n_retries
+        FROM email_queue
+        WHERE execute_after <= now()
+        ORDER BY execute_after
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to dequeue an email.")?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+async fn delete_task(
+    transaction: &mut Transaction<'static, Postgres>,
+    id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!("DELETE FROM email_queue WHERE id = $1", id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete a queued email.")?;
+    Ok(())
+}
+
+async fn reschedule_task(
+    transaction: &mut Transaction<'static, Postgres>,
+    id: Uuid,
+    delay: std::time::Duration,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE email_queue
+        SET n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => $2)
+        WHERE id = $1
+        "#,
+        id,
+        delay.as_secs_f64(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to reschedule a queued email.")?;
+    Ok(())
+}
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+    title: String,
+    html_content: String,
+    text_content: String,
+    sender_email: Option<String>,
+    management_token: Option<String>,
+}
+
+/// Deliver the oldest due newsletter issue to one subscriber, recording the
+/// outcome once there is nothing left to retry. A delivery already attempted
+/// `max_sends_per_subscriber` times fails without sending.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id = tracing::field::Empty,
+        recipient = tracing::field::Empty
+    ),
+    err
+)]
+async fn try_deliver_issue(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    settings: &EmailQueueSettings,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, task)) = dequeue_delivery(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current()
+        .record(
+            "newsletter_issue_id",
+            tracing::field::display(task.newsletter_issue_id),
+        )
+        .record(
+            "recipient",
+            tracing::field::display(email_client.pii_logging().email(&task.subscriber_email)),
+        );
+
+    let status = if is_suppressed(&mut transaction, &task.subscriber_email)
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!("Failing the delivery of an issue to a suppressed address.");
+        Some(DeliveryStatus::Failed)
+    } else {
+        let send_attempts =
+            record_send_attempt(pool, task.newsletter_issue_id, &task.subscriber_email)
+                .await
+                .context("Failed to count the attempt at a delivery.")?;
+        if send_attempts > i32::from(settings.max_sends_per_subscriber) {
+            tracing::error!(
+                send_attempts,
+                "Refusing to send an issue to the same subscriber again, failing the delivery."
+            );
+            Some(DeliveryStatus::Failed)
+        } else {
+            let manage_link = task
+                .management_token
+                .as_deref()
+                .map(|token| manage_link(base_url, token));
+            let email = newsletter_email(&task.title, &task.html_content, &task.text_content)
+                .with_manage_link(manage_link.as_deref());
+            let metadata = EmailMetadata::from([
+                ("issue_id".to_owned(), task.newsletter_issue_id.to_string()),
+                ("subscriber_id".to_owned(), task.subscriber_id.to_string()),
+            ]);
+            let outcome = send(
+                email_client,
+                task.sender_email.as_deref(),
+                MessageStream::Broadcast,
+                &task.subscriber_email,
+                &email.subject,
+                &email.html_body,
+                &email.text_body,
+                Some(&metadata),
+            )
+            .await;
+            match outcome {
+                Ok(()) => Some(DeliveryStatus::Delivered),
+                Err(e) if is_inactive_recipient(&e) => {
+                    tracing::warn!("Suppressing the inactive recipient of a newsletter delivery.");
+                    suppress_inactive_recipient(&mut transaction, &task.subscriber_email).await?;
+                    Some(DeliveryStatus::Failed)
+                }
+                Err(e) if gives_up(task.n_retries, settings) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        n_retries = task.n_retries,
+                        "Giving up on a newsletter delivery."
+                    );
+                    Some(DeliveryStatus::Failed)
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        n_retries = task.n_retries,
+                        "Failed to deliver a newsletter issue, retrying later."
+                    );
+                    None
+                }
+            }
+        }
+    };
+    match status {
+        Some(status) => {
+            record_delivery(
+                &mut transaction,
+                task.newsletter_issue_id,
+                &task.subscriber_email,
+                status,
+            )
+            .await
+            .context("Failed to record the outcome of a delivery.")?;
+            delete_delivery(&mut transaction, &task).await?;
+        }
+        None => reschedule_delivery(&mut transaction, &task, settings.retry_delay()).await?,
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the delivery queue transaction.")?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Lock the oldest due delivery, along with the content of its issue.
+async fn dequeue_delivery(
+    pool: &PgPool,
+) -> Result<Option<(Transaction<'static, Postgres>, DeliveryTask)>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let task = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_id,
+            s.email AS subscriber_email,
+            q.n_retries,
+            i.title,
+            i.html_content,
+            i.text_content,
+            i.sender_email,
+            m.management_token AS "management_token?"
+        FROM issue_delivery_queue q
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        LEFT JOIN management_tokens m ON m.subscriber_id = q.subscriber_id
+        WHERE q.execute_after <= now()
+        ORDER BY q.execute_after
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to dequeue a newsletter delivery.")?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+async fn delete_delivery(
+    transaction: &mut Transaction<'static, Postgres>,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete a queued delivery.")?;
+    Ok(())
+}
+
+async fn reschedule_delivery(
+    transaction: &mut Transaction<'static, Postgres>,
+    task: &DeliveryTask,
+    delay: std::time::Duration,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => $3)
+        WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+        delay.as_secs_f64(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to reschedule a queued delivery.")?;
+    Ok(())
+}
+
+/// Drain the queue until `token` is cancelled, polling every
+/// `poll_interval_millis` once it is empty and pausing for
+/// `inter_batch_delay_millis` after every `batch_size` emails. An email being
+/// sent when the token is cancelled is finished first.
+pub async fn run_worker_until_stopped(
+    pool: PgPool,
+    email_client: std::sync::Arc<EmailClient>,
+    settings: EmailQueueSettings,
+    base_url: String,
+    batch_pause_duration: Counter,
+    token: CancellationToken,
+) {
+    let inter_batch_delay = settings.inter_batch_delay();
+    let mut sent_in_batch = 0;
+    while !token.is_cancelled() {
+        let pause = match try_execute_task(&pool, &email_client, &settings, &base_url).await {
+            Ok(ExecutionOutcome::TaskCompleted) => {
+                sent_in_batch += 1;
+                if sent_in_batch < settings.batch_size || inter_batch_delay.is_zero() {
+                    continue;
+                }
+                sent_in_batch = 0;
+                tracing::info!(
+                    delay_ms = inter_batch_delay.as_millis() as u64,
+                    "Pausing between batches of emails."
+                );
+                batch_pause_duration.inc_by(inter_batch_delay.as_secs_f64());
+                inter_batch_delay
+            }
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                sent_in_batch = 0;
+                settings.poll_interval()
+            }
+            // Most likely the database is unavailable: back off.
+            Err(_) => std::time::Duration::from_secs(1),
+        };
+        tokio::select! {
+            _ = token.cancelled() => {}
+            _ = tokio::time::sleep(pause) => {}
+        }
+    }
+}
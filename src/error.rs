@@ -0,0 +1,219 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::{InternalError, JsonPayloadError, UrlencodedError};
+use actix_web::http::header::{ALLOW, LOCATION};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+
+/// The JSON body returned by every API error, e.g.
+/// `{ "code": "maintenance", "message": "..." }`.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    /// Every field that failed validation, when the error is about the
+    /// request's fields.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// A field of the request that failed validation, e.g.
+/// `{ "field": "email", "code": "invalid", "message": "..." }`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            code: code.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// The messages of `errors`, in one line.
+pub fn field_errors_message(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+pub fn json_error(status: StatusCode, code: &str, message: impl Into<String>) -> HttpResponse {
+    json_field_errors(status, code, message, Vec::new())
+}
+
+/// An error about the request's fields, listing each field that failed.
+pub fn json_field_errors(
+    status: StatusCode,
+    code: &str,
+    message: impl Into<String>,
+    errors: Vec<FieldError>,
+) -> HttpResponse {
+    HttpResponse::build(status).json(ErrorBody {
+        code: code.into(),
+        message: message.into(),
+        errors,
+    })
+}
+
+pub fn error_chain_fmt(
+    e: &impl std::error::Error,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    writeln!(f, "{}\n", e)?;
+    let mut current = e.source();
+    while let Some(cause) = current {
+        writeln!(f, "Caused by:\n\t{}", cause)?;
+        current = cause.source();
+    }
+    Ok(())
+}
+
+/// Postgres' error code for a query cancelled by `statement_timeout`.
+const QUERY_CANCELED: &str = "57014";
+
+/// Whether `e` is Postgres aborting a query that ran past the configured
+/// `statement_timeout`.
+pub fn is_statement_timeout(e: &(dyn std::error::Error + 'static)) -> bool {
+    match e.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Database(e)) => e.code().as_deref() == Some(QUERY_CANCELED),
+        _ => false,
+    }
+}
+
+/// The response to an error we did not expect: a 503 when the database took
+/// too long to answer, since retrying later may well succeed, a 500
+/// otherwise.
+pub fn unexpected_error(e: &anyhow::Error) -> HttpResponse {
+    if e.chain().any(is_statement_timeout) {
+        database_timeout()
+    } else {
+        internal_error()
+    }
+}
+
+fn database_timeout() -> HttpResponse {
+    json_error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database_timeout",
+        "The database took too long to answer. Try again later.",
+    )
+}
+
+pub(crate) fn internal_error() -> HttpResponse {
+    json_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "internal_error",
+        "Something went wrong.",
+    )
+}
+
+/// Convert an unexpected error into a 500 (or a 503, see
+/// [`unexpected_error`]) carrying the JSON error envelope, preserving the
+/// original error for logging.
+pub fn e500<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    let any: &dyn std::any::Any = &e;
+    let timed_out = match (
+        any.downcast_ref::<sqlx::Error>(),
+        any.downcast_ref::<anyhow::Error>(),
+    ) {
+        (Some(e), _) => is_statement_timeout(e),
+        (_, Some(e)) => e.chain().any(is_statement_timeout),
+        _ => false,
+    };
+    let response = if timed_out {
+        database_timeout()
+    } else {
+        internal_error()
+    };
+    InternalError::from_response(e, response).into()
+}
+
+/// The response to a request matching no route.
+pub async fn not_found() -> HttpResponse {
+    json_error(
+        StatusCode::NOT_FOUND,
+        "not_found",
+        "The requested resource does not exist.",
+    )
+}
+
+/// Give actix's bare 405s the JSON error envelope, keeping the `Allow` header
+/// listing the methods the route does accept.
+pub async fn method_not_allowed_as_json<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let response = next.call(req).await?;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return Ok(response.map_into_left_body());
+    }
+    let allow = response.headers().get(ALLOW).cloned();
+    let mut json = json_error(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "method_not_allowed",
+        format!(
+            "{} is not allowed on this path.",
+            response.request().method()
+        ),
+    );
+    if let Some(allow) = allow {
+        json.headers_mut().insert(ALLOW, allow);
+    }
+    let (req, _) = response.into_parts();
+    Ok(ServiceResponse::new(req, json).map_into_right_body())
+}
+
+/// Answer domain-validation failures with a 400 like before they became
+/// 422s, for clients that only know the former; shared as app data.
+pub struct LegacyValidationStatus(pub bool);
+
+/// Turn 422s back into 400s when `LegacyValidationStatus` is on.
+pub async fn downgrade_validation_errors<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let legacy = req
+        .app_data::<web::Data<LegacyValidationStatus>>()
+        .is_some_and(|l| l.0);
+    let mut response = next.call(req).await?;
+    if legacy && response.status() == StatusCode::UNPROCESSABLE_ENTITY {
+        *response.response_mut().status_mut() = StatusCode::BAD_REQUEST;
+    }
+    Ok(response)
+}
+
+pub fn see_other(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish()
+}
+
+/// Report `web::Form` deserialization failures with the JSON error envelope,
+/// naming the offending field (e.g. "missing field `email`").
+pub fn form_error_handler(err: UrlencodedError, _req: &HttpRequest) -> actix_web::Error {
+    let status = err.status_code();
+    let message = err.to_string();
+    let response = json_error(status, "invalid_body", message);
+    InternalError::from_response(err, response).into()
+}
+
+/// Report `web::Json` deserialization failures with the JSON error envelope,
+/// naming the offending field (e.g. "missing field `title`").
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let status = err.status_code();
+    let message = err.to_string();
+    let response = json_error(status, "invalid_body", message);
+    InternalError::from_response(err, response).into()
+}
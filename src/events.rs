@@ -0,0 +1,119 @@
+//! Reporting subscriber lifecycle events, e.g. to an analytics service, without
+//! the core flow depending on where they go or whether they get there.
+use std::future::Future;
+use std::pin::Pin;
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use uuid::Uuid;
+
+pub type EventFuture<'a> = Pin<Box<dyn Future<Output = Result<(), anyhow::Error>> + Send + 'a>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ConfirmedEvent {
+    pub subscriber_id: Uuid,
+    pub confirmed_at: DateTime<Utc>,
+}
+
+/// Where subscriber events are sent. Callers log a failure and carry on.
+pub trait EventSink: Send + Sync {
+    fn subscriber_confirmed<'a>(&'a self, event: &'a ConfirmedEvent) -> EventFuture<'a>;
+}
+
+/// Drops every event.
+pub struct NoopEventSink;
+
+impl EventSink for NoopEventSink {
+    fn subscriber_confirmed<'a>(&'a self, _event: &'a ConfirmedEvent) -> EventFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// POSTs each event as JSON to `url`.
+pub struct HttpEventSink {
+    http_client: Client,
+    url: String,
+}
+
+impl HttpEventSink {
+    pub fn new(url: String, timeout: std::time::Duration) -> Self {
+        Self {
+            http_client: Client::builder().timeout(timeout).build().unwrap(),
+            url,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct EventBody<'a> {
+    event: &'a str,
+    #[serde(flatten)]
+    payload: &'a ConfirmedEvent,
+}
+
+impl EventSink for HttpEventSink {
+    fn subscriber_confirmed<'a>(&'a self, event: &'a ConfirmedEvent) -> EventFuture<'a> {
+        Box::pin(async move {
+            self.http_client
+                .post(&self.url)
+                .json(&EventBody {
+                    event: "subscriber_confirmed",
+                    payload: event,
+                })
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use claim::{assert_err, assert_ok};
+    use uuid::Uuid;
+    use wiremock::matchers::{body_partial_json, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::{ConfirmedEvent, EventSink, HttpEventSink};
+
+    fn sink(server: &MockServer) -> HttpEventSink {
+        HttpEventSink::new(server.uri(), std::time::Duration::from_millis(200))
+    }
+
+    #[tokio::test]
+    async fn the_http_sink_posts_the_event_as_json() {
+        let server = MockServer::start().await;
+        let event = ConfirmedEvent {
+            subscriber_id: Uuid::new_v4(),
+            confirmed_at: Utc::now(),
+        };
+        Mock::given(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "event": "subscriber_confirmed",
+                "subscriber_id": event.subscriber_id,
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_ok!(sink(&server).subscriber_confirmed(&event).await);
+    }
+
+    #[tokio::test]
+    async fn the_http_sink_fails_if_the_endpoint_does() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+        let event = ConfirmedEvent {
+            subscriber_id: Uuid::new_v4(),
+            confirmed_at: Utc::now(),
+        };
+
+        assert_err!(sink(&server).subscriber_confirmed(&event).await);
+    }
+}
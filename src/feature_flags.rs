@@ -0,0 +1,146 @@
+//! Switches stored in the database, letting admins change behaviour without
+//! a deploy. Every instance keeps a copy of the flags in memory and refreshes
+//! it periodically, so checking a flag never hits the database.
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use tokio_util::sync::CancellationToken;
+
+/// While enabled, `POST /subscriptions` turns new subscribers away.
+pub const SUBSCRIPTIONS_PAUSED: &str = "subscriptions_paused";
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The in-memory copy of the flags, shared as app data.
+#[derive(Default)]
+pub struct FeatureFlags {
+    flags: RwLock<HashMap<String, bool>>,
+}
+
+impl FeatureFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Unknown flags are disabled.
+    pub fn is_enabled(&self, flag: &str) -> bool {
+        self.flags
+            .read()
+            .unwrap()
+            .get(flag)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// The names of the enabled flags, sorted.
+    pub fn enabled(&self) -> Vec<String> {
+        let mut enabled: Vec<String> = self
+            .flags
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, enabled)| **enabled)
+            .map(|(name, _)| name.clone())
+            .collect();
+        enabled.sort();
+        enabled
+    }
+
+    /// Replace the cached flags with the ones currently in the database.
+    #[tracing::instrument(name = "Refreshing feature flags", skip(self, pool))]
+    pub async fn refresh(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let flags = list_flags(pool)
+            .await?
+            .into_iter()
+            .map(|flag| (flag.name, flag.enabled))
+            .collect();
+        *self.flags.write().unwrap() = flags;
+        Ok(())
+    }
+}
+
+/// A flag name is a short snake_case identifier, e.g. `subscriptions_paused`.
+pub fn is_valid_flag_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[tracing::instrument(name = "Listing feature flags", skip(pool))]
+pub async fn list_flags(pool: &PgPool) -> Result<Vec<FeatureFlag>, sqlx::Error> {
+    sqlx::query_as!(
+        FeatureFlag,
+        "SELECT name, enabled, updated_at FROM feature_flags ORDER BY name"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Create or update a flag. Other instances pick the change up on their next
+/// refresh.
+#[tracing::instrument(name = "Setting a feature flag", skip(executor))]
+pub async fn set_flag(
+    executor: impl PgExecutor<'_>,
+    name: &str,
+    enabled: bool,
+) -> Result<FeatureFlag, sqlx::Error> {
+    sqlx::query_as!(
+        FeatureFlag,
+        r#"
+        INSERT INTO feature_flags (name, enabled, updated_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (name) DO UPDATE
+        SET enabled = EXCLUDED.enabled, updated_at = EXCLUDED.updated_at
+        RETURNING name, enabled, updated_at
+        "#,
+        name,
+        enabled
+    )
+    .fetch_one(executor)
+    .await
+}
+
+pub async fn run_refresh_until_stopped(
+    pool: PgPool,
+    flags: Arc<FeatureFlags>,
+    interval: std::time::Duration,
+    token: CancellationToken,
+) {
+    while !token.is_cancelled() {
+        if let Err(e) = flags.refresh(&pool).await {
+            tracing::warn!(error = %e, "Failed to refresh feature flags");
+        }
+        tokio::select! {
+            _ = token.cancelled() => {}
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_flag_name, FeatureFlags};
+
+    #[test]
+    fn unknown_flags_are_disabled() {
+        assert!(!FeatureFlags::new().is_enabled("anything"));
+    }
+
+    #[test]
+    fn flag_names_are_snake_case_identifiers() {
+        assert!(is_valid_flag_name("subscriptions_paused"));
+        assert!(is_valid_flag_name("flow_v2"));
+        assert!(!is_valid_flag_name(""));
+        assert!(!is_valid_flag_name("Subscriptions Paused"));
+        assert!(!is_valid_flag_name(&"a".repeat(65)));
+    }
+}
@@ -0,0 +1,126 @@
+//! Form bodies that decode cleanly: `web::Form` silently mangles a `%zz` or
+//! escapes decoding to bytes that are not UTF-8 instead of rejecting them.
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use serde::de::DeserializeOwned;
+
+use crate::error::json_error;
+
+/// A `web::Form` whose body is checked first: one that does not decode is
+/// rejected with a 400 `malformed_body`. Other failures are left to
+/// `web::Form` and the `FormConfig`.
+pub struct StrictForm<T>(pub T);
+
+impl<T> std::ops::Deref for StrictForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for StrictForm<T> {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        // Anything else fails `web::Form`'s own content type check.
+        if req.content_type() != "application/x-www-form-urlencoded" {
+            let form = web::Form::<T>::from_request(&req, payload);
+            return Box::pin(async move { Ok(Self(form.await?.into_inner())) });
+        }
+        let body = web::Bytes::from_request(&req, payload);
+        Box::pin(async move {
+            let body = body.await?;
+            if let Err(message) = check_form_encoding(&body) {
+                let response = json_error(StatusCode::BAD_REQUEST, "malformed_body", &message);
+                return Err(InternalError::from_response(message, response).into());
+            }
+            let form = web::Form::<T>::from_request(&req, &mut Payload::from(body)).await?;
+            Ok(Self(form.into_inner()))
+        })
+    }
+}
+
+fn check_form_encoding(body: &[u8]) -> Result<(), String> {
+    let hex = |b: Option<&u8>| b.and_then(|b| (*b as char).to_digit(16));
+    let mut decoded = Vec::with_capacity(body.len());
+    let mut i = 0;
+    while i < body.len() {
+        match body[i] {
+            b'%' => match (hex(body.get(i + 1)), hex(body.get(i + 2))) {
+                (Some(high), Some(low)) => {
+                    decoded.push((high * 16 + low) as u8);
+                    i += 3;
+                    continue;
+                }
+                _ => {
+                    return Err(format!(
+                        "The form body has an invalid percent-encoding at byte {}: \
+                        `%` must be followed by two hexadecimal digits.",
+                        i
+                    ))
+                }
+            },
+            b'+' => decoded.push(b' '),
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    std::str::from_utf8(&decoded)
+        .map(|_| ())
+        .map_err(|_| "The form body is not valid UTF-8 once decoded.".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test as actix_test, web, App, HttpResponse};
+
+    use super::StrictForm;
+    use crate::error::ErrorBody;
+
+    #[derive(serde::Deserialize)]
+    struct Form {
+        name: String,
+    }
+
+    async fn post(body: &'static str) -> actix_web::dev::ServiceResponse {
+        let app =
+            actix_test::init_service(App::new().route(
+                "/form",
+                web::post().to(|form: StrictForm<Form>| async move {
+                    HttpResponse::Ok().body(form.0.name)
+                }),
+            ))
+            .await;
+        let request = actix_test::TestRequest::post()
+            .uri("/form")
+            .insert_header(("Content-Type", "application/x-www-form-urlencoded"))
+            .set_payload(body)
+            .to_request();
+        actix_test::call_service(&app, request).await
+    }
+
+    #[actix_web::test]
+    async fn a_well_encoded_form_reaches_the_handler() {
+        let response = post("name=le%20guin").await;
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(actix_test::read_body(response).await, "le guin");
+    }
+
+    #[actix_web::test]
+    async fn a_form_that_does_not_decode_is_rejected() {
+        let response = post("name=%zz").await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        let error: ErrorBody = actix_test::read_body_json(response).await;
+        assert_eq!(error.code, "malformed_body");
+    }
+}
@@ -1,6 +1,40 @@
+pub mod access_log;
+pub mod audit;
+pub mod authentication;
+pub mod client_ip;
+pub mod clock;
+pub mod concurrency_limit;
 pub mod configuration;
+pub mod cors;
+pub mod db_pool;
+pub mod deliveries;
+pub mod dns;
 pub mod domain;
 pub mod email_client;
+pub mod email_templates;
+pub mod email_worker;
+pub mod error;
+pub mod events;
+pub mod feature_flags;
+pub mod form;
+pub mod maintenance;
+pub mod metrics;
+pub mod migrations;
+pub mod newsletter_content;
+pub mod newsletter_issues;
+pub mod pii;
+pub mod rate_limit;
+pub mod retry;
 pub mod routes;
+pub mod sender_domain;
+pub mod session_state;
+pub mod smtp;
 pub mod startup;
+pub mod suppressions;
+pub mod task_supervisor;
 pub mod telemetry;
+pub mod test_databases;
+pub mod token_cache;
+pub mod token_sweeper;
+pub mod topics;
+pub mod webhooks;
@@ -2,12 +2,32 @@ use zero2prod::configuration::get_configuration;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
+const USAGE: &str = "Usage: zero2prod [--migrations-path <directory>]";
+
+fn parse_migrations_path(mut args: impl Iterator<Item = String>) -> Result<Option<String>, String> {
+    match (args.next().as_deref(), args.next(), args.next()) {
+        (None, _, _) => Ok(None),
+        (Some("--migrations-path"), Some(path), None) => Ok(Some(path)),
+        _ => Err(USAGE.into()),
+    }
+}
+
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
+    let migrations_path = match parse_migrations_path(std::env::args().skip(1)) {
+        Ok(migrations_path) => migrations_path,
+        Err(usage) => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
 
-    let config = get_configuration().expect("Failed to read config");
+    let mut config = get_configuration().expect("Failed to read config");
+    if migrations_path.is_some() {
+        config.database.migrations_path = migrations_path;
+    }
     let server = Application::build(&config).await?;
     server.run_until_stopped().await?;
 
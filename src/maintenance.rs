@@ -0,0 +1,37 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web;
+
+use crate::error::json_error;
+
+/// Whether the application is in maintenance mode, shared as app data.
+///
+/// While enabled, write endpoints wrapped in [`reject_during_maintenance`]
+/// answer with a 503; read-only endpoints keep working.
+#[derive(Clone, Copy)]
+pub struct MaintenanceMode(pub bool);
+
+pub async fn reject_during_maintenance<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let enabled = req
+        .app_data::<web::Data<MaintenanceMode>>()
+        .map(|m| m.0)
+        .unwrap_or(false);
+
+    if enabled {
+        let response = json_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "maintenance",
+            "The service is undergoing maintenance, please try again later.",
+        );
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
@@ -0,0 +1,198 @@
+//! Prometheus metrics, exposed on `/metrics`.
+use prometheus::core::Metric;
+use prometheus::{
+    Counter, Encoder, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+
+/// The percentiles of connection acquisition latency reported alongside its
+/// histogram.
+const ACQUIRE_QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+
+/// Every metric we export, registered in a registry of its own so that
+/// several applications can live in the same process (e.g. in tests).
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    /// How long the email provider took to answer, by provider.
+    pub email_send_duration: HistogramVec,
+    /// Time the email worker spent pausing between batches.
+    pub email_batch_pause_duration: Counter,
+    /// Subscribers stored, pending confirmation or not: the top of the
+    /// signup funnel.
+    pub subscriptions_created: IntCounter,
+    /// Subscribers confirmed, the bottom of the funnel.
+    pub confirmations_completed: IntCounter,
+    /// Requests being handled right now, across every worker.
+    pub http_requests_in_flight: IntGauge,
+    /// How long handlers waited for a connection from the pool.
+    pub db_pool_acquire_duration: Histogram,
+    /// Percentiles of `db_pool_acquire_duration`, estimated on each render.
+    db_pool_acquire_duration_quantiles: GaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let email_send_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "email_send_duration_seconds",
+                "Time spent sending an email through the provider's API.",
+            ),
+            &["provider"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(email_send_duration.clone()))
+            .unwrap();
+        let email_batch_pause_duration = Counter::with_opts(Opts::new(
+            "email_queue_batch_pause_seconds_total",
+            "Time the email worker spent pausing between batches of sends.",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(email_batch_pause_duration.clone()))
+            .unwrap();
+        let subscriptions_created = IntCounter::with_opts(Opts::new(
+            "subscriptions_created_total",
+            "New subscribers stored, whether pending confirmation or not.",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(subscriptions_created.clone()))
+            .unwrap();
+        let confirmations_completed = IntCounter::with_opts(Opts::new(
+            "confirmations_completed_total",
+            "Subscribers who confirmed their subscription.",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(confirmations_completed.clone()))
+            .unwrap();
+        let http_requests_in_flight = IntGauge::with_opts(Opts::new(
+            "http_requests_in_flight",
+            "Requests currently being handled.",
+        ))
+        .unwrap();
+        registry
+            .register(Box::new(http_requests_in_flight.clone()))
+            .unwrap();
+        let db_pool_acquire_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "db_pool_acquire_duration_seconds",
+                "Time spent acquiring a connection from the database pool.",
+            )
+            .buckets(vec![
+                0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+            ]),
+        )
+        .unwrap();
+        registry
+            .register(Box::new(db_pool_acquire_duration.clone()))
+            .unwrap();
+        let db_pool_acquire_duration_quantiles = GaugeVec::new(
+            Opts::new(
+                "db_pool_acquire_duration_seconds_quantile",
+                "Percentiles of the time spent acquiring a connection, \
+                estimated from the histogram's buckets.",
+            ),
+            &["quantile"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(db_pool_acquire_duration_quantiles.clone()))
+            .unwrap();
+        Self {
+            registry,
+            email_send_duration,
+            email_batch_pause_duration,
+            subscriptions_created,
+            confirmations_completed,
+            http_requests_in_flight,
+            db_pool_acquire_duration,
+            db_pool_acquire_duration_quantiles,
+        }
+    }
+
+    /// The metrics in Prometheus' text exposition format.
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        for q in ACQUIRE_QUANTILES {
+            self.db_pool_acquire_duration_quantiles
+                .with_label_values(&[&q.to_string()])
+                .set(bucket_quantile(&self.db_pool_acquire_duration, q));
+        }
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer).expect("Prometheus metrics are valid UTF-8"))
+    }
+}
+
+/// The `q` quantile of the observations in `histogram`, interpolated within
+/// the bucket it falls in as Prometheus' `histogram_quantile` does; `NaN`
+/// before any observation.
+fn bucket_quantile(histogram: &Histogram, q: f64) -> f64 {
+    let metric = histogram.metric();
+    let histogram = metric.get_histogram();
+    let count = histogram.get_sample_count();
+    if count == 0 {
+        return f64::NAN;
+    }
+    let rank = q * count as f64;
+    let (mut lower_bound, mut below) = (0.0, 0);
+    for bucket in histogram.get_bucket() {
+        let cumulative = bucket.get_cumulative_count();
+        if cumulative as f64 >= rank {
+            return lower_bound
+                + (bucket.get_upper_bound() - lower_bound) * (rank - below as f64)
+                    / (cumulative - below) as f64;
+        }
+        lower_bound = bucket.get_upper_bound();
+        below = cumulative;
+    }
+    // Past the last bucket, its bound is all we know.
+    lower_bound
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{Histogram, HistogramOpts};
+
+    use super::bucket_quantile;
+
+    fn histogram(observations: &[f64]) -> Histogram {
+        let histogram =
+            Histogram::with_opts(HistogramOpts::new("test", "test").buckets(vec![1.0, 2.0, 4.0]))
+                .unwrap();
+        for observation in observations {
+            histogram.observe(*observation);
+        }
+        histogram
+    }
+
+    #[test]
+    fn quantiles_are_interpolated_within_their_bucket() {
+        let histogram = histogram(&[0.5, 1.5, 1.5, 3.0]);
+
+        assert_eq!(bucket_quantile(&histogram, 0.25), 1.0);
+        assert_eq!(bucket_quantile(&histogram, 0.5), 1.5);
+        assert_eq!(bucket_quantile(&histogram, 0.875), 3.0);
+    }
+
+    #[test]
+    fn quantiles_past_the_last_bucket_are_capped_at_its_bound() {
+        let histogram = histogram(&[10.0]);
+
+        assert_eq!(bucket_quantile(&histogram, 0.99), 4.0);
+    }
+
+    #[test]
+    fn there_are_no_quantiles_without_observations() {
+        assert!(bucket_quantile(&histogram(&[]), 0.5).is_nan());
+    }
+}
@@ -0,0 +1,106 @@
+//! The schema migrations embedded in the binary, and how far the database
+//! is behind them; or, for hotfixes, plain SQL files read from a directory at
+//! runtime.
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use sqlx::migrate::Migrator;
+use sqlx::{Executor, PgPool};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Postgres' error code for a missing table: nothing has been migrated yet.
+const UNDEFINED_TABLE: &str = "42P01";
+
+/// The versions of the embedded migrations the database has not applied.
+#[tracing::instrument(name = "Checking for pending migrations", skip(pool))]
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<i64>, sqlx::Error> {
+    let applied: Vec<i64> =
+        match sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+        {
+            Ok(applied) => applied,
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(UNDEFINED_TABLE) => {
+                Vec::new()
+            }
+            Err(e) => return Err(e),
+        };
+    Ok(MIGRATOR
+        .iter()
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect())
+}
+
+/// The `.sql` files of `directory`, in lexical order, leaving out the
+/// `.down.sql` files reverting them.
+fn sql_files(directory: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        let is_down = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.ends_with(".down.sql"));
+        if path.is_file() && path.extension().is_some_and(|e| e == "sql") && !is_down {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Apply the `.sql` files of `directory` the database has not seen yet, in
+/// lexical order, each in a transaction of its own. Applied files are tracked
+/// by name in `_runtime_migrations`, apart from the embedded migrations.
+///
+/// Returns the names of the files applied.
+#[tracing::instrument(name = "Applying runtime migrations", skip(pool))]
+pub async fn run_directory_migrations(
+    pool: &PgPool,
+    directory: &Path,
+) -> Result<Vec<String>, anyhow::Error> {
+    let files = sql_files(directory)
+        .with_context(|| format!("Failed to list the migrations in {}", directory.display()))?;
+    pool.execute(
+        "CREATE TABLE IF NOT EXISTS _runtime_migrations (
+            name TEXT PRIMARY KEY,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )",
+    )
+    .await
+    .context("Failed to create the runtime migrations table")?;
+
+    let mut applied = Vec::new();
+    for file in files {
+        let name = file
+            .file_name()
+            .and_then(|n| n.to_str())
+            .with_context(|| format!("{} is not a valid UTF-8 file name", file.display()))?
+            .to_owned();
+        let sql = std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let mut transaction = pool.begin().await?;
+        // Claiming the name first makes a concurrent instance wait for us,
+        // then skip the file.
+        let claimed = sqlx::query(
+            "INSERT INTO _runtime_migrations (name) VALUES ($1) ON CONFLICT DO NOTHING",
+        )
+        .bind(&name)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+        if claimed == 0 {
+            continue;
+        }
+        (&mut transaction)
+            .execute(sql.as_str())
+            .await
+            .with_context(|| format!("Failed to apply {}", name))?;
+        transaction.commit().await?;
+        tracing::info!(name, "Applied a runtime migration");
+        applied.push(name);
+    }
+    Ok(applied)
+}
@@ -0,0 +1,143 @@
+//! Checking that the text body of an issue keeps up with its HTML body: an
+//! admin editing the HTML can easily forget the text version, which is what
+//! plain-text mail clients show.
+
+/// What publishing does about a text body that looks out of step with the
+/// HTML one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextBodyCheckMode {
+    Disabled,
+    #[default]
+    Warn,
+    Reject,
+}
+
+/// Flag a text body that is empty, or shorter than `min_ratio` times the
+/// text of the HTML body once its markup is stripped.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+pub struct TextBodyCheck {
+    pub mode: TextBodyCheckMode,
+    pub min_ratio: f64,
+}
+
+impl Default for TextBodyCheck {
+    fn default() -> Self {
+        Self {
+            mode: TextBodyCheckMode::Warn,
+            min_ratio: 0.2,
+        }
+    }
+}
+
+impl TextBodyCheck {
+    /// What looks wrong with `text` next to `html`, if anything.
+    pub fn inspect(&self, html: &str, text: &str) -> Option<String> {
+        if self.mode == TextBodyCheckMode::Disabled {
+            return None;
+        }
+        let text_length = visible_length(text);
+        let html_length = visible_length(&strip_tags(html));
+        if text_length == 0 && html_length > 0 {
+            return Some("The text body is empty.".into());
+        }
+        if (text_length as f64) < html_length as f64 * self.min_ratio {
+            return Some(format!(
+                "The text body is much shorter than the HTML body \
+                ({} characters against {}): it may be out of date.",
+                text_length, html_length
+            ));
+        }
+        None
+    }
+}
+
+/// The text of `html` without its markup, the contents of `<script>` and
+/// `<style>` elements included. Entities are kept as written: they only need
+/// counting, not rendering.
+pub fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        // Tags separate words: `a<br>b` reads as two.
+        text.push(' ');
+        let tag = &rest[start..];
+        let Some(end) = tag.find('>') else {
+            return text;
+        };
+        let name = tag[1..end]
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        rest = &tag[end + 1..];
+        if name == "script" || name == "style" {
+            let closing = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&closing) {
+                Some(close) => &rest[close..],
+                None => "",
+            };
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// How many characters of `text` a reader sees, counting each run of
+/// whitespace as a single space.
+fn visible_length(text: &str) -> usize {
+    text.split_whitespace()
+        .map(|word| word.chars().count() + 1)
+        .sum::<usize>()
+        .saturating_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{strip_tags, TextBodyCheck, TextBodyCheckMode};
+
+    const RICH_HTML: &str = r#"<html><head><style>p { color: red; }</style></head>
+        <body><h1>Issue #12</h1><p>This week we look back at the <b>release</b>
+        and what comes next for the <a href="https://example.com">project</a>.</p>
+        <p>Thanks for reading!</p></body></html>"#;
+
+    #[test]
+    fn a_near_empty_text_body_against_rich_html_is_flagged() {
+        let check = TextBodyCheck::default();
+
+        assert!(check.inspect(RICH_HTML, "").is_some());
+        assert!(check.inspect(RICH_HTML, " \n ").is_some());
+        assert!(check.inspect(RICH_HTML, "Issue #12").is_some());
+    }
+
+    #[test]
+    fn a_text_body_matching_the_html_passes() {
+        let check = TextBodyCheck::default();
+        let text = "Issue #12\n\nThis week we look back at the release and what \
+            comes next for the project.\n\nThanks for reading!";
+
+        assert_eq!(check.inspect(RICH_HTML, text), None);
+        assert_eq!(check.inspect("", ""), None);
+    }
+
+    #[test]
+    fn nothing_is_flagged_when_disabled() {
+        let check = TextBodyCheck {
+            mode: TextBodyCheckMode::Disabled,
+            ..TextBodyCheck::default()
+        };
+
+        assert_eq!(check.inspect(RICH_HTML, ""), None);
+    }
+
+    #[test]
+    fn markup_scripts_and_styles_are_stripped() {
+        let html = "<p>Hello<br/>world</p><script>alert('<b>')</script><STYLE>b{}</STYLE>!";
+
+        assert_eq!(
+            strip_tags(html).split_whitespace().collect::<Vec<_>>(),
+            ["Hello", "world", "!"]
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! The newsletter issues published so far.
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::deliveries::DeliveryCounts;
+
+/// An issue along with how its deliveries went.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct IssueSummary {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub published_by: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub counts: DeliveryCounts,
+}
+
+/// An issue about to be published.
+pub struct NewIssue<'a> {
+    pub title: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    /// Sent from the configured sender when `None`.
+    pub sender_email: Option<&'a str>,
+    /// Sent to every confirmed subscriber when `None`.
+    pub topic: Option<&'a str>,
+}
+
+/// Store the issue's content once; its deliveries refer to it by id.
+#[tracing::instrument(name = "Recording a newsletter issue", skip(executor, issue))]
+pub async fn record_issue(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+    issue: &NewIssue<'_>,
+    published_by: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, html_content, text_content, sender_email, topic,
+            published_by
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        newsletter_issue_id,
+        issue.title,
+        issue.html_content,
+        issue.text_content,
+        issue.sender_email,
+        issue.topic,
+        published_by
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Queue a delivery of the issue to every confirmed subscriber (of `topic`,
+/// when given), minus any address on the suppression list, and record them
+/// as pending. Returns the number of deliveries queued.
+#[tracing::instrument(name = "Queueing the deliveries of an issue", skip(transaction))]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    topic: Option<&str>,
+) -> Result<u64, sqlx::Error> {
+    let queued = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id)
+        SELECT $1, id
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        AND NOT EXISTS (
+            SELECT 1 FROM suppressions WHERE suppressions.email = subscriptions.email
+        )
+        AND (
+            $2::text IS NULL
+            OR EXISTS (
+                SELECT 1 FROM subscriber_topics
+                WHERE subscriber_topics.subscriber_id = subscriptions.id
+                AND subscriber_topics.topic = $2
+            )
+        )
+        "#,
+        newsletter_issue_id,
+        topic,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_email, status)
+        SELECT $1, s.email, 'pending'
+        FROM issue_delivery_queue q
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        WHERE q.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(queued)
+}
+
+/// The most recent issues first.
+#[tracing::instrument(name = "Listing newsletter issues", skip(pool))]
+pub async fn list_issues(
+    pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<IssueSummary>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_by,
+            i.created_at,
+            COUNT(d.status) FILTER (WHERE d.status = 'pending') AS "pending!",
+            COUNT(d.status) FILTER (WHERE d.status = 'delivered') AS "delivered!",
+            COUNT(d.status) FILTER (WHERE d.status = 'failed') AS "failed!"
+        FROM newsletter_issues i
+        LEFT JOIN newsletter_deliveries d
+            ON d.newsletter_issue_id = i.newsletter_issue_id
+        GROUP BY i.newsletter_issue_id
+        ORDER BY i.created_at DESC, i.newsletter_issue_id
+        LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| IssueSummary {
+            issue_id: r.newsletter_issue_id,
+            title: r.title,
+            published_by: r.published_by,
+            created_at: r.created_at,
+            counts: DeliveryCounts {
+                pending: r.pending,
+                delivered: r.delivered,
+                failed: r.failed,
+            },
+        })
+        .collect())
+}
@@ -0,0 +1,149 @@
+//! Keeping subscribers' personal data out of the logs for privacy-sensitive
+//! deployments.
+use sha2::{Digest, Sha256};
+
+/// How email addresses and names are written into spans and log lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PiiLogging {
+    /// As they are.
+    #[default]
+    Plain,
+    /// Only the domain of email addresses; names are dropped.
+    Redacted,
+    /// A truncated SHA-256, enough to correlate the lines about one address.
+    Hashed,
+}
+
+const REDACTED: &str = "[redacted]";
+
+impl PiiLogging {
+    pub fn email(&self, email: &str) -> String {
+        match self {
+            Self::Plain => email.to_owned(),
+            Self::Redacted => match email.rsplit_once('@') {
+                Some((_, domain)) => format!("***@{}", domain),
+                None => REDACTED.to_owned(),
+            },
+            Self::Hashed => hash(email),
+        }
+    }
+
+    pub fn name(&self, name: &str) -> String {
+        match self {
+            Self::Plain => name.to_owned(),
+            Self::Redacted => REDACTED.to_owned(),
+            Self::Hashed => hash(name),
+        }
+    }
+}
+
+fn hash(value: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(value.as_bytes()));
+    format!("sha256:{}", &digest[..16])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{test as actix_test, web, App};
+    use chrono::Duration;
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::PiiLogging;
+    use crate::clock::SystemClock;
+    use crate::domain::NamePolicy;
+    use crate::feature_flags::FeatureFlags;
+    use crate::metrics::Metrics;
+    use crate::routes::{
+        subscribe, ConfirmationTokenTtl, EmailDomainCheck, SubscriberQuota, TokenGenerationAttempts,
+    };
+    use crate::startup::ApplicationBaseUrl;
+    use crate::telemetry::{get_subscriber, CapturedLogs};
+    use crate::token_cache::TokenCache;
+
+    #[test]
+    fn redaction_keeps_only_the_email_domain() {
+        assert_eq!(
+            PiiLogging::Redacted.email("ursula@example.com"),
+            "***@example.com"
+        );
+        assert_eq!(PiiLogging::Redacted.email("not-an-email"), "[redacted]");
+        assert_eq!(PiiLogging::Redacted.name("Ursula"), "[redacted]");
+    }
+
+    #[test]
+    fn hashing_is_stable_and_hides_the_value() {
+        let hashed = PiiLogging::Hashed.email("ursula@example.com");
+        assert_eq!(hashed, PiiLogging::Hashed.email("ursula@example.com"));
+        assert_ne!(hashed, PiiLogging::Hashed.email("le.guin@example.com"));
+        assert!(!hashed.contains("ursula"));
+    }
+
+    #[test]
+    fn plain_logging_leaves_values_untouched() {
+        assert_eq!(
+            PiiLogging::Plain.email("ursula@example.com"),
+            "ursula@example.com"
+        );
+        assert_eq!(PiiLogging::Plain.name("Ursula"), "Ursula");
+    }
+
+    /// Logs of a subscription that fails validation before touching the
+    /// database, so that no database is needed.
+    async fn subscription_logs(pii_logging: PiiLogging) -> String {
+        let captured = CapturedLogs::default();
+        let subscriber = get_subscriber("test".into(), "info".into(), captured.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(ApplicationBaseUrl(
+                    "http://localhost".into(),
+                )))
+                .app_data(web::Data::new(TokenGenerationAttempts(1)))
+                .app_data(web::Data::new(SubscriberQuota(None)))
+                .app_data(web::Data::new(FeatureFlags::new()))
+                .app_data(web::Data::new(pii_logging))
+                .app_data(web::Data::new(Metrics::new()))
+                .app_data(web::Data::new(EmailDomainCheck::default()))
+                .app_data(web::Data::new(NamePolicy::default()))
+                .app_data(web::Data::new(TokenCache::new(
+                    0,
+                    Duration::hours(1),
+                    Arc::new(SystemClock),
+                )))
+                .app_data(web::Data::new(ConfirmationTokenTtl(Duration::hours(1))))
+                .route("/subscriptions", web::post().to(subscribe)),
+        )
+        .await;
+
+        let request = actix_test::TestRequest::post()
+            .uri("/subscriptions")
+            .set_form([("name", " "), ("email", "ursula@example.com")])
+            .to_request();
+        let response = actix_test::call_service(&app, request).await;
+        assert_eq!(response.status().as_u16(), 422);
+
+        captured.contents()
+    }
+
+    #[actix_web::test]
+    async fn redacted_subscription_logs_do_not_contain_the_email() {
+        let logs = subscription_logs(PiiLogging::Redacted).await;
+
+        assert!(logs.contains("***@example.com"));
+        assert!(!logs.contains("ursula@example.com"));
+    }
+
+    #[actix_web::test]
+    async fn plain_subscription_logs_contain_the_email() {
+        let logs = subscription_logs(PiiLogging::Plain).await;
+
+        assert!(logs.contains("ursula@example.com"));
+    }
+}
@@ -0,0 +1,220 @@
+//! Per-route request limits, counted in fixed windows per key (usually the
+//! client IP).
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpResponse};
+use sha2::{Digest, Sha256};
+
+use crate::client_ip::ClientIp;
+use crate::error::json_error;
+
+/// How many requests a single key may issue per window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub window_seconds: u64,
+}
+
+impl RateLimit {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_seconds)
+    }
+}
+
+/// Above this many tracked keys, expired windows are dropped on the next
+/// check so that the map cannot grow without bound.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// The most keys tracked at once. Past it, even after pruning, new keys are
+/// refused until windows expire: a flood of distinct keys must not exhaust
+/// memory.
+const MAX_KEYS: usize = 100_000;
+
+struct Window {
+    started_at: Instant,
+    requests: u32,
+}
+
+pub struct RateLimiter {
+    limit: RateLimit,
+    max_keys: usize,
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            max_keys: MAX_KEYS,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a request for `key`; once the limit is reached, return how long
+    /// the caller has to wait for the window to reset.
+    pub fn check(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let window_length = self.limit.window();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() > PRUNE_THRESHOLD {
+            windows.retain(|_, w| now.duration_since(w.started_at) < window_length);
+        }
+        if windows.len() >= self.max_keys && !windows.contains_key(key) {
+            tracing::warn!("Too many rate-limited keys, refusing a new one");
+            return Err(window_length);
+        }
+        let window = windows.entry(key.to_owned()).or_insert(Window {
+            started_at: now,
+            requests: 0,
+        });
+        if now.duration_since(window.started_at) >= window_length {
+            window.started_at = now;
+            window.requests = 0;
+        }
+        if window.requests >= self.limit.requests {
+            return Err(window_length - now.duration_since(window.started_at));
+        }
+        window.requests += 1;
+        Ok(())
+    }
+}
+
+/// The limiters of the rate-limited routes, shared as app data; a route
+/// without a configured limit is not limited.
+#[derive(Default)]
+pub struct RateLimiters {
+    pub subscribe: Option<RateLimiter>,
+    pub login: Option<RateLimiter>,
+    pub confirmation_resend: Option<RateLimiter>,
+}
+
+/// Limit subscription attempts per client IP.
+pub async fn limit_subscriptions<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let client_ip = ClientIp::extract(req.request()).await?;
+    enforce(|l| l.subscribe.as_ref(), &client_ip.to_string(), req, next).await
+}
+
+#[derive(serde::Deserialize)]
+struct LoginUsername {
+    #[serde(default)]
+    username: String,
+}
+
+/// Limit login attempts per username and client IP, so that guessing one
+/// account's password does not lock everybody else behind the same NAT out.
+pub async fn limit_logins<B: MessageBody>(
+    mut req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let client_ip = ClientIp::extract(req.request()).await?;
+    // Peek at the form and put it back for the handler to extract.
+    let body = req.extract::<web::Bytes>().await?;
+    let username = serde_urlencoded::from_bytes::<LoginUsername>(&body)
+        .map(|form| form.username)
+        .unwrap_or_default();
+    req.set_payload(body.into());
+    // Hashed, so that a key takes the same room however long the username.
+    let key = format!("{:x}|{}", Sha256::digest(username.as_bytes()), client_ip);
+    enforce(|l| l.login.as_ref(), &key, req, next).await
+}
+
+async fn enforce<B: MessageBody>(
+    limiter: impl FnOnce(&RateLimiters) -> Option<&RateLimiter>,
+    key: &str,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let outcome = req
+        .app_data::<web::Data<RateLimiters>>()
+        .and_then(|limiters| limiter(limiters))
+        .map(|limiter| limiter.check(key, Instant::now()));
+
+    if let Some(Err(retry_after)) = outcome {
+        tracing::warn!(key, "Rate limit exceeded");
+        return Ok(req
+            .into_response(rate_limited(retry_after))
+            .map_into_right_body());
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// A 429 telling the client to retry after `retry_after`.
+pub fn rate_limited(retry_after: Duration) -> HttpResponse {
+    // Round up: retrying after a truncated delay would be rejected again.
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let mut response = json_error(
+        StatusCode::TOO_MANY_REQUESTS,
+        "rate_limited",
+        "Too many requests, please try again later.",
+    );
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, seconds.max(1).into());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimit, RateLimiter};
+    use std::time::{Duration, Instant};
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimit {
+            requests: 2,
+            window_seconds: 60,
+        })
+    }
+
+    #[test]
+    fn requests_beyond_the_limit_are_rejected_until_the_window_resets() {
+        let limiter = limiter();
+        let start = Instant::now();
+
+        assert!(limiter.check("a", start).is_ok());
+        assert!(limiter.check("a", start).is_ok());
+        let retry_after = limiter
+            .check("a", start + Duration::from_secs(20))
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(40));
+
+        assert!(limiter.check("a", start + Duration::from_secs(60)).is_ok());
+    }
+
+    #[test]
+    fn new_keys_are_refused_once_the_map_is_full() {
+        let limiter = RateLimiter {
+            max_keys: 2,
+            ..limiter()
+        };
+        let now = Instant::now();
+
+        assert!(limiter.check("a", now).is_ok());
+        assert!(limiter.check("b", now).is_ok());
+        assert!(limiter.check("c", now).is_err());
+        // Known keys are still counted.
+        assert!(limiter.check("a", now).is_ok());
+    }
+
+    #[test]
+    fn keys_are_counted_separately() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert!(limiter.check("a", now).is_ok());
+        assert!(limiter.check("a", now).is_ok());
+        assert!(limiter.check("a", now).is_err());
+        assert!(limiter.check("b", now).is_ok());
+    }
+}
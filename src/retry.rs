@@ -0,0 +1,129 @@
+//! Riding out brief database blips, e.g. Postgres restarting: a read that
+//! fails because its connection went away is worth one more try.
+use std::future::Future;
+use std::time::Duration;
+
+/// How long to wait before the second attempt.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Whether `e` is a connection-level failure that a fresh connection may not
+/// hit, as opposed to a problem with the query itself.
+pub fn is_transient(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) => true,
+        // Class 08 is "connection exception"; 57P01-57P03 are the server
+        // shutting down or not accepting connections yet.
+        sqlx::Error::Database(e) => e.code().is_some_and(|code| {
+            code.starts_with("08") || matches!(code.as_ref(), "57P01" | "57P02" | "57P03")
+        }),
+        _ => false,
+    }
+}
+
+/// The errors returned by our queries: plain [`sqlx::Error`]s, or
+/// [`anyhow::Error`]s for queries that post-process their rows.
+pub trait QueryError: std::fmt::Display {
+    fn as_sqlx_error(&self) -> Option<&sqlx::Error>;
+}
+
+impl QueryError for sqlx::Error {
+    fn as_sqlx_error(&self) -> Option<&sqlx::Error> {
+        Some(self)
+    }
+}
+
+impl QueryError for anyhow::Error {
+    fn as_sqlx_error(&self) -> Option<&sqlx::Error> {
+        self.downcast_ref()
+    }
+}
+
+/// Run a read-only query, running it once more after a short delay if the
+/// first attempt hits a transient error.
+///
+/// Only for reads: a write may have been applied before its connection
+/// dropped, and repeating it is not always harmless.
+pub async fn retry_read<T, E, F, Fut>(mut query: F) -> Result<T, E>
+where
+    E: QueryError,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    match query().await {
+        Err(e) if e.as_sqlx_error().is_some_and(is_transient) => {
+            tracing::warn!(error = %e, "Retrying a read after a transient database error");
+            tokio::time::sleep(RETRY_DELAY).await;
+            query().await
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::retry_read;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn connection_reset() -> sqlx::Error {
+        sqlx::Error::Io(std::io::Error::from(std::io::ErrorKind::ConnectionReset))
+    }
+
+    #[tokio::test]
+    async fn a_transient_error_is_retried_once() {
+        let attempts = AtomicU32::new(0);
+
+        let result = retry_read(|| async {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(connection_reset()),
+                _ => Ok(42),
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn a_second_transient_error_is_returned() {
+        let attempts = AtomicU32::new(0);
+
+        let result: Result<(), _> = retry_read(|| async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(connection_reset())
+        })
+        .await;
+
+        assert!(matches!(result, Err(sqlx::Error::Io(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn transient_errors_wrapped_in_anyhow_are_retried() {
+        let attempts = AtomicU32::new(0);
+
+        let result = retry_read(|| async {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(anyhow::Error::from(connection_reset())),
+                _ => Ok(42),
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_retried() {
+        let attempts = AtomicU32::new(0);
+
+        let result: Result<(), _> = retry_read(|| async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::RowNotFound)
+        })
+        .await;
+
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,17 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use super::Pagination;
+use crate::audit::list_audit_entries;
+use crate::error::e500;
+use crate::retry::retry_read;
+
+pub async fn get_audit_log(
+    pagination: web::Query<Pagination>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let entries = retry_read(|| list_audit_entries(&pool, pagination.limit(), pagination.offset()))
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(entries))
+}
@@ -0,0 +1,46 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::Pagination;
+use crate::deliveries::{
+    count_deliveries, list_deliveries, Delivery, DeliveryCounts, DeliveryStatus,
+};
+use crate::error::e500;
+use crate::retry::retry_read;
+
+#[derive(serde::Deserialize)]
+pub struct DeliveryFilter {
+    status: Option<DeliveryStatus>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct DeliveryReport {
+    /// Counts over every delivery of the issue, whatever the filter.
+    pub counts: DeliveryCounts,
+    pub deliveries: Vec<Delivery>,
+}
+
+pub async fn get_deliveries(
+    issue_id: web::Path<Uuid>,
+    filter: web::Query<DeliveryFilter>,
+    pagination: web::Query<Pagination>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let deliveries = retry_read(|| {
+        list_deliveries(
+            &pool,
+            issue_id,
+            filter.status,
+            pagination.limit(),
+            pagination.offset(),
+        )
+    })
+    .await
+    .map_err(e500)?;
+    let counts = retry_read(|| count_deliveries(&pool, issue_id))
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(DeliveryReport { counts, deliveries }))
+}
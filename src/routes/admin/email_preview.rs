@@ -0,0 +1,40 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+
+use crate::email_templates::{confirmation_email, manage_link, newsletter_email};
+use crate::startup::ApplicationBaseUrl;
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailType {
+    Confirmation,
+    Newsletter,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreviewQuery {
+    #[serde(rename = "type")]
+    email_type: EmailType,
+}
+
+/// Render an email template with sample data, without sending anything.
+pub async fn preview_email(
+    query: web::Query<PreviewQuery>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let email = match query.email_type {
+        EmailType::Confirmation => confirmation_email(&format!(
+            "{}/subscriptions/confirm?subscription_token=SAMPLE_TOKEN",
+            base_url.0
+        )),
+        EmailType::Newsletter => newsletter_email(
+            "Sample issue",
+            "<h1>Sample issue</h1><p>The body of the issue.</p>",
+            "Sample issue\n\nThe body of the issue.",
+        ),
+    };
+    let email = email.with_manage_link(Some(&manage_link(&base_url.0, "SAMPLE_TOKEN")));
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(email.html_body)
+}
@@ -0,0 +1,70 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::audit::{record_audit_entry, FEATURE_FLAG_CHANGED};
+use crate::authentication::UserId;
+use crate::db_pool::begin_transaction;
+use crate::error::{e500, json_error};
+use crate::feature_flags::{is_valid_flag_name, list_flags, set_flag, FeatureFlags};
+use crate::metrics::Metrics;
+
+#[derive(serde::Deserialize)]
+pub struct FlagUpdate {
+    enabled: bool,
+}
+
+pub async fn get_feature_flags(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let flags = list_flags(&pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(flags))
+}
+
+/// Flip a flag; this instance sees the change right away, the others on
+/// their next refresh.
+#[tracing::instrument(
+    name = "Update a feature flag",
+    skip(body, pool, metrics, flags, user_id)
+)]
+pub async fn update_feature_flag(
+    name: web::Path<String>,
+    body: web::Json<FlagUpdate>,
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+    flags: web::Data<FeatureFlags>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = name.into_inner();
+    if !is_valid_flag_name(&name) {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_flag",
+            "Flag names are lowercase letters, digits and underscores.",
+        ));
+    }
+    let mut transaction = begin_transaction(&pool, &metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let flag = set_flag(&mut transaction, &name, body.enabled)
+        .await
+        .context("Failed to store the feature flag.")
+        .map_err(e500)?;
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        FEATURE_FLAG_CHANGED,
+        &name,
+        serde_json::json!({ "enabled": flag.enabled }),
+    )
+    .await
+    .context("Failed to record the flag change in the audit log.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a feature flag.")
+        .map_err(e500)?;
+    flags.refresh(&pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(flag))
+}
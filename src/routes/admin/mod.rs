@@ -0,0 +1,19 @@
+mod audit_log;
+mod deliveries;
+mod email_preview;
+mod feature_flags;
+mod newsletters;
+mod pagination;
+mod password;
+mod subscriptions;
+mod suppressions;
+
+pub use audit_log::*;
+pub use deliveries::*;
+pub use email_preview::*;
+pub use feature_flags::*;
+pub use newsletters::*;
+pub use pagination::*;
+pub use password::*;
+pub use subscriptions::*;
+pub use suppressions::*;
@@ -0,0 +1,17 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use super::Pagination;
+use crate::error::e500;
+use crate::newsletter_issues::list_issues;
+use crate::retry::retry_read;
+
+pub async fn get_newsletter_history(
+    pagination: web::Query<Pagination>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issues = retry_read(|| list_issues(&pool, pagination.limit(), pagination.offset()))
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(issues))
+}
@@ -0,0 +1,118 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::audit::{record_audit_entry, PASSWORD_CHANGED};
+use crate::authentication::{
+    change_password as store_password, get_username, validate_credentials, AuthError, Credentials,
+    PasswordPolicy, UserId,
+};
+use crate::db_pool::begin_transaction;
+use crate::error::{error_chain_fmt, json_error, unexpected_error};
+use crate::metrics::Metrics;
+
+#[derive(serde::Deserialize)]
+pub struct ChangePasswordData {
+    current_password: Secret<String>,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum ChangePasswordError {
+    #[error("The current password is incorrect.")]
+    WrongPassword(#[source] anyhow::Error),
+    #[error("You entered two different new passwords - the field values must match.")]
+    PasswordMismatch,
+    #[error("{0}")]
+    InvalidPassword(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ChangePasswordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ChangePasswordError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ChangePasswordError::WrongPassword(_) => json_error(
+                StatusCode::UNAUTHORIZED,
+                "invalid_credentials",
+                self.to_string(),
+            ),
+            ChangePasswordError::PasswordMismatch => json_error(
+                StatusCode::BAD_REQUEST,
+                "password_mismatch",
+                self.to_string(),
+            ),
+            ChangePasswordError::InvalidPassword(message) => {
+                json_error(StatusCode::BAD_REQUEST, "invalid_password", message)
+            }
+            ChangePasswordError::UnexpectedError(e) => unexpected_error(e),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Change the admin's password",
+    skip(body, pool, metrics, user_id, policy)
+)]
+pub async fn change_password(
+    body: web::Json<ChangePasswordData>,
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+    user_id: web::ReqData<UserId>,
+    policy: web::Data<PasswordPolicy>,
+) -> Result<HttpResponse, ChangePasswordError> {
+    let user_id = user_id.into_inner();
+    let ChangePasswordData {
+        current_password,
+        new_password,
+        new_password_check,
+    } = body.0;
+
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        return Err(ChangePasswordError::PasswordMismatch);
+    }
+    if let Err(violations) = policy.check(&new_password) {
+        let messages: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        return Err(ChangePasswordError::InvalidPassword(messages.join(" ")));
+    }
+
+    let username = get_username(*user_id, &pool).await?;
+    let credentials = Credentials {
+        username,
+        password: current_password,
+    };
+    validate_credentials(credentials, &pool, &metrics)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => ChangePasswordError::WrongPassword(e.into()),
+            AuthError::UnexpectedError(_) => ChangePasswordError::UnexpectedError(e.into()),
+        })?;
+
+    let mut transaction = begin_transaction(&pool, &metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    store_password(&mut transaction, *user_id, new_password).await?;
+    record_audit_entry(
+        &mut transaction,
+        Some(*user_id),
+        PASSWORD_CHANGED,
+        &user_id.to_string(),
+        serde_json::json!({}),
+    )
+    .await
+    .context("Failed to record the password change in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change a password.")?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,444 @@
+use actix_web::http::header::{EntityTag, IfNoneMatch, ETAG};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::Pagination;
+use crate::audit::{record_audit_entry, CONFIRMATIONS_RESENT, SUBSCRIBER_DELETED};
+use crate::authentication::UserId;
+use crate::configuration::EmailQueueSettings;
+use crate::db_pool::{acquire, begin_transaction};
+use crate::domain::SubscriberEmail;
+use crate::error::{e500, json_error};
+use crate::metrics::Metrics;
+use crate::retry::retry_read;
+use crate::routes::{
+    enqueue_confirmation_email, generate_subscription_token, store_new_token, ConfirmationTokenTtl,
+    TokenGenerationAttempts, TokenKind,
+};
+use crate::startup::ApplicationBaseUrl;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct SubscriberRow {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+/// A page of subscribers, tagged with a weak ETag so that polling clients can
+/// skip downloading an unchanged list.
+pub async fn list_subscriptions(
+    pagination: web::Query<Pagination>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscribers = retry_read(|| {
+        get_subscribers_page(&pool, &metrics, pagination.limit(), pagination.offset())
+    })
+    .await
+    .map_err(e500)?;
+    let last_updated_at = retry_read(|| get_last_updated_at(&pool, &metrics))
+        .await
+        .map_err(e500)?;
+    let etag = compute_etag(&subscribers, last_updated_at);
+
+    let unchanged = match if_none_match.map(|h| h.into_inner()) {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+    if unchanged {
+        return Ok(HttpResponse::NotModified()
+            .insert_header((ETAG, etag.to_string()))
+            .finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header((ETAG, etag.to_string()))
+        .json(subscribers))
+}
+
+fn compute_etag(rows: &[SubscriberRow], last_updated_at: Option<DateTime<Utc>>) -> EntityTag {
+    let mut hasher = Sha256::new();
+    for row in rows {
+        hasher.update(row.id.as_bytes());
+        hasher.update(row.email.as_bytes());
+        hasher.update(row.name.as_bytes());
+        hasher.update(row.status.as_bytes());
+    }
+    if let Some(last_updated_at) = last_updated_at {
+        hasher.update(last_updated_at.to_rfc3339().as_bytes());
+    }
+    EntityTag::new_weak(format!("{:x}", hasher.finalize()))
+}
+
+#[tracing::instrument(name = "Get a page of subscribers", skip(pool, metrics))]
+async fn get_subscribers_page(
+    pool: &PgPool,
+    metrics: &Metrics,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<SubscriberRow>, sqlx::Error> {
+    let mut connection = acquire(pool, metrics).await?;
+    sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        ORDER BY subscribed_at, id
+        LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(&mut connection)
+    .await
+}
+
+#[derive(serde::Deserialize)]
+pub struct SearchQuery {
+    q: String,
+}
+
+/// Subscribers whose email or name contains `q`, ignoring case.
+pub async fn search_subscriptions(
+    query: web::Query<SearchQuery>,
+    pagination: web::Query<Pagination>,
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_query",
+            "The search query cannot be empty.",
+        ));
+    }
+    let subscribers = retry_read(|| {
+        search_subscribers(&pool, &metrics, q, pagination.limit(), pagination.offset())
+    })
+    .await
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(subscribers))
+}
+
+/// Escape `LIKE` wildcards so that `%` or `_` in the query match literally.
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[tracing::instrument(name = "Search subscribers", skip(pool, metrics))]
+async fn search_subscribers(
+    pool: &PgPool,
+    metrics: &Metrics,
+    q: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<SubscriberRow>, sqlx::Error> {
+    let pattern = format!("%{}%", escape_like(q));
+    let mut connection = acquire(pool, metrics).await?;
+    sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE email ILIKE $1 OR name ILIKE $1
+        ORDER BY email
+        LIMIT $2 OFFSET $3
+        "#,
+        pattern,
+        limit,
+        offset
+    )
+    .fetch_all(&mut connection)
+    .await
+}
+
+#[tracing::instrument(name = "Get the latest subscriber update", skip(pool, metrics))]
+async fn get_last_updated_at(
+    pool: &PgPool,
+    metrics: &Metrics,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let mut connection = acquire(pool, metrics).await?;
+    let row = sqlx::query!("SELECT MAX(updated_at) AS last_updated_at FROM subscriptions")
+        .fetch_one(&mut connection)
+        .await?;
+    Ok(row.last_updated_at)
+}
+
+/// Remove a subscriber along with their confirmation tokens.
+#[tracing::instrument(name = "Delete a subscriber", skip(pool, metrics, user_id))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = begin_transaction(&pool, &metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let Some(email) = delete_subscriber_rows(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to delete the subscriber.")
+        .map_err(e500)?
+    else {
+        return Ok(json_error(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            "There is no subscriber with this id.",
+        ));
+    };
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        SUBSCRIBER_DELETED,
+        &subscriber_id.to_string(),
+        serde_json::json!({ "email": email }),
+    )
+    .await
+    .context("Failed to record the deletion in the audit log.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber.")
+        .map_err(e500)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Returns the email of the deleted subscriber, if there was one.
+async fn delete_subscriber_rows(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let row = sqlx::query!(
+        "DELETE FROM subscriptions WHERE id = $1 RETURNING email",
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    Ok(row.map(|r| r.email))
+}
+
+/// The response to a re-send of the pending confirmations.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ResentConfirmations {
+    pub queued: u64,
+}
+
+/// Queue the confirmation email again for every subscriber still pending
+/// confirmation, e.g. after fixing a broken template. Each is sent their
+/// latest unexpired token, or a fresh one if they have none left. The emails
+/// are spread out over time to respect the provider's limits.
+#[tracing::instrument(name = "Re-send pending confirmations", skip_all)]
+pub async fn resend_pending_confirmations(
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    email_queue: web::Data<EmailQueueSettings>,
+    token_attempts: web::Data<TokenGenerationAttempts>,
+    token_ttl: web::Data<ConfirmationTokenTtl>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = begin_transaction(&pool, &metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let pending = get_pending_tokens(&mut transaction, token_ttl.0)
+        .await
+        .context("Failed to list the pending subscribers.")
+        .map_err(e500)?;
+
+    let spacing = chrono::Duration::from_std(email_queue.resend_spacing()).map_err(e500)?;
+    let start = Utc::now();
+    let mut queued: i32 = 0;
+    for subscriber in pending {
+        let recipient = match SubscriberEmail::parse(subscriber.email) {
+            Ok(recipient) => recipient,
+            Err(e) => {
+                tracing::warn!(error = %e, "Skipping a pending subscriber with an invalid email");
+                continue;
+            }
+        };
+        let token = match subscriber.token {
+            Some(token) => token,
+            None => store_new_token(
+                &mut transaction,
+                TokenKind::Confirmation,
+                subscriber.id,
+                token_attempts.0,
+                generate_subscription_token,
+            )
+            .await
+            .context("Failed to store a new confirmation token.")
+            .map_err(e500)?,
+        };
+        enqueue_confirmation_email(
+            &mut transaction,
+            subscriber.id,
+            &recipient,
+            &base_url.0,
+            &token,
+            start + spacing * queued,
+        )
+        .await
+        .context("Failed to queue a confirmation email.")
+        .map_err(e500)?;
+        queued += 1;
+    }
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        CONFIRMATIONS_RESENT,
+        "pending_confirmation",
+        serde_json::json!({ "queued": queued }),
+    )
+    .await
+    .context("Failed to record the re-send in the audit log.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to re-send confirmations.")
+        .map_err(e500)?;
+    tracing::info!(queued, "Queued the pending confirmations again.");
+
+    Ok(HttpResponse::Ok().json(ResentConfirmations {
+        queued: queued as u64,
+    }))
+}
+
+/// A pending subscriber, and their latest confirmation token still within
+/// the TTL, if any.
+struct PendingSubscriber {
+    id: Uuid,
+    email: String,
+    token: Option<String>,
+}
+
+/// Every pending subscriber, minus any address on the suppression list.
+/// Token ages are measured on the database's clock, as when confirming.
+async fn get_pending_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    ttl: chrono::Duration,
+) -> Result<Vec<PendingSubscriber>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT s.id, s.email, t.subscription_token AS "token?"
+        FROM subscriptions s
+        LEFT JOIN LATERAL (
+            SELECT subscription_token FROM subscription_tokens
+            WHERE subscriber_id = s.id AND created_at >= now() - make_interval(secs => $1)
+            ORDER BY created_at DESC
+            LIMIT 1
+        ) t ON true
+        WHERE s.status = 'pending_confirmation'
+        AND NOT EXISTS (SELECT 1 FROM suppressions WHERE suppressions.email = s.email)
+        ORDER BY s.id
+        "#,
+        ttl.num_milliseconds() as f64 / 1000.0,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| PendingSubscriber {
+            id: r.id,
+            email: r.email,
+            token: r.token,
+        })
+        .collect())
+}
+
+#[derive(serde::Deserialize)]
+pub struct FunnelQuery {
+    from: NaiveDate,
+    to: NaiveDate,
+}
+
+/// How many of the subscribers who signed up over a date range went on to
+/// confirm.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Funnel {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub subscribed: i64,
+    pub confirmed: i64,
+    /// `confirmed / subscribed`, `0` when nobody subscribed.
+    pub conversion_rate: f64,
+}
+
+/// The signup funnel of the subscribers who signed up between `from` and
+/// `to`, both included, in UTC. A subscriber confirmed since counts as
+/// confirmed even if they were suppressed or unsubscribed later.
+pub async fn get_subscription_funnel(
+    query: web::Query<FunnelQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FunnelQuery { from, to } = query.into_inner();
+    if from > to {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_query",
+            "The start of the range cannot be after its end.",
+        ));
+    }
+    let (subscribed, confirmed) = retry_read(|| count_funnel(&pool, from, to))
+        .await
+        .map_err(e500)?;
+    let conversion_rate = if subscribed == 0 {
+        0.0
+    } else {
+        confirmed as f64 / subscribed as f64
+    };
+    Ok(HttpResponse::Ok().json(Funnel {
+        from,
+        to,
+        subscribed,
+        confirmed,
+        conversion_rate,
+    }))
+}
+
+#[tracing::instrument(name = "Count the signup funnel", skip(pool))]
+async fn count_funnel(
+    pool: &PgPool,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<(i64, i64), sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "subscribed!", COUNT(confirmed_at) AS "confirmed!"
+        FROM subscriptions
+        WHERE (subscribed_at AT TIME ZONE 'UTC')::date BETWEEN $1 AND $2
+        "#,
+        from,
+        to
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok((row.subscribed, row.confirmed))
+}
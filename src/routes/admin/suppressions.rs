@@ -0,0 +1,55 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::domain::SubscriberEmail;
+use crate::error::{e500, json_error};
+use crate::retry::retry_read;
+use crate::suppressions::{list_suppressions, suppress, unsuppress};
+
+#[derive(serde::Deserialize)]
+pub struct NewSuppression {
+    email: String,
+    reason: Option<String>,
+}
+
+pub async fn get_suppressions(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let suppressions = retry_read(|| list_suppressions(&pool))
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(suppressions))
+}
+
+pub async fn add_suppression(
+    body: web::Json<NewSuppression>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let NewSuppression { email, reason } = body.0;
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(e) => return Ok(json_error(StatusCode::BAD_REQUEST, "invalid_email", e)),
+    };
+    suppress(
+        pool.get_ref(),
+        email.as_ref(),
+        reason.as_deref().unwrap_or("manual"),
+    )
+    .await
+    .map_err(e500)?;
+    Ok(HttpResponse::Created().finish())
+}
+
+pub async fn remove_suppression(
+    email: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if unsuppress(&pool, &email).await.map_err(e500)? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(json_error(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            "The address is not suppressed.",
+        ))
+    }
+}
@@ -1,5 +1,127 @@
-use actix_web::{HttpResponse, Responder};
+use std::collections::BTreeMap;
+use std::time::Instant;
 
-pub async fn health_checker() -> impl Responder {
-    HttpResponse::Ok()
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::db_pool::acquire;
+use crate::error::{e500, json_error};
+use crate::metrics::Metrics;
+use crate::migrations::pending_migrations;
+use crate::sender_domain::SenderDomainProblems;
+
+#[derive(serde::Deserialize)]
+pub struct HealthCheckQuery {
+    #[serde(default)]
+    verbose: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Error,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct CheckReport {
+    pub status: HealthStatus,
+    pub latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub checks: BTreeMap<String, CheckReport>,
+}
+
+/// 200 when every dependency is reachable, 503 otherwise. With
+/// `?verbose=true` the body details each check and how long it took.
+pub async fn health_checker(
+    query: web::Query<HealthCheckQuery>,
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+) -> HttpResponse {
+    let mut checks = BTreeMap::new();
+    checks.insert(
+        "database".to_string(),
+        check_database(&pool, &metrics).await,
+    );
+
+    let status = if checks.values().all(|c| c.status == HealthStatus::Ok) {
+        HealthStatus::Ok
+    } else {
+        HealthStatus::Error
+    };
+    let mut response = match status {
+        HealthStatus::Ok => HttpResponse::Ok(),
+        HealthStatus::Error => HttpResponse::ServiceUnavailable(),
+    };
+    if query.verbose {
+        response.json(HealthReport { status, checks })
+    } else {
+        response.finish()
+    }
+}
+
+#[tracing::instrument(name = "Checking the database", skip(pool, metrics))]
+async fn check_database(pool: &PgPool, metrics: &Metrics) -> CheckReport {
+    let start = Instant::now();
+    let outcome = match acquire(pool, metrics).await {
+        Ok(mut connection) => sqlx::query("SELECT 1").execute(&mut connection).await,
+        Err(e) => Err(e),
+    };
+    let latency_ms = start.elapsed().as_millis();
+    match outcome {
+        Ok(_) => CheckReport {
+            status: HealthStatus::Ok,
+            latency_ms,
+            error: None,
+        },
+        // The details stay in the logs: the endpoint is public.
+        Err(e) => {
+            tracing::warn!(error = %e, "The database health check failed");
+            CheckReport {
+                status: HealthStatus::Error,
+                latency_ms,
+                error: Some("unreachable".into()),
+            }
+        }
+    }
+}
+
+/// Ready to serve traffic: the database schema is up to date, and the sender
+/// domain publishes the records it was found missing on start, if any.
+pub async fn readiness(
+    pool: web::Data<PgPool>,
+    sender_domain_problems: web::Data<SenderDomainProblems>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let pending = pending_migrations(&pool).await.map_err(e500)?;
+    if !pending.is_empty() {
+        let versions: Vec<_> = pending.iter().map(i64::to_string).collect();
+        return Ok(json_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "pending_migrations",
+            format!(
+                "The database is missing migrations: {}.",
+                versions.join(", ")
+            ),
+        ));
+    }
+    if !sender_domain_problems.0.is_empty() {
+        let records: Vec<_> = sender_domain_problems
+            .0
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+        return Ok(json_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "sender_domain_misconfigured",
+            format!("The sender domain has no {} record.", records.join(" or ")),
+        ));
+    }
+    Ok(HttpResponse::Ok().finish())
 }
@@ -0,0 +1,100 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use crate::authentication::{
+    locked_until, record_failed_login, reset_failed_logins, validate_credentials, AuthError,
+    Credentials, LoginLockout,
+};
+use crate::clock::Clock;
+use crate::error::{error_chain_fmt, json_error, unexpected_error};
+use crate::form::StrictForm;
+use crate::metrics::Metrics;
+use crate::session_state::TypedSession;
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: Secret<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum LoginError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("The account is locked")]
+    Locked,
+    #[error("Something went wrong")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for LoginError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            LoginError::AuthError(_) => json_error(
+                StatusCode::UNAUTHORIZED,
+                "invalid_credentials",
+                "Invalid username or password.",
+            ),
+            LoginError::Locked => json_error(
+                StatusCode::FORBIDDEN,
+                "account_locked",
+                "Too many failed login attempts. The account is locked, please try again later.",
+            ),
+            LoginError::UnexpectedError(e) => unexpected_error(e),
+        }
+    }
+}
+
+#[tracing::instrument(
+    skip(form, pool, metrics, session, clock, lockout),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn login(
+    form: StrictForm<LoginFormData>,
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+    session: TypedSession,
+    clock: web::Data<dyn Clock>,
+    lockout: web::Data<LoginLockout>,
+) -> Result<HttpResponse, LoginError> {
+    let credentials = Credentials {
+        username: form.0.username,
+        password: form.0.password,
+    };
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+
+    let now = clock.now();
+    let username = credentials.username.clone();
+    // A locked account is rejected before looking at the password, so that
+    // guessing right during the lockout gives nothing away.
+    if locked_until(&username, now, &pool, &metrics)
+        .await?
+        .is_some()
+    {
+        return Err(LoginError::Locked);
+    }
+
+    let user_id = match validate_credentials(credentials, &pool, &metrics).await {
+        Ok(user_id) => user_id,
+        Err(AuthError::InvalidCredentials(e)) => {
+            record_failed_login(&username, now, &lockout, &pool, &metrics).await?;
+            return Err(LoginError::AuthError(e));
+        }
+        Err(AuthError::UnexpectedError(e)) => return Err(LoginError::UnexpectedError(e)),
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    reset_failed_logins(user_id, &pool, &metrics).await?;
+
+    session
+        .log_in(user_id, clock.now())
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    Ok(HttpResponse::Ok().finish())
+}
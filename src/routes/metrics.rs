@@ -0,0 +1,10 @@
+use actix_web::{web, HttpResponse};
+use prometheus::TEXT_FORMAT;
+
+use crate::error::e500;
+use crate::metrics::Metrics;
+
+pub async fn get_metrics(metrics: web::Data<Metrics>) -> Result<HttpResponse, actix_web::Error> {
+    let body = metrics.render().map_err(e500)?;
+    Ok(HttpResponse::Ok().content_type(TEXT_FORMAT).body(body))
+}
@@ -0,0 +1,155 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit::{record_audit_entry, NEWSLETTER_PUBLISHED};
+use crate::authentication::UserId;
+use crate::db_pool::begin_transaction;
+use crate::domain::{NewsletterTitle, SubscriberEmail, TopicName};
+use crate::error::{error_chain_fmt, json_error, unexpected_error};
+use crate::metrics::Metrics;
+use crate::newsletter_content::{TextBodyCheck, TextBodyCheckMode};
+use crate::newsletter_issues::{enqueue_delivery_tasks, record_issue, NewIssue};
+use crate::topics::unknown_topics;
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+    /// Send the issue from this address instead of the configured sender.
+    #[serde(default)]
+    sender: Option<String>,
+    /// Only send the issue to the subscribers of this topic; everyone
+    /// confirmed gets it otherwise.
+    #[serde(default)]
+    topic: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Content {
+    html: String,
+    text: String,
+}
+
+/// The response to a successful publication.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct PublishedIssue {
+    pub issue_id: Uuid,
+    /// How many deliveries were queued: one per confirmed subscriber of the
+    /// issue's topic who is not suppressed.
+    pub queued: u64,
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PublishError::ValidationError(message) => json_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_newsletter",
+                message,
+            ),
+            PublishError::UnexpectedError(e) => unexpected_error(e),
+        }
+    }
+}
+
+/// Store the issue and queue its deliveries: the email worker sends them in
+/// the background, recording how each went.
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(body, pool, metrics, text_body_check, user_id),
+    fields(title = %body.title, issue_id = tracing::field::Empty)
+)]
+pub async fn publish_newsletter(
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+    text_body_check: web::Data<TextBodyCheck>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, PublishError> {
+    let title =
+        NewsletterTitle::parse(body.title.clone()).map_err(PublishError::ValidationError)?;
+    let sender = body
+        .sender
+        .clone()
+        .map(SubscriberEmail::parse)
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+    let topic = body
+        .topic
+        .clone()
+        .map(TopicName::parse)
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+    if let Some(topic) = &topic {
+        let unknown = unknown_topics(pool.get_ref(), std::slice::from_ref(topic))
+            .await
+            .context("Failed to look up the topic of the issue.")?;
+        if !unknown.is_empty() {
+            return Err(PublishError::ValidationError(format!(
+                "Unknown topic: {}.",
+                topic.as_ref()
+            )));
+        }
+    }
+    if let Some(problem) = text_body_check.inspect(&body.content.html, &body.content.text) {
+        tracing::warn!(
+            problem,
+            "The text body of the issue looks out of step with the HTML."
+        );
+        if text_body_check.mode == TextBodyCheckMode::Reject {
+            return Err(PublishError::ValidationError(problem));
+        }
+    }
+    let issue_id = Uuid::new_v4();
+    tracing::Span::current().record("issue_id", tracing::field::display(issue_id));
+
+    let mut transaction = begin_transaction(&pool, &metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = NewIssue {
+        title: title.as_ref(),
+        html_content: &body.content.html,
+        text_content: &body.content.text,
+        sender_email: sender.as_ref().map(AsRef::as_ref),
+        topic: topic.as_ref().map(AsRef::as_ref),
+    };
+    record_issue(&mut transaction, issue_id, &issue, **user_id)
+        .await
+        .context("Failed to record the newsletter issue.")?;
+    let queued = enqueue_delivery_tasks(&mut transaction, issue_id, issue.topic)
+        .await
+        .context("Failed to queue the deliveries of the newsletter issue.")?;
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        NEWSLETTER_PUBLISHED,
+        &issue_id.to_string(),
+        serde_json::json!({ "title": title.as_ref() }),
+    )
+    .await
+    .context("Failed to record the publication in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a newsletter issue.")?;
+    tracing::info!(queued, "Queued the newsletter issue for delivery.");
+
+    Ok(HttpResponse::Ok().json(PublishedIssue { issue_id, queued }))
+}
@@ -0,0 +1,433 @@
+//! The OpenAPI contract of the public API. It is written out by hand, so it
+//! has to be updated along with the routes it describes; the limits that are
+//! configurable are filled in from the configuration when it is served.
+use actix_web::{web, HttpResponse};
+use serde_json::{json, Value};
+
+use super::subscriptions::MAX_IDEMPOTENCY_KEY_LENGTH;
+use crate::domain::{LengthUnit, NamePolicy, NewsletterTitle};
+use crate::startup::ApplicationBasePath;
+
+pub async fn openapi_document(
+    base_path: web::Data<ApplicationBasePath>,
+    name_policy: web::Data<NamePolicy>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(openapi_spec(&base_path.0, &name_policy))
+}
+
+/// A response carrying the JSON error envelope, listing the `code`s it may
+/// hold.
+fn error_response(description: &str, codes: &[&str]) -> Value {
+    json!({
+        "description": format!("{} (`code`: {})", description, codes.join(", ")),
+        "content": {
+            "application/json": { "schema": { "$ref": "#/components/schemas/ErrorBody" } }
+        }
+    })
+}
+
+/// A subscriber name, as long as the `NamePolicy` allows. `maxLength` counts
+/// Unicode scalar values: it is left out when the policy counts graphemes,
+/// which may each take several.
+fn name_schema(policy: &NamePolicy) -> Value {
+    let unit = match policy.length_unit {
+        LengthUnit::Graphemes => "characters, as a reader perceives them",
+        LengthUnit::ScalarValues => "Unicode scalar values",
+        LengthUnit::Bytes => "UTF-8 bytes",
+    };
+    let mut schema = json!({
+        "type": "string",
+        "description": format!("At most {} {}.", policy.max_length, unit),
+    });
+    if policy.length_unit != LengthUnit::Graphemes {
+        schema["maxLength"] = json!(policy.max_length);
+    }
+    schema
+}
+
+pub fn openapi_spec(base_path: &str, name_policy: &NamePolicy) -> Value {
+    let server = if base_path.is_empty() { "/" } else { base_path };
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "zero2prod",
+            "description": "Newsletter subscriptions and publishing.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "url": server }],
+        "paths": {
+            "/subscriptions": {
+                "post": {
+                    "summary": "Subscribe to the newsletter",
+                    "description": "Stores a pending subscriber and emails them a confirmation link. A subscriber suppressed since, e.g. after a complaint, is back to pending and must confirm again.",
+                    "parameters": [{
+                        "name": "Idempotency-Key",
+                        "in": "header",
+                        "required": false,
+                        "description": "A retry carrying the key of a subscription that went through gets the same answer, without a second subscriber or email.",
+                        "schema": { "type": "string", "maxLength": MAX_IDEMPOTENCY_KEY_LENGTH }
+                    }],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/x-www-form-urlencoded": {
+                                "schema": { "$ref": "#/components/schemas/SubscribeForm" }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "The confirmation email is on its way. The body is empty unless the client accepts `application/json` or `json_subscribe_response` is set.",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/SubscribeResponse" }
+                                }
+                            }
+                        },
+                        "400": error_response(
+                            "The body does not decode or misses a field, or the idempotency key is invalid",
+                            &["invalid_body", "malformed_body", "invalid_idempotency_key"],
+                        ),
+                        "403": error_response(
+                            "The deployment has reached `max_subscribers`",
+                            &["subscriber_quota_reached"],
+                        ),
+                        "409": error_response("The address is already subscribed", &["already_subscribed"]),
+                        "422": error_response(
+                            "The name, the email or a topic is invalid (400 with `legacy_validation_status`)",
+                            &["invalid_subscriber"],
+                        ),
+                        "429": error_response("Too many attempts from this client", &["rate_limited"]),
+                        "500": error_response("Unexpected failure", &["internal_error"]),
+                        "503": error_response(
+                            "Subscriptions are temporarily unavailable",
+                            &["maintenance", "subscriptions_paused", "overloaded", "database_timeout"],
+                        ),
+                    }
+                }
+            },
+            "/subscriptions/confirm": {
+                "get": {
+                    "summary": "Confirm a subscription",
+                    "parameters": [{
+                        "name": "subscription_token",
+                        "in": "query",
+                        "required": true,
+                        "description": "The token from the confirmation link.",
+                        "schema": { "type": "string" }
+                    }],
+                    "responses": {
+                        "200": { "description": "The subscription is confirmed." },
+                        "303": { "description": "The subscription is confirmed: redirects to the configured page." },
+                        "400": { "description": "The token is missing." },
+                        "401": error_response("The token is unknown", &["unknown_token"]),
+                        "410": error_response("The token has expired", &["expired_token"]),
+                        "500": error_response("Unexpected failure", &["internal_error"]),
+                        "503": error_response("The database timed out", &["database_timeout"]),
+                    }
+                }
+            },
+            "/subscriptions/confirm/{subscription_token}": {
+                "get": {
+                    "summary": "Confirm a subscription, with the token in the path",
+                    "description": "Answers as `/subscriptions/confirm` does, for email clients that mangle query strings.",
+                    "parameters": [{
+                        "name": "subscription_token",
+                        "in": "path",
+                        "required": true,
+                        "description": "The token from the confirmation link.",
+                        "schema": { "type": "string" }
+                    }],
+                    "responses": {
+                        "200": { "description": "The subscription is confirmed." },
+                        "303": { "description": "The subscription is confirmed: redirects to the configured page." },
+                        "401": error_response("The token is unknown", &["unknown_token"]),
+                        "410": error_response("The token has expired", &["expired_token"]),
+                        "500": error_response("Unexpected failure", &["internal_error"]),
+                        "503": error_response("The database timed out", &["database_timeout"]),
+                    }
+                }
+            },
+            "/subscriptions/status": {
+                "get": {
+                    "summary": "See the status of a subscription",
+                    "parameters": [{
+                        "name": "token",
+                        "in": "query",
+                        "required": true,
+                        "description": "The subscriber's management token, from the links in their emails.",
+                        "schema": { "type": "string" }
+                    }],
+                    "responses": {
+                        "200": {
+                            "description": "The subscription of the token's holder.",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/SubscriptionStatus" }
+                                }
+                            }
+                        },
+                        "400": { "description": "The token is missing." },
+                        "401": error_response("The token is unknown", &["unknown_token"]),
+                        "500": error_response("Unexpected failure", &["internal_error"]),
+                        "503": error_response("The database timed out", &["database_timeout"]),
+                    }
+                }
+            },
+            "/subscriptions/resend": {
+                "post": {
+                    "summary": "Resend the confirmation email",
+                    "description": "Emails a pending subscriber a fresh confirmation link. Answers the same whether or not the address is subscribed.",
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/x-www-form-urlencoded": {
+                                "schema": { "$ref": "#/components/schemas/ResendForm" }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": { "description": "A confirmation email is on its way if the address is pending confirmation." },
+                        "400": error_response("The body does not decode or misses a field", &["invalid_body", "malformed_body"]),
+                        "422": error_response("The email is invalid", &["invalid_email"]),
+                        "429": error_response("A confirmation was resent to this address too recently", &["rate_limited"]),
+                        "500": error_response("Unexpected failure", &["internal_error"]),
+                        "503": error_response("The service is unavailable", &["maintenance", "database_timeout"]),
+                    }
+                }
+            },
+            "/newsletters": {
+                "post": {
+                    "summary": "Publish a newsletter issue",
+                    "description": "Requires an admin session. Deliveries are sent in the background.",
+                    "security": [{ "session": [] }],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": {
+                                "schema": { "$ref": "#/components/schemas/NewsletterIssue" }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "The issue is queued for delivery.",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/PublishedIssue" }
+                                }
+                            }
+                        },
+                        "303": { "description": "No admin session: redirects to the login." },
+                        "400": error_response("The body misses a field", &["invalid_body"]),
+                        "422": error_response(
+                            "The issue is invalid, or its text body looks out of date and `text_body_check` rejects it (400 with `legacy_validation_status`)",
+                            &["invalid_newsletter"],
+                        ),
+                        "500": error_response("Unexpected failure", &["internal_error"]),
+                        "503": error_response(
+                            "Publishing is temporarily unavailable",
+                            &["maintenance", "database_timeout"],
+                        ),
+                    }
+                }
+            }
+        },
+        "components": {
+            "securitySchemes": {
+                "session": { "type": "apiKey", "in": "cookie", "name": "id" }
+            },
+            "schemas": {
+                "ErrorBody": {
+                    "type": "object",
+                    "required": ["code", "message"],
+                    "properties": {
+                        "code": { "type": "string" },
+                        "message": { "type": "string" },
+                        "errors": {
+                            "type": "array",
+                            "description": "Every field that failed validation, on validation errors.",
+                            "items": { "$ref": "#/components/schemas/FieldError" }
+                        }
+                    }
+                },
+                "FieldError": {
+                    "type": "object",
+                    "required": ["field", "code", "message"],
+                    "properties": {
+                        "field": { "type": "string" },
+                        "code": { "type": "string", "enum": ["invalid", "unknown", "undeliverable"] },
+                        "message": { "type": "string" }
+                    }
+                },
+                "ResendForm": {
+                    "type": "object",
+                    "required": ["email"],
+                    "properties": {
+                        "email": { "type": "string", "format": "email" },
+                    }
+                },
+                "SubscriptionStatus": {
+                    "type": "object",
+                    "required": ["email", "status", "topics"],
+                    "properties": {
+                        "email": { "type": "string", "format": "email" },
+                        "status": {
+                            "type": "string",
+                            "enum": ["pending_confirmation", "confirmed", "suppressed"]
+                        },
+                        "topics": { "type": "array", "items": { "type": "string" } },
+                    }
+                },
+                "SubscribeForm": {
+                    "type": "object",
+                    "required": ["name", "email"],
+                    "properties": {
+                        "name": name_schema(name_policy),
+                        "email": { "type": "string", "format": "email" },
+                        "topics": {
+                            "type": "string",
+                            "description": "A comma-separated list of topics to subscribe to, e.g. `weekly,product`."
+                        }
+                    }
+                },
+                "SubscribeResponse": {
+                    "type": "object",
+                    "required": ["id", "status"],
+                    "properties": {
+                        "id": { "type": "string", "format": "uuid" },
+                        "status": { "type": "string", "enum": ["pending_confirmation"] }
+                    }
+                },
+                "NewsletterIssue": {
+                    "type": "object",
+                    "required": ["title", "content"],
+                    "properties": {
+                        "title": {
+                            "type": "string",
+                            "description": format!(
+                                "At most {} characters, as a reader perceives them.",
+                                NewsletterTitle::MAX_LENGTH
+                            )
+                        },
+                        "topic": {
+                            "type": "string",
+                            "description": "Only sends the issue to the subscribers of this topic."
+                        },
+                        "sender": {
+                            "type": "string",
+                            "format": "email",
+                            "description": "Sends the issue from this address instead of the default sender."
+                        },
+                        "content": {
+                            "type": "object",
+                            "required": ["html", "text"],
+                            "properties": {
+                                "html": { "type": "string" },
+                                "text": { "type": "string" }
+                            }
+                        }
+                    }
+                },
+                "PublishedIssue": {
+                    "type": "object",
+                    "required": ["issue_id", "queued"],
+                    "properties": {
+                        "issue_id": { "type": "string", "format": "uuid" },
+                        "queued": {
+                            "type": "integer",
+                            "description": "How many deliveries were queued; the deliveries report tells how they went."
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use serde::Serialize;
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    use super::openapi_spec;
+    use crate::domain::{LengthUnit, NamePolicy};
+    use crate::error::{ErrorBody, FieldError};
+    use crate::routes::{PublishedIssue, SubscribeResponse};
+
+    fn keys(value: &Value) -> BTreeSet<String> {
+        value.as_object().unwrap().keys().cloned().collect()
+    }
+
+    /// The body holds every key its schema requires, and none it does not
+    /// describe.
+    fn assert_matches_schema(body: impl Serialize, schema: &str) {
+        let spec = openapi_spec("", &NamePolicy::default());
+        let schema = &spec["components"]["schemas"][schema];
+        let body = keys(&serde_json::to_value(body).unwrap());
+        let required: BTreeSet<String> = schema["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|key| key.as_str().unwrap().to_owned())
+            .collect();
+
+        assert!(
+            required.is_subset(&body),
+            "{:?} misses one of {:?}",
+            body,
+            required
+        );
+        assert!(body.is_subset(&keys(&schema["properties"])), "{:?}", body);
+    }
+
+    #[test]
+    fn response_bodies_match_their_schemas() {
+        assert_matches_schema(
+            SubscribeResponse {
+                id: Uuid::new_v4(),
+                status: "pending_confirmation".into(),
+            },
+            "SubscribeResponse",
+        );
+        assert_matches_schema(
+            PublishedIssue {
+                issue_id: Uuid::new_v4(),
+                queued: 1,
+            },
+            "PublishedIssue",
+        );
+        assert_matches_schema(
+            ErrorBody {
+                code: "invalid_subscriber".into(),
+                message: "The subscriber is invalid.".into(),
+                errors: vec![FieldError::new("email", "invalid", "Not an email.")],
+            },
+            "ErrorBody",
+        );
+    }
+
+    #[test]
+    fn the_name_limit_comes_from_the_name_policy() {
+        let policy = NamePolicy {
+            max_length: 100,
+            length_unit: LengthUnit::ScalarValues,
+        };
+
+        let spec = openapi_spec("", &policy);
+
+        let name = &spec["components"]["schemas"]["SubscribeForm"]["properties"]["name"];
+        assert_eq!(name["maxLength"], 100);
+        // A grapheme may take several scalar values.
+        let spec = openapi_spec("", &NamePolicy::default());
+        let name = &spec["components"]["schemas"]["SubscribeForm"]["properties"]["name"];
+        assert!(name.get("maxLength").is_none());
+        assert_eq!(
+            name["description"],
+            "At most 256 characters, as a reader perceives them."
+        );
+    }
+}
@@ -1,67 +1,935 @@
-use actix_web::{web, HttpResponse, Responder};
-use sqlx::PgPool;
+use std::future::{ready, Ready};
 
-use chrono::Utc;
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::ExposeSecret;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use subtle::ConstantTimeEq;
+
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::audit::{record_audit_entry, SUBSCRIBER_RESUBSCRIBED};
+use crate::client_ip::ClientIp;
+use crate::configuration::TrustedSourceSettings;
+use crate::db_pool::begin_transaction;
+use crate::dns::DnsResolver;
+use crate::domain::{NamePolicy, NewSubscriber, SubscriberEmail, SubscriberName, TopicName};
+use crate::email_templates::{confirmation_email, manage_link};
+use crate::email_worker::enqueue_email;
+use crate::error::{
+    error_chain_fmt, field_errors_message, json_error, json_field_errors, unexpected_error,
+    FieldError,
+};
+use crate::feature_flags::{FeatureFlags, SUBSCRIPTIONS_PAUSED};
+use crate::form::StrictForm;
+use crate::metrics::Metrics;
+use crate::pii::PiiLogging;
+use crate::routes::ConfirmationTokenTtl;
+use crate::startup::ApplicationBaseUrl;
+use crate::suppressions::{is_permanent, is_suppressed, lift_suppression, suppression_reason};
+use crate::token_cache::TokenCache;
+use crate::topics::{enroll_in_default_topic, enroll_in_topics, unknown_topics};
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     pub name: String,
     pub email: String,
+    /// A honeypot: the subscription form hides this field, so only bots
+    /// fill it in.
+    #[serde(default)]
+    pub website: Option<String>,
+    /// The topics to subscribe to: a comma-separated list in a form, a list
+    /// or a comma-separated string in JSON.
+    #[serde(default)]
+    pub topics: Option<TopicList>,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+pub enum TopicList {
+    Joined(String),
+    Listed(Vec<String>),
+}
 
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+impl TopicList {
+    fn into_names(self) -> Vec<String> {
+        match self {
+            TopicList::Joined(joined) => joined
+                .split(',')
+                .filter(|name| !name.trim().is_empty())
+                .map(str::to_owned)
+                .collect(),
+            TopicList::Listed(names) => names,
+        }
     }
 }
 
+impl FormData {
+    fn is_from_a_bot(&self) -> bool {
+        self.website
+            .as_deref()
+            .is_some_and(|website| !website.trim().is_empty())
+    }
+
+    /// Every field is parsed, so that all the invalid ones are reported at
+    /// once.
+    fn into_new_subscriber(
+        self,
+        name_policy: &NamePolicy,
+    ) -> Result<NewSubscriber, Vec<FieldError>> {
+        let mut errors = Vec::new();
+        let name = SubscriberName::parse_with(self.name, name_policy)
+            .map_err(|e| errors.push(FieldError::new("name", "invalid", e)))
+            .ok();
+        let email = SubscriberEmail::parse(self.email)
+            .map_err(|e| errors.push(FieldError::new("email", "invalid", e)))
+            .ok();
+        let mut topics = Vec::new();
+        for topic in self.topics.map(TopicList::into_names).unwrap_or_default() {
+            match TopicName::parse(topic) {
+                Ok(topic) if !topics.contains(&topic) => topics.push(topic),
+                Ok(_) => {}
+                Err(e) => errors.push(FieldError::new("topics", "invalid", e)),
+            }
+        }
+        match (name, email) {
+            (Some(name), Some(email)) if errors.is_empty() => Ok(NewSubscriber {
+                email,
+                name,
+                topics,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscribeError {
+    #[error("{}", field_errors_message(.0))]
+    ValidationError(Vec<FieldError>),
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("This email address is already subscribed.")]
+    AlreadySubscribed,
+    #[error("Subscriptions are paused, please try again later.")]
+    Paused,
+    #[error("This newsletter has reached its maximum number of subscribers.")]
+    QuotaReached,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscribeError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeError::ValidationError(errors) => json_field_errors(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_subscriber",
+                self.to_string(),
+                errors.clone(),
+            ),
+            SubscribeError::AuthError(_) => {
+                let mut response =
+                    json_error(StatusCode::UNAUTHORIZED, "unauthorized", "Invalid API key.");
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                response
+            }
+            SubscribeError::AlreadySubscribed => {
+                json_error(StatusCode::CONFLICT, "already_subscribed", self.to_string())
+            }
+            SubscribeError::Paused => json_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "subscriptions_paused",
+                self.to_string(),
+            ),
+            SubscribeError::QuotaReached => json_error(
+                StatusCode::FORBIDDEN,
+                "subscriber_quota_reached",
+                self.to_string(),
+            ),
+            SubscribeError::UnexpectedError(e) => unexpected_error(e),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool),
+    skip(form, pool, base_url, token_attempts, client_ip, flags, pii, metrics, email_domain_check, token_cache, token_ttl, name_policy, quota, wants_json, idempotency_key),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name
+        subscriber_email = %pii.email(&form.email),
+        subscriber_name = %pii.name(&form.name),
+        client_ip = %client_ip
     )
 )]
-pub async fn subscribe(form: web::Form<FormData>, pool: web::Data<PgPool>) -> impl Responder {
-    let new_subscriber = match form.0.try_into() {
-        Ok(s) => s,
-        Err(_) => return HttpResponse::BadRequest().finish(),
+pub async fn subscribe(
+    form: StrictForm<FormData>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    token_attempts: web::Data<TokenGenerationAttempts>,
+    client_ip: ClientIp,
+    flags: web::Data<FeatureFlags>,
+    pii: web::Data<PiiLogging>,
+    metrics: web::Data<Metrics>,
+    email_domain_check: web::Data<EmailDomainCheck>,
+    token_cache: web::Data<TokenCache>,
+    token_ttl: web::Data<ConfirmationTokenTtl>,
+    name_policy: web::Data<NamePolicy>,
+    quota: web::Data<SubscriberQuota>,
+    wants_json: WantsJsonResponse,
+    idempotency_key: IdempotencyKey,
+) -> Result<HttpResponse, SubscribeError> {
+    if flags.is_enabled(SUBSCRIPTIONS_PAUSED) {
+        return Err(SubscribeError::Paused);
+    }
+    if form.is_from_a_bot() {
+        // Pretend everything went fine so that the bot moves on.
+        tracing::info!("Ignoring a subscription with a filled-in honeypot field.");
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    let new_subscriber = form
+        .0
+        .into_new_subscriber(&name_policy)
+        .map_err(SubscribeError::ValidationError)?;
+    reject_unknown_topics(&pool, &new_subscriber.topics).await?;
+    email_domain_check.check(&new_subscriber.email).await?;
+
+    let mut transaction = begin_transaction(&pool, &metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let inserted_id = insert_subscriber(
+        &mut transaction,
+        &new_subscriber,
+        "pending_confirmation",
+        idempotency_key.0.as_deref(),
+    )
+    .await
+    .context("Failed to insert new subscriber in the database.")?;
+    if let (None, Some(key)) = (inserted_id, &idempotency_key.0) {
+        // A retry of a subscription that went through: answer as the first
+        // time, without sending another email.
+        if let Some(subscriber_id) = subscriber_with_idempotency_key(&mut transaction, key)
+            .await
+            .context("Failed to look up the idempotency key.")?
+        {
+            tracing::info!("Replaying a subscription with a known idempotency key.");
+            return Ok(subscribed_response(subscriber_id, &wants_json));
+        }
+    }
+    let (subscriber_id, subscription_token, entered_the_funnel, voided_tokens) = match inserted_id {
+        Some(subscriber_id) => {
+            enroll_new_subscriber(&mut transaction, subscriber_id, &new_subscriber.topics)
+                .await
+                .context("Failed to enroll the new subscriber in their topics.")?;
+            let subscription_token = store_new_token(
+                &mut transaction,
+                TokenKind::Confirmation,
+                subscriber_id,
+                token_attempts.0,
+                generate_subscription_token,
+            )
+            .await
+            .context("Failed to store the confirmation token for a new subscriber.")?;
+            store_new_token(
+                &mut transaction,
+                TokenKind::Management,
+                subscriber_id,
+                token_attempts.0,
+                generate_subscription_token,
+            )
+            .await
+            .context("Failed to store the management token for a new subscriber.")?;
+            (subscriber_id, subscription_token, true, Vec::new())
+        }
+        // A repeated signup, e.g. a double submit: send the pending
+        // subscriber their confirmation link again.
+        None => {
+            let existing = existing_subscription(
+                &mut transaction,
+                &new_subscriber,
+                token_attempts.0,
+                token_ttl.0,
+            )
+            .await?;
+            let Some(subscription_token) = existing.subscription_token else {
+                // Nothing to send, nor to commit.
+                return Ok(subscribed_response(existing.subscriber_id, &wants_json));
+            };
+            (
+                existing.subscriber_id,
+                subscription_token,
+                existing.resubscribed,
+                existing.voided_tokens,
+            )
+        }
     };
+    if let (true, Some(max_subscribers)) = (entered_the_funnel, quota.0) {
+        // The count includes the subscriber just inserted or re-subscribed,
+        // who is rolled back along with the transaction when over the cap.
+        let subscribers = count_subscribers(&mut transaction)
+            .await
+            .context("Failed to count the subscribers.")?;
+        if subscribers > max_subscribers {
+            return Err(SubscribeError::QuotaReached);
+        }
+    }
+    if is_suppressed(&mut transaction, new_subscriber.email.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!("Skipping the confirmation email to a suppressed address.");
+    } else {
+        enqueue_confirmation_email(
+            &mut transaction,
+            subscriber_id,
+            &new_subscriber.email,
+            &base_url.0,
+            &subscription_token,
+            Utc::now(),
+        )
+        .await
+        .context("Failed to queue a confirmation email.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new subscriber.")?;
+    // An old link still cached would otherwise confirm the new signup.
+    for token in &voided_tokens {
+        token_cache.remove(token);
+    }
+    if entered_the_funnel {
+        token_cache.insert(&subscription_token, subscriber_id);
+        metrics.subscriptions_created.inc();
+    }
+
+    Ok(subscribed_response(subscriber_id, &wants_json))
+}
 
-    match insert_subscriber(&pool, new_subscriber).await {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+fn subscribed_response(subscriber_id: Uuid, wants_json: &WantsJsonResponse) -> HttpResponse {
+    if wants_json.0 {
+        HttpResponse::Ok().json(SubscribeResponse {
+            id: subscriber_id,
+            status: "pending_confirmation".into(),
+        })
+    } else {
+        HttpResponse::Ok().finish()
     }
 }
 
+/// Subscribe on behalf of a trusted partner, e.g. when importing their
+/// mailing list: the partner already collected consent, so the subscriber is
+/// confirmed straight away and no confirmation email is sent.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Adding a subscriber from a trusted source",
+    skip(body, request, pool, trusted_source, token_attempts, pii, metrics, name_policy),
+    fields(
+        subscriber_email = %pii.email(&body.email),
+        subscriber_name = %pii.name(&body.name)
+    )
+)]
+pub async fn subscribe_trusted(
+    body: web::Json<FormData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    trusted_source: web::Data<TrustedSourceSettings>,
+    token_attempts: web::Data<TokenGenerationAttempts>,
+    pii: web::Data<PiiLogging>,
+    metrics: web::Data<Metrics>,
+    name_policy: web::Data<NamePolicy>,
+) -> Result<HttpResponse, SubscribeError> {
+    verify_api_key(request.headers(), &trusted_source).map_err(SubscribeError::AuthError)?;
+
+    let new_subscriber = body
+        .0
+        .into_new_subscriber(&name_policy)
+        .map_err(SubscribeError::ValidationError)?;
+    reject_unknown_topics(&pool, &new_subscriber.topics).await?;
+    let mut transaction = begin_transaction(&pool, &metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber, "confirmed", None)
+        .await
+        .context("Failed to insert new subscriber in the database.")?
+        .ok_or(SubscribeError::AlreadySubscribed)?;
+    enroll_new_subscriber(&mut transaction, subscriber_id, &new_subscriber.topics)
+        .await
+        .context("Failed to enroll the new subscriber in their topics.")?;
+    store_new_token(
+        &mut transaction,
+        TokenKind::Management,
+        subscriber_id,
+        token_attempts.0,
+        generate_subscription_token,
+    )
+    .await
+    .context("Failed to store the management token for a new subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new subscriber.")?;
+    // Trusted subscribers are confirmed on the spot, entering and leaving the
+    // funnel at once.
+    metrics.subscriptions_created.inc();
+    metrics.confirmations_completed.inc();
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Subscribe a new subscriber to the topics they picked, or to the default
+/// topic when they picked none.
+async fn enroll_new_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    topics: &[TopicName],
+) -> Result<(), sqlx::Error> {
+    if topics.is_empty() {
+        enroll_in_default_topic(transaction, subscriber_id).await
+    } else {
+        enroll_in_topics(transaction, subscriber_id, topics).await
+    }
+}
+
+async fn reject_unknown_topics(pool: &PgPool, topics: &[TopicName]) -> Result<(), SubscribeError> {
+    if topics.is_empty() {
+        return Ok(());
+    }
+    let unknown = unknown_topics(pool, topics)
+        .await
+        .context("Failed to look up the requested topics.")?;
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(SubscribeError::ValidationError(vec![FieldError::new(
+            "topics",
+            "unknown",
+            format!("Unknown topics: {}.", unknown.join(", ")),
+        )]))
+    }
+}
+
+fn verify_api_key(
+    headers: &HeaderMap,
+    trusted_source: &TrustedSourceSettings,
+) -> Result<(), anyhow::Error> {
+    let expected = trusted_source
+        .api_key
+        .as_ref()
+        .context("No API key is configured for trusted sources.")?;
+    let api_key = headers
+        .get(header::AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?
+        .strip_prefix("Bearer ")
+        .context("The authorization scheme was not 'Bearer'.")?;
+
+    // In constant time, lest how long the comparison takes leak the key.
+    let matches: bool = api_key
+        .as_bytes()
+        .ct_eq(expected.expose_secret().as_bytes())
+        .into();
+    if !matches {
+        anyhow::bail!("Invalid API key.");
+    }
+    Ok(())
+}
+
+/// Generate a random 25-characters-long case-sensitive subscription token.
+pub(crate) fn generate_subscription_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
+
+/// Queue the confirmation email in `transaction`, so that it is only sent once
+/// the subscriber is stored, and a provider outage does not fail the signup.
+#[tracing::instrument(
+    name = "Queue a confirmation email to a new subscriber",
+    skip(transaction, recipient, base_url, subscription_token)
+)]
+pub async fn enqueue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    recipient: &SubscriberEmail,
+    base_url: &str,
+    subscription_token: &str,
+    execute_after: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        base_url, subscription_token
+    );
+    let manage_link = management_token(&mut *transaction, subscriber_id)
+        .await?
+        .map(|token| manage_link(base_url, &token));
+    let email = confirmation_email(&confirmation_link).with_manage_link(manage_link.as_deref());
+    enqueue_email(
+        &mut *transaction,
+        recipient,
+        &email.subject,
+        &email.html_body,
+        &email.text_body,
+        execute_after,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Returns `None`, leaving the stored subscriber untouched, when the email
+/// address is already known.
 #[tracing::instrument(
     name = "Saving a new subscriber to the database",
-    skip(new_subscriber, pool)
+    skip(new_subscriber, transaction),
+    fields(rows_affected = tracing::field::Empty)
 )]
 async fn insert_subscriber(
-    pool: &PgPool,
-    new_subscriber: NewSubscriber,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        "INSERT INTO subscriptions(id, email, name, subscribed_at, status) VALUES ($1, $2, $3, $4, 'confirmed');",
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    status: &str,
+    idempotency_key: Option<&str>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    // No conflict target: a taken idempotency key inserts nothing either.
+    let row = sqlx::query!(
+        r#"INSERT INTO subscriptions
+            (id, email, name, subscribed_at, status, confirmed_at, idempotency_key)
+        VALUES ($1, $2, $3, $4, $5, CASE WHEN $5 = 'confirmed' THEN $4::timestamptz END, $6)
+        ON CONFLICT DO NOTHING
+        RETURNING id"#,
         Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        status,
+        idempotency_key,
     )
-    .execute(pool)
+    .fetch_optional(transaction)
+    .await?;
+    tracing::Span::current().record("rows_affected", u64::from(row.is_some()));
+    Ok(row.map(|r| r.id))
+}
+
+/// Subscribers confirmed or pending confirmation, i.e. those counting towards
+/// the `SubscriberQuota`.
+async fn count_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<u64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM subscriptions
+        WHERE status IN ('confirmed', 'pending_confirmation')"#
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(row.count as u64)
+}
+
+/// The subscriber created by a request with `idempotency_key`, if any.
+#[tracing::instrument(name = "Look up an idempotency key", skip(transaction))]
+async fn subscriber_with_idempotency_key(
+    transaction: &mut Transaction<'_, Postgres>,
+    idempotency_key: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE idempotency_key = $1",
+        idempotency_key
+    )
+    .fetch_optional(transaction)
+    .await?;
+    tracing::Span::current().record("rows_affected", u64::from(row.is_some()));
+    Ok(row.map(|r| r.id))
+}
+
+/// A subscriber signing up again, and the token to send them.
+struct ExistingSubscription {
+    subscriber_id: Uuid,
+    /// `None` if their address stays suppressed, e.g. after a hard bounce:
+    /// they get the usual answer, but no email.
+    subscription_token: Option<String>,
+    /// Whether they had been suppressed, e.g. after unsubscribing, and have
+    /// to confirm again.
+    resubscribed: bool,
+    /// The tokens sent before they were suppressed, now deleted, which the
+    /// `TokenCache` must forget too.
+    voided_tokens: Vec<String>,
+}
+
+/// An existing subscriber, who must still be pending confirmation or else
+/// have been suppressed: someone who unsubscribed may want back in.
+///
+/// They are sent their latest confirmation token again, unless it has expired
+/// and would only lead to a 410: a fresh one is issued then.
+#[tracing::instrument(
+    name = "Get the token of an existing subscriber",
+    skip(transaction, new_subscriber)
+)]
+async fn existing_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    token_attempts: u32,
+    token_ttl: chrono::Duration,
+) -> Result<ExistingSubscription, SubscribeError> {
+    let subscriber = sqlx::query!(
+        "SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE",
+        new_subscriber.email.as_ref()
+    )
+    .fetch_one(&mut *transaction)
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(())
+    .context("Failed to retrieve the existing subscriber.")?;
+    let (resubscribed, voided_tokens) = match subscriber.status.as_str() {
+        "pending_confirmation" => (false, Vec::new()),
+        "suppressed" => {
+            let voided_tokens = resubscribe(transaction, subscriber.id, new_subscriber)
+                .await
+                .context("Failed to resubscribe a suppressed subscriber.")?;
+            match voided_tokens {
+                Some(voided_tokens) => (true, voided_tokens),
+                None => {
+                    return Ok(ExistingSubscription {
+                        subscriber_id: subscriber.id,
+                        subscription_token: None,
+                        resubscribed: false,
+                        voided_tokens: Vec::new(),
+                    })
+                }
+            }
+        }
+        _ => return Err(SubscribeError::AlreadySubscribed),
+    };
+
+    let token = unexpired_confirmation_token(transaction, subscriber.id, token_ttl)
+        .await
+        .context("Failed to retrieve the existing subscription token.")?;
+    let subscription_token = match token {
+        Some(token) => token,
+        None => store_new_token(
+            transaction,
+            TokenKind::Confirmation,
+            subscriber.id,
+            token_attempts,
+            generate_subscription_token,
+        )
+        .await
+        .context("Failed to store a new confirmation token.")?,
+    };
+    Ok(ExistingSubscription {
+        subscriber_id: subscriber.id,
+        subscription_token: Some(subscription_token),
+        resubscribed,
+        voided_tokens,
+    })
+}
+
+/// The latest confirmation token of `subscriber_id` still within `ttl`, its
+/// age measured on the database's clock as when confirming.
+#[tracing::instrument(name = "Get an unexpired confirmation token", skip(transaction))]
+pub async fn unexpired_confirmation_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    ttl: chrono::Duration,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT subscription_token FROM subscription_tokens
+        WHERE subscriber_id = $1 AND created_at >= now() - make_interval(secs => $2)
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+        subscriber_id,
+        ttl.num_milliseconds() as f64 / 1000.0,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    Ok(row.map(|r| r.subscription_token))
+}
+
+/// Restart the double opt-in of a suppressed subscriber: lift the suppression
+/// of their address, which would hold back the confirmation email, and void
+/// the tokens sent to them before, which are returned.
+///
+/// Returns `None`, changing nothing, if the suppression is permanent.
+#[tracing::instrument(
+    name = "Resubscribe a suppressed subscriber",
+    skip(transaction, new_subscriber)
+)]
+async fn resubscribe(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_subscriber: &NewSubscriber,
+) -> Result<Option<Vec<String>>, sqlx::Error> {
+    let email = new_subscriber.email.as_ref();
+    let suppression_reason = suppression_reason(&mut *transaction, email).await?;
+    if suppression_reason.as_deref().is_some_and(is_permanent) {
+        tracing::info!("Keeping the permanent suppression of a subscriber signing up again.");
+        return Ok(None);
+    }
+    lift_suppression(&mut *transaction, email).await?;
+    sqlx::query!(
+        r#"UPDATE subscriptions
+        SET status = 'pending_confirmation', name = $2,
+            confirmed_at = NULL, confirmation_token_hash = NULL
+        WHERE id = $1"#,
+        subscriber_id,
+        new_subscriber.name.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let voided_tokens = sqlx::query_scalar!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1 RETURNING subscription_token",
+        subscriber_id
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    record_audit_entry(
+        &mut *transaction,
+        None,
+        SUBSCRIBER_RESUBSCRIBED,
+        &subscriber_id.to_string(),
+        serde_json::json!({ "suppression_reason": suppression_reason }),
+    )
+    .await?;
+    tracing::info!("Lifted the suppression of a subscriber signing up again.");
+    Ok(Some(voided_tokens))
+}
+
+/// How many tokens to generate before giving up on finding an unused one.
+pub struct TokenGenerationAttempts(pub u32);
+
+/// The most subscribers, confirmed or pending, to accept; `None` is unlimited.
+pub struct SubscriberQuota(pub Option<u64>);
+
+/// Answer every successful subscription with a `SubscribeResponse`, not only
+/// those of clients asking for JSON; shared as app data.
+pub struct JsonSubscribeResponse(pub bool);
+
+/// The body of a successful subscription, for the clients that want one.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct SubscribeResponse {
+    pub id: Uuid,
+    pub status: String,
+}
+
+/// Whether to answer with a `SubscribeResponse` rather than an empty body:
+/// when configured to, or when the client's `Accept` header asks for JSON.
+pub struct WantsJsonResponse(bool);
+
+impl FromRequest for WantsJsonResponse {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let configured = req
+            .app_data::<web::Data<JsonSubscribeResponse>>()
+            .is_some_and(|j| j.0);
+        let accepts_json = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|h| h.to_str().ok())
+            .is_some_and(|accept| {
+                accept
+                    .split(',')
+                    .any(|media_type| media_type.trim().starts_with("application/json"))
+            });
+        ready(Ok(Self(configured || accepts_json)))
+    }
+}
+
+/// The header a client sends its idempotency key in.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// The longest `Idempotency-Key` accepted.
+pub(crate) const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// The client's `Idempotency-Key` header: a retried subscription carrying
+/// the same key is answered as the first one was, instead of being processed
+/// again.
+pub struct IdempotencyKey(Option<String>);
+
+impl FromRequest for IdempotencyKey {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let Some(value) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+            return ready(Ok(Self(None)));
+        };
+        let key = value
+            .to_str()
+            .ok()
+            .filter(|key| !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH);
+        ready(match key {
+            Some(key) => Ok(Self(Some(key.to_owned()))),
+            None => {
+                let message = format!(
+                    "The Idempotency-Key header must hold 1 to {} visible ASCII characters.",
+                    MAX_IDEMPOTENCY_KEY_LENGTH
+                );
+                let response =
+                    json_error(StatusCode::BAD_REQUEST, "invalid_idempotency_key", &message);
+                Err(InternalError::from_response(message, response).into())
+            }
+        })
+    }
+}
+
+/// What subscribing does about an address whose domain publishes no MX
+/// records, e.g. a typo like `gmial.com`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MxCheck {
+    #[default]
+    Disabled,
+    Warn,
+    Reject,
+}
+
+/// The MX check of new subscribers' addresses, shared as app data; nothing
+/// is checked without a resolver.
+#[derive(Default)]
+pub struct EmailDomainCheck {
+    pub mode: MxCheck,
+    pub resolver: Option<DnsResolver>,
+}
+
+impl EmailDomainCheck {
+    pub fn new(mode: MxCheck) -> Self {
+        if mode == MxCheck::Disabled {
+            return Self::default();
+        }
+        match DnsResolver::from_system_conf() {
+            Ok(resolver) => Self {
+                mode,
+                resolver: Some(resolver),
+            },
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to set up a DNS resolver, email domains will not be checked");
+                Self::default()
+            }
+        }
+    }
+
+    /// Warn about an address whose domain accepts no email, and reject it
+    /// when configured to.
+    async fn check(&self, email: &SubscriberEmail) -> Result<(), SubscribeError> {
+        let Some(resolver) = &self.resolver else {
+            return Ok(());
+        };
+        if email.verify_deliverable(resolver).await {
+            return Ok(());
+        }
+        tracing::warn!("The email domain publishes no MX records: the address may be mistyped.");
+        match self.mode {
+            MxCheck::Reject => Err(SubscribeError::ValidationError(vec![FieldError::new(
+                "email",
+                "undeliverable",
+                "The domain of the email address does not accept email.",
+            )])),
+            MxCheck::Disabled | MxCheck::Warn => Ok(()),
+        }
+    }
+}
+
+/// The tokens issued to a subscriber, each kind stored in its own table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Sent in the confirmation link, and consumed on confirming.
+    Confirmation,
+    /// Sent in the links to manage the subscription, and kept for good.
+    Management,
+}
+
+/// The management token of `subscriber_id`, to link to from their emails;
+/// `None` if they have none.
+#[tracing::instrument(name = "Get a management token", skip(executor))]
+pub async fn management_token(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT management_token FROM management_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Store a `kind` token from `generate` for `subscriber_id`, generating a new
+/// one if it is already taken, up to `max_attempts` times.
+#[tracing::instrument(name = "Store a new subscription token", skip(transaction, generate))]
+pub async fn store_new_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    kind: TokenKind,
+    subscriber_id: Uuid,
+    max_attempts: u32,
+    mut generate: impl FnMut() -> String,
+) -> Result<String, anyhow::Error> {
+    for attempt in 1..=max_attempts {
+        let token = generate();
+        if store_token(transaction, kind, subscriber_id, &token).await? {
+            return Ok(token);
+        }
+        tracing::warn!(attempt, "The generated subscription token is already taken");
+    }
+    anyhow::bail!(
+        "Failed to generate an unused subscription token in {} attempts.",
+        max_attempts
+    )
+}
+
+/// Returns `false`, storing nothing, if the token is already taken.
+#[tracing::instrument(
+    name = "Store subscription token in the database",
+    skip(token, transaction),
+    fields(rows_affected = tracing::field::Empty)
+)]
+async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    kind: TokenKind,
+    subscriber_id: Uuid,
+    token: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = match kind {
+        TokenKind::Confirmation => {
+            sqlx::query!(
+                r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+                VALUES ($1, $2)
+                ON CONFLICT (subscription_token) DO NOTHING"#,
+                token,
+                subscriber_id
+            )
+            .execute(&mut *transaction)
+            .await?
+        }
+        TokenKind::Management => {
+            sqlx::query!(
+                r#"INSERT INTO management_tokens (management_token, subscriber_id)
+                VALUES ($1, $2)
+                ON CONFLICT (management_token) DO NOTHING"#,
+                token,
+                subscriber_id
+            )
+            .execute(&mut *transaction)
+            .await?
+        }
+    };
+    tracing::Span::current().record("rows_affected", result.rows_affected());
+    Ok(result.rows_affected() == 1)
 }
@@ -0,0 +1,399 @@
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::clock::Clock;
+use crate::db_pool::{acquire, begin_transaction};
+use crate::domain::SubscriberEmail;
+use crate::email_templates::{manage_link, EmailContent};
+use crate::email_worker::enqueue_email;
+use crate::error::{error_chain_fmt, json_error, see_other, unexpected_error};
+use crate::events::{ConfirmedEvent, EventSink};
+use crate::metrics::Metrics;
+use crate::retry::retry_read;
+use crate::routes::management_token;
+use crate::startup::ApplicationBaseUrl;
+use crate::token_cache::TokenCache;
+
+/// Where to send subscribers once confirmed; `None` answers with a bare 200.
+/// Only ever holds a URL that passed the allowlist.
+pub struct ConfirmationRedirect(pub Option<String>);
+
+/// The email sent to subscribers once confirmed; `None` sends nothing.
+pub struct WelcomeEmail(pub Option<EmailContent>);
+
+/// How long a confirmation token stays valid after being issued.
+pub struct ConfirmationTokenTtl(pub chrono::Duration);
+
+#[derive(serde::Deserialize)]
+struct Parameters {
+    subscription_token: String,
+}
+
+/// The token being confirmed, taken from the path of
+/// `/subscriptions/confirm/{subscription_token}`, or else from the query of
+/// `/subscriptions/confirm?subscription_token=...`: some email clients mangle
+/// query strings.
+pub struct ConfirmationToken(String);
+
+impl FromRequest for ConfirmationToken {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        if let Some(token) = req.match_info().get("subscription_token") {
+            return ready(Ok(Self(token.to_owned())));
+        }
+        ready(
+            web::Query::<Parameters>::from_query(req.query_string())
+                .map(|parameters| Self(parameters.into_inner().subscription_token))
+                .map_err(Into::into),
+        )
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum ConfirmationError {
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error("The confirmation link has expired, please subscribe again.")]
+    ExpiredToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConfirmationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmationError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ConfirmationError::UnknownToken => {
+                json_error(StatusCode::UNAUTHORIZED, "unknown_token", self.to_string())
+            }
+            ConfirmationError::ExpiredToken => {
+                json_error(StatusCode::GONE, "expired_token", self.to_string())
+            }
+            ConfirmationError::UnexpectedError(e) => unexpected_error(e),
+        }
+    }
+}
+
+/// Confirm the subscriber and delete their token in the same transaction.
+/// The hash of the consumed token is kept, so that following the link again
+/// still answers with a 200.
+///
+/// A token older than `ConfirmationTokenTtl`, going by the database's clock,
+/// is rejected with a 410.
+///
+/// Tokens in the `TokenCache`, which expire on its own clock, skip the lookup;
+/// a token the cache misses is looked up in the database.
+///
+/// A subscriber confirmed for the first time is sent the `WelcomeEmail`.
+///
+/// Once confirmed, the subscriber is reported to the `EventSink`; failing to
+/// report them is logged, and does not fail the confirmation.
+///
+/// The span records the token's hash, never the token itself, along with the
+/// subscriber and the `outcome`: `confirmed`, `already_confirmed`, `expired`
+/// or `unknown`.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(
+        token,
+        pool,
+        redirect,
+        metrics,
+        clock,
+        ttl,
+        token_cache,
+        event_sink,
+        welcome_email,
+        base_url
+    ),
+    fields(
+        token_hash = tracing::field::Empty,
+        subscriber_id = tracing::field::Empty,
+        outcome = tracing::field::Empty
+    )
+)]
+pub async fn confirm(
+    token: ConfirmationToken,
+    pool: web::Data<PgPool>,
+    redirect: web::Data<ConfirmationRedirect>,
+    metrics: web::Data<Metrics>,
+    clock: web::Data<dyn Clock>,
+    ttl: web::Data<ConfirmationTokenTtl>,
+    token_cache: web::Data<TokenCache>,
+    event_sink: web::Data<dyn EventSink>,
+    welcome_email: web::Data<WelcomeEmail>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ConfirmationError> {
+    let span = tracing::Span::current();
+    let token = &token.0;
+    let token_hash = hash_token(token);
+    span.record("token_hash", &token_hash[..]);
+    let issued = match token_cache.get(token) {
+        Some(cached) => Some(IssuedToken {
+            subscriber_id: cached.subscriber_id,
+            expired: cached.expired,
+        }),
+        None => retry_read(|| get_subscriber_id_from_token(&pool, &metrics, token, ttl.0))
+            .await
+            .context("Failed to retrieve the subscriber id associated with the provided token.")?,
+    };
+    let Some(IssuedToken {
+        subscriber_id,
+        expired,
+    }) = issued
+    else {
+        let confirmed_id = retry_read(|| confirmed_with(&pool, &metrics, &token_hash))
+            .await
+            .context("Failed to look up the consumed token.")?;
+        return match confirmed_id {
+            Some(subscriber_id) => {
+                span.record("subscriber_id", tracing::field::display(subscriber_id))
+                    .record("outcome", "already_confirmed");
+                Ok(confirmed_response(&redirect))
+            }
+            None => {
+                span.record("outcome", "unknown");
+                Err(ConfirmationError::UnknownToken)
+            }
+        };
+    };
+    span.record("subscriber_id", tracing::field::display(subscriber_id));
+    if expired {
+        span.record("outcome", "expired");
+        return Err(ConfirmationError::ExpiredToken);
+    }
+
+    let mut transaction = begin_transaction(&pool, &metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let confirmed = confirm_subscriber(&mut transaction, subscriber_id, &token_hash)
+        .await
+        .context("Failed to update the subscriber status to `confirmed`.")?;
+    let Some(confirmed) = confirmed else {
+        // The subscriber was deleted, or e.g. suppressed, after their token
+        // was issued.
+        token_cache.remove(token);
+        span.record("outcome", "unknown");
+        return Err(ConfirmationError::UnknownToken);
+    };
+    if let (true, Some(welcome_email)) = (confirmed.was_pending, &welcome_email.0) {
+        enqueue_welcome_email(
+            &mut transaction,
+            subscriber_id,
+            confirmed.email,
+            welcome_email,
+            &base_url.0,
+        )
+        .await
+        .context("Failed to queue the welcome email.")?;
+    }
+    delete_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to delete the consumed subscription token.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+    token_cache.remove(token);
+    span.record("outcome", "confirmed");
+    metrics.confirmations_completed.inc();
+    let event = ConfirmedEvent {
+        subscriber_id,
+        confirmed_at: clock.now(),
+    };
+    if let Err(e) = event_sink.subscriber_confirmed(&event).await {
+        tracing::warn!(error.cause_chain = ?e, "Failed to report the confirmed subscriber");
+    }
+    Ok(confirmed_response(&redirect))
+}
+
+fn confirmed_response(redirect: &ConfirmationRedirect) -> HttpResponse {
+    match &redirect.0 {
+        Some(url) => see_other(url),
+        None => HttpResponse::Ok().finish(),
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// A subscriber whose status was just set to `confirmed`.
+struct ConfirmedSubscriber {
+    email: String,
+    /// Whether they were pending confirmation, rather than e.g. confirmed by
+    /// an earlier token of theirs.
+    was_pending: bool,
+}
+
+/// Returns `None` if the subscriber no longer exists, or is neither pending
+/// nor confirmed: a suppressed subscriber must not be brought back by an old
+/// confirmation link.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(transaction, token_hash),
+    fields(rows_affected = tracing::field::Empty)
+)]
+async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    token_hash: &str,
+) -> Result<Option<ConfirmedSubscriber>, sqlx::Error> {
+    // `RETURNING` only sees the new row: the previous status comes from a
+    // locked read of the old one.
+    let row = sqlx::query!(
+        r#"
+        UPDATE subscriptions s
+        SET status = 'confirmed', confirmation_token_hash = $2, confirmed_at = now()
+        FROM (
+            SELECT id, status FROM subscriptions
+            WHERE id = $1 AND status IN ('pending_confirmation', 'confirmed')
+            FOR UPDATE
+        ) previous
+        WHERE s.id = previous.id
+        RETURNING s.email, previous.status AS previous_status
+        "#,
+        subscriber_id,
+        token_hash,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    tracing::Span::current().record("rows_affected", u64::from(row.is_some()));
+    if row.is_none() {
+        tracing::warn!("Confirming the subscriber updated no rows.");
+    }
+    Ok(row.map(|r| ConfirmedSubscriber {
+        email: r.email,
+        was_pending: r.previous_status == "pending_confirmation",
+    }))
+}
+
+/// Queue the welcome email in `transaction`, so that it goes out once and
+/// only if the confirmation is committed.
+#[tracing::instrument(name = "Queue a welcome email", skip_all)]
+async fn enqueue_welcome_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: String,
+    welcome_email: &EmailContent,
+    base_url: &str,
+) -> Result<(), sqlx::Error> {
+    let recipient = match SubscriberEmail::parse(email) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            tracing::warn!(error = %e, "Skipping the welcome email to an invalid address");
+            return Ok(());
+        }
+    };
+    let manage_link = management_token(&mut *transaction, subscriber_id)
+        .await?
+        .map(|token| manage_link(base_url, &token));
+    let welcome_email = welcome_email
+        .clone()
+        .with_manage_link(manage_link.as_deref());
+    enqueue_email(
+        &mut *transaction,
+        &recipient,
+        &welcome_email.subject,
+        &welcome_email.html_body,
+        &welcome_email.text_body,
+        Utc::now(),
+    )
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Delete the subscriber's tokens",
+    skip(transaction),
+    fields(rows_affected = tracing::field::Empty)
+)]
+async fn delete_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    tracing::Span::current().record("rows_affected", result.rows_affected());
+    Ok(())
+}
+
+/// The subscriber confirmed with the token hashing to `token_hash`, if any.
+#[tracing::instrument(name = "Look up a consumed token", skip(pool, metrics))]
+async fn confirmed_with(
+    pool: &PgPool,
+    metrics: &Metrics,
+    token_hash: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let mut connection = acquire(pool, metrics).await?;
+    let row = sqlx::query!(
+        r#"
+        SELECT id FROM subscriptions
+        WHERE confirmation_token_hash = $1 AND status = 'confirmed'
+        "#,
+        token_hash,
+    )
+    .fetch_optional(&mut connection)
+    .await?;
+    Ok(row.map(|r| r.id))
+}
+
+/// A token found in the cache or the database.
+struct IssuedToken {
+    subscriber_id: Uuid,
+    /// Older than the `ConfirmationTokenTtl`.
+    expired: bool,
+}
+
+/// The subscriber the token was issued to, and whether it has expired.
+///
+/// The token's age is measured on the database's clock, which stamped its
+/// `created_at`: comparing it with ours would expire tokens early, or late,
+/// by however far the two clocks drift apart.
+#[tracing::instrument(
+    name = "Get subscriber_id from token",
+    skip(subscription_token, pool, metrics)
+)]
+async fn get_subscriber_id_from_token(
+    pool: &PgPool,
+    metrics: &Metrics,
+    subscription_token: &str,
+    ttl: chrono::Duration,
+) -> Result<Option<IssuedToken>, sqlx::Error> {
+    let mut connection = acquire(pool, metrics).await?;
+    let result = sqlx::query!(
+        r#"
+        SELECT subscriber_id, created_at < now() - make_interval(secs => $2) AS "expired!"
+        FROM subscription_tokens
+        WHERE subscription_token = $1
+        "#,
+        subscription_token,
+        ttl.num_milliseconds() as f64 / 1000.0,
+    )
+    .fetch_optional(&mut connection)
+    .await?;
+    Ok(result.map(|r| IssuedToken {
+        subscriber_id: r.subscriber_id,
+        expired: r.expired,
+    }))
+}
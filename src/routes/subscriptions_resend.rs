@@ -0,0 +1,146 @@
+use std::time::{Duration, Instant};
+
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::db_pool::begin_transaction;
+use crate::domain::SubscriberEmail;
+use crate::error::{error_chain_fmt, json_error, unexpected_error};
+use crate::form::StrictForm;
+use crate::metrics::Metrics;
+use crate::pii::PiiLogging;
+use crate::rate_limit::{rate_limited, RateLimiters};
+use crate::routes::{
+    enqueue_confirmation_email, generate_subscription_token, store_new_token,
+    TokenGenerationAttempts, TokenKind,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppressions::is_suppressed;
+
+#[derive(serde::Deserialize)]
+pub struct ResendForm {
+    email: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum ResendError {
+    #[error("{0}")]
+    InvalidEmail(String),
+    #[error("A confirmation was resent to this address too recently.")]
+    TooSoon(Duration),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ResendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ResendError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ResendError::InvalidEmail(_) => json_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_email",
+                self.to_string(),
+            ),
+            ResendError::TooSoon(retry_after) => rate_limited(*retry_after),
+            ResendError::UnexpectedError(e) => unexpected_error(e),
+        }
+    }
+}
+
+/// Email a pending subscriber a fresh confirmation link.
+///
+/// The cooldown is counted per address whether or not it is subscribed, and
+/// the answer is the same empty 200 either way, so that the endpoint tells
+/// nobody which addresses are. The email is queued rather than sent, which
+/// keeps a known address from taking noticeably longer to answer.
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(form, pool, metrics, base_url, token_attempts, limiters, pii),
+    fields(subscriber_email = %pii.email(&form.email))
+)]
+pub async fn resend_confirmation(
+    form: StrictForm<ResendForm>,
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    token_attempts: web::Data<TokenGenerationAttempts>,
+    limiters: web::Data<RateLimiters>,
+    pii: web::Data<PiiLogging>,
+) -> Result<HttpResponse, ResendError> {
+    let email = SubscriberEmail::parse(form.0.email).map_err(ResendError::InvalidEmail)?;
+    if let Some(limiter) = &limiters.confirmation_resend {
+        // Addresses are matched case-insensitively by mail servers: so is the
+        // cooldown, lest changing the case of a letter get around it.
+        limiter
+            .check(&email.as_ref().to_lowercase(), Instant::now())
+            .map_err(ResendError::TooSoon)?;
+    }
+
+    let mut transaction = begin_transaction(&pool, &metrics)
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let Some(subscriber_id) = pending_subscriber(&mut transaction, &email)
+        .await
+        .context("Failed to look up the pending subscriber.")?
+    else {
+        tracing::info!("No pending subscriber to resend a confirmation to.");
+        return Ok(HttpResponse::Ok().finish());
+    };
+    if is_suppressed(&mut transaction, email.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!("Skipping the confirmation email to a suppressed address.");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    // A fresh token: the ones sent before may be about to expire.
+    let subscription_token = store_new_token(
+        &mut transaction,
+        TokenKind::Confirmation,
+        subscriber_id,
+        token_attempts.0,
+        generate_subscription_token,
+    )
+    .await
+    .context("Failed to store a new confirmation token.")?;
+    enqueue_confirmation_email(
+        &mut transaction,
+        subscriber_id,
+        &email,
+        &base_url.0,
+        &subscription_token,
+        Utc::now(),
+    )
+    .await
+    .context("Failed to queue a confirmation email.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to resend a confirmation.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// The id of the subscriber with `email`, if they are pending confirmation.
+#[tracing::instrument(name = "Get a pending subscriber", skip(transaction, email))]
+async fn pending_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE email = $1 AND status = 'pending_confirmation'",
+        email.as_ref()
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.map(|r| r.id))
+}
@@ -0,0 +1,83 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::error::{error_chain_fmt, json_error, unexpected_error};
+use crate::retry::retry_read;
+
+#[derive(serde::Deserialize)]
+pub struct StatusQuery {
+    token: String,
+}
+
+/// What a subscriber sees of their own subscription.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct SubscriptionStatus {
+    pub email: String,
+    pub status: String,
+    /// The names of the topics they follow, in alphabetical order.
+    pub topics: Vec<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum StatusError {
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for StatusError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            StatusError::UnknownToken => {
+                json_error(StatusCode::UNAUTHORIZED, "unknown_token", self.to_string())
+            }
+            StatusError::UnexpectedError(e) => unexpected_error(e),
+        }
+    }
+}
+
+/// The subscription of the holder of a management token, as linked from the
+/// emails they receive.
+#[tracing::instrument(name = "Get the status of a subscription", skip(query, pool))]
+pub async fn subscription_status(
+    query: web::Query<StatusQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, StatusError> {
+    let status = retry_read(|| get_subscription_status(&pool, &query.token))
+        .await
+        .context("Failed to retrieve the status of the subscription.")?
+        .ok_or(StatusError::UnknownToken)?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+#[tracing::instrument(name = "Get a subscription by management token", skip(pool, token))]
+async fn get_subscription_status(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<SubscriptionStatus>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriptionStatus,
+        r#"
+        SELECT s.email, s.status,
+            ARRAY(
+                SELECT topic FROM subscriber_topics
+                WHERE subscriber_id = s.id ORDER BY topic
+            ) AS "topics!"
+        FROM management_tokens m
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE m.management_token = $1
+        "#,
+        token
+    )
+    .fetch_optional(pool)
+    .await
+}
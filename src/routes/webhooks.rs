@@ -0,0 +1,171 @@
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::http::header::{HeaderMap, HeaderValue};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use base64::Engine;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+
+use crate::configuration::WebhookCredentials;
+use crate::db_pool::begin_transaction;
+use crate::error::{error_chain_fmt, json_error, unexpected_error};
+use crate::metrics::Metrics;
+use crate::suppressions::{suppress, suppress_subscriber};
+
+/// The subset of Postmark's webhook payloads we act upon.
+/// Every other `RecordType` (deliveries, opens, ...) is acknowledged and ignored.
+#[derive(serde::Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    Bounce {
+        #[serde(rename = "Type")]
+        bounce_type: String,
+        #[serde(rename = "Email")]
+        email: String,
+    },
+    SpamComplaint {
+        #[serde(rename = "Email")]
+        email: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl PostmarkEvent {
+    /// The address we must stop emailing and why, if the event calls for it.
+    fn address_to_suppress(&self) -> Option<(&str, &'static str)> {
+        match self {
+            PostmarkEvent::Bounce { bounce_type, email } if bounce_type == "HardBounce" => {
+                Some((email, "hard_bounce"))
+            }
+            PostmarkEvent::SpamComplaint { email } => Some((email, "spam_complaint")),
+            _ => None,
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            WebhookError::AuthError(_) => {
+                let mut response = json_error(
+                    StatusCode::UNAUTHORIZED,
+                    "unauthorized",
+                    "Invalid webhook credentials.",
+                );
+                let header_value = HeaderValue::from_str(r#"Basic realm="postmark""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+            WebhookError::UnexpectedError(e) => unexpected_error(e),
+        }
+    }
+}
+
+/// Proof that the request carries the webhook's Basic-auth credentials.
+/// Listed before the body, it turns an unauthenticated request away before
+/// the body is read.
+pub struct PostmarkAuth;
+
+impl FromRequest for PostmarkAuth {
+    type Error = WebhookError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let Some(credentials) = req.app_data::<web::Data<WebhookCredentials>>() else {
+            return ready(Err(WebhookError::UnexpectedError(anyhow::anyhow!(
+                "The webhook credentials are not configured"
+            ))));
+        };
+        ready(
+            verify_credentials(req.headers(), credentials)
+                .map(|()| PostmarkAuth)
+                .map_err(WebhookError::AuthError),
+        )
+    }
+}
+
+#[tracing::instrument(
+    name = "Handling a Postmark webhook",
+    skip(_auth, event, pool, metrics)
+)]
+pub async fn postmark_webhook(
+    _auth: PostmarkAuth,
+    event: web::Json<PostmarkEvent>,
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, WebhookError> {
+    if let Some((email, reason)) = event.address_to_suppress() {
+        let mut transaction = begin_transaction(&pool, &metrics)
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        suppress_subscriber(&mut transaction, email)
+            .await
+            .context("Failed to suppress the subscriber")?;
+        suppress(&mut transaction, email, reason)
+            .await
+            .context("Failed to add the address to the suppression list")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to suppress an address.")?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn verify_credentials(
+    headers: &HeaderMap,
+    expected: &WebhookCredentials,
+) -> Result<(), anyhow::Error> {
+    let header_value = headers
+        .get(header::AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .context("A password must be provided in 'Basic' auth.")?;
+    let password = Secret::new(password.to_string());
+
+    // Both fields are compared in constant time, and both always are, so
+    // that the timing tells nothing about either.
+    let username_matches = username.as_bytes().ct_eq(expected.username.as_bytes());
+    let password_matches = password
+        .expose_secret()
+        .as_bytes()
+        .ct_eq(expected.password.expose_secret().as_bytes());
+    if !bool::from(username_matches & password_matches) {
+        anyhow::bail!("Invalid username or password.");
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Checking at startup that the sender domain publishes the SPF and DKIM
+//! records receiving servers look for, so that a deliverability
+//! misconfiguration shows up in our logs rather than in spam folders.
+use crate::dns::TxtResolver;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissingRecord {
+    Spf,
+    Dkim,
+}
+
+impl std::fmt::Display for MissingRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spf => write!(f, "SPF"),
+            Self::Dkim => write!(f, "DKIM"),
+        }
+    }
+}
+
+/// The records found missing on start that the readiness probe reports;
+/// empty when the check is off, passed, or only warns.
+#[derive(Debug, Default)]
+pub struct SenderDomainProblems(pub Vec<MissingRecord>);
+
+/// Warn about each record `domain` does not publish: its SPF policy, and the
+/// DKIM key of `dkim_selector` when given. A failed lookup counts as a
+/// missing record.
+#[tracing::instrument(name = "Checking the sender domain", skip(resolver))]
+pub async fn check_sender_domain(
+    resolver: &impl TxtResolver,
+    domain: &str,
+    dkim_selector: Option<&str>,
+) -> Vec<MissingRecord> {
+    let mut checks = vec![(MissingRecord::Spf, domain.to_owned(), "v=spf1")];
+    if let Some(selector) = dkim_selector {
+        checks.push((
+            MissingRecord::Dkim,
+            format!("{}._domainkey.{}", selector, domain),
+            "v=DKIM1",
+        ));
+    }
+
+    let mut missing = Vec::new();
+    for (record, name, prefix) in checks {
+        let found = match resolver.txt_records(&name).await {
+            Ok(records) => records.iter().any(|r| r.trim_start().starts_with(prefix)),
+            Err(e) => {
+                tracing::warn!(error = %e, name, "Failed to look up the sender domain's records");
+                false
+            }
+        };
+        if !found {
+            tracing::warn!(
+                name,
+                "The sender domain has no {} record: emails may end up in spam",
+                record
+            );
+            missing.push(record);
+        }
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use trust_dns_resolver::error::ResolveError;
+
+    use super::{check_sender_domain, MissingRecord, TxtResolver};
+    use crate::telemetry::{get_subscriber, CapturedLogs};
+
+    /// Answers from a fixed set of records.
+    struct MockResolver(HashMap<&'static str, &'static str>);
+
+    impl TxtResolver for MockResolver {
+        async fn txt_records(&self, name: &str) -> Result<Vec<String>, ResolveError> {
+            Ok(self
+                .0
+                .get(name)
+                .map(|r| r.to_string())
+                .into_iter()
+                .collect())
+        }
+    }
+
+    /// What the check finds, and the warnings it logs.
+    async fn check(records: &[(&'static str, &'static str)]) -> (Vec<MissingRecord>, String) {
+        let captured = CapturedLogs::default();
+        let subscriber = get_subscriber("test".into(), "warn".into(), captured.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let resolver = MockResolver(records.iter().copied().collect());
+
+        let missing = check_sender_domain(&resolver, "example.com", Some("mail")).await;
+
+        let logs = captured.contents();
+        (missing, logs)
+    }
+
+    const SPF: (&str, &str) = ("example.com", "v=spf1 include:spf.mtasv.net ~all");
+    const DKIM: (&str, &str) = ("mail._domainkey.example.com", "v=DKIM1; k=rsa; p=MIGf");
+
+    #[tokio::test]
+    async fn a_missing_spf_record_is_warned_about() {
+        let (missing, logs) = check(&[DKIM]).await;
+
+        assert_eq!(missing, vec![MissingRecord::Spf]);
+        assert!(logs.contains("no SPF record"), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn a_present_spf_record_is_not_warned_about() {
+        let (missing, logs) = check(&[SPF, DKIM]).await;
+
+        assert!(missing.is_empty());
+        assert!(!logs.contains("no SPF record"), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn a_missing_dkim_key_is_warned_about() {
+        let (missing, logs) = check(&[SPF]).await;
+
+        assert_eq!(missing, vec![MissingRecord::Dkim]);
+        assert!(logs.contains("no DKIM record"), "{}", logs);
+    }
+}
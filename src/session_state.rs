@@ -0,0 +1,62 @@
+use std::future::{ready, Ready};
+
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// A strongly-typed wrapper around the admin's session.
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+    const CREATED_AT_KEY: &'static str = "created_at";
+    const LAST_SEEN_AT_KEY: &'static str = "last_seen_at";
+
+    pub fn renew(&self) {
+        self.0.renew();
+    }
+
+    pub fn purge(&self) {
+        self.0.purge();
+    }
+
+    /// Start a fresh session for `user_id` at `now`.
+    pub fn log_in(&self, user_id: Uuid, now: DateTime<Utc>) -> Result<(), SessionInsertError> {
+        self.renew();
+        self.insert_user_id(user_id)?;
+        self.0.insert(Self::CREATED_AT_KEY, now)?;
+        self.touch(now)
+    }
+
+    /// Record activity at `now`, sliding the idle timeout.
+    pub fn touch(&self, now: DateTime<Utc>) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::LAST_SEEN_AT_KEY, now)
+    }
+
+    pub fn get_created_at(&self) -> Result<Option<DateTime<Utc>>, SessionGetError> {
+        self.0.get(Self::CREATED_AT_KEY)
+    }
+
+    pub fn get_last_seen_at(&self) -> Result<Option<DateTime<Utc>>, SessionGetError> {
+        self.0.get(Self::LAST_SEEN_AT_KEY)
+    }
+
+    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)
+    }
+
+    pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::USER_ID_KEY)
+    }
+}
+
+impl FromRequest for TypedSession {
+    type Error = <Session as FromRequest>::Error;
+    type Future = Ready<Result<TypedSession, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(TypedSession(req.get_session())))
+    }
+}
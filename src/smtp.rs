@@ -0,0 +1,247 @@
+//! Handing emails over to an SMTP relay, built on `lettre`: it upgrades the
+//! connection with STARTTLS unless told the relay sits on a trusted network
+//! (e.g. a sidecar or a local mail catcher), and authenticates with
+//! `AUTH PLAIN` only over TLS.
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+use crate::configuration::SmtpTls;
+use crate::domain::SubscriberEmail;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SmtpError {
+    #[error("Failed to talk to the SMTP server")]
+    Transport(#[from] lettre::transport::smtp::Error),
+    #[error("The SMTP server did not answer in time")]
+    Timeout,
+    #[error("The {0} header contains a line break")]
+    HeaderInjection(&'static str),
+    #[error("Failed to parse an email address")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("Failed to build the email")]
+    Message(#[from] lettre::error::Error),
+}
+
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+/// An email ready to be handed over to the relay.
+pub struct Message<'a> {
+    pub from: &'a SubscriberEmail,
+    pub to: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+}
+
+impl SmtpTransport {
+    /// Fails if no TLS client can be set up for `host`. Credentials are only
+    /// ever sent over TLS: configuration validation rejects them along with
+    /// `SmtpTls::None`.
+    pub fn new(
+        host: String,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, Secret<String>)>,
+    ) -> Result<Self, SmtpError> {
+        let tls = match tls {
+            SmtpTls::Starttls => Tls::Required(TlsParameters::new(host.clone())?),
+            SmtpTls::None => Tls::None,
+        };
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+            .port(port)
+            .tls(tls);
+        if let Some((username, password)) = credentials {
+            builder = builder
+                .credentials(Credentials::new(
+                    username,
+                    password.expose_secret().to_owned(),
+                ))
+                .authentication(vec![Mechanism::Plain]);
+        }
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+
+    pub async fn send(
+        &self,
+        message: &Message<'_>,
+        timeout: std::time::Duration,
+    ) -> Result<(), SmtpError> {
+        let email = build_message(message)?;
+        tokio::time::timeout(timeout, self.transport.send(email))
+            .await
+            .map_err(|_| SmtpError::Timeout)??;
+        Ok(())
+    }
+}
+
+/// The MIME message carrying both versions of the body. `lettre` adds the
+/// `Date`, encodes a non-ASCII subject, picks a random boundary and a
+/// transfer encoding that keeps lines short.
+fn build_message(message: &Message<'_>) -> Result<lettre::Message, SmtpError> {
+    // A line break would start a header of the caller's choosing.
+    if message.subject.contains(['\r', '\n']) {
+        return Err(SmtpError::HeaderInjection("Subject"));
+    }
+    let from: Address = message.from.as_ref().parse()?;
+    let to: Address = message.to.as_ref().parse()?;
+    let message_id = format!("<{}@{}>", Uuid::new_v4(), from.domain());
+    let email = lettre::Message::builder()
+        .from(Mailbox::new(None, from))
+        .to(Mailbox::new(None, to))
+        .subject(message.subject)
+        .message_id(Some(message_id))
+        .multipart(MultiPart::alternative_plain_html(
+            message.text_body.to_owned(),
+            message.html_body.to_owned(),
+        ))?;
+    Ok(email)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Message, SmtpError, SmtpTransport};
+    use crate::configuration::SmtpTls;
+    use crate::domain::SubscriberEmail;
+    use secrecy::Secret;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Play the server side of an SMTP session, which does not offer
+    /// STARTTLS, returning what the client sent.
+    async fn fake_server(listener: TcpListener) -> Vec<String> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        stream.get_mut().write_all(b"220 ready\r\n").await.unwrap();
+        let mut received = Vec::new();
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap() == 0 {
+                return received;
+            }
+            let line = line.trim_end().to_owned();
+            let reply: &[u8] = if in_data {
+                if line == "." {
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else {
+                    b""
+                }
+            } else if line.starts_with("EHLO") {
+                b"250-hello\r\n250 SIZE 1000000\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line == "QUIT" {
+                b"221 bye\r\n"
+            } else {
+                b"250 ok\r\n"
+            };
+            received.push(line);
+            stream.get_mut().write_all(reply).await.unwrap();
+        }
+    }
+
+    async fn send(
+        tls: SmtpTls,
+        credentials: Option<(String, Secret<String>)>,
+        subject: &str,
+        text_body: &str,
+    ) -> (Result<(), SmtpError>, Vec<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_server(listener));
+        let from = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let to = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+
+        let result = SmtpTransport::new("localhost".into(), port, tls, credentials)
+            .unwrap()
+            .send(
+                &Message {
+                    from: &from,
+                    to: &to,
+                    subject,
+                    html_body: "<p>Hi</p>",
+                    text_body,
+                },
+                std::time::Duration::from_secs(5),
+            )
+            .await;
+        (result, server.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn an_email_is_handed_over_to_the_server() {
+        let (result, received) = send(SmtpTls::None, None, "Welcome!", ".Hi").await;
+
+        result.unwrap();
+        assert!(received
+            .iter()
+            .any(|line| line.starts_with("MAIL FROM:<sender@example.com>")));
+        assert!(received
+            .iter()
+            .any(|line| line.starts_with("RCPT TO:<ursula@example.com>")));
+        assert!(received.contains(&"Subject: Welcome!".to_owned()));
+        assert!(received.iter().any(|line| line.starts_with("Date: ")));
+        assert!(received
+            .iter()
+            .any(|line| line.starts_with("Message-ID: <") && line.ends_with("@example.com>")));
+        // The leading dot is escaped.
+        assert!(received.contains(&"..Hi".to_owned()));
+        assert_eq!(received.last().unwrap(), "QUIT");
+    }
+
+    #[tokio::test]
+    async fn non_ascii_content_is_encoded() {
+        let text_body = "Voilà, à bientôt !";
+
+        let (result, received) = send(SmtpTls::None, None, "Bienvenue à bord", text_body).await;
+
+        result.unwrap();
+        assert!(received.contains(&"Subject: Bienvenue =?utf-8?b?w6A=?= bord".to_owned()));
+        assert!(received.contains(&"Content-Transfer-Encoding: quoted-printable".to_owned()));
+        assert!(received.iter().all(|line| line.is_ascii()));
+    }
+
+    #[tokio::test]
+    async fn a_subject_with_a_line_break_is_refused() {
+        let from = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let to = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+
+        // Refused before connecting: there is no server to talk to.
+        let result = SmtpTransport::new("localhost".into(), 25, SmtpTls::None, None)
+            .unwrap()
+            .send(
+                &Message {
+                    from: &from,
+                    to: &to,
+                    subject: "Hi\r\nBcc: everyone@example.com",
+                    html_body: "<p>Hi</p>",
+                    text_body: "Hi",
+                },
+                std::time::Duration::from_secs(5),
+            )
+            .await;
+
+        assert!(matches!(result, Err(SmtpError::HeaderInjection("Subject"))));
+    }
+
+    #[tokio::test]
+    async fn credentials_are_never_sent_without_starttls() {
+        let credentials = Some(("me".to_owned(), Secret::new("secret".to_owned())));
+
+        let (result, received) = send(SmtpTls::Starttls, credentials, "Welcome!", "Hi").await;
+
+        assert!(result.is_err());
+        assert!(!received.iter().any(|line| line.starts_with("AUTH")));
+        assert!(!received.iter().any(|line| line.starts_with("MAIL FROM")));
+    }
+}
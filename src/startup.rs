@@ -1,76 +1,680 @@
-use std::net::TcpListener;
+use std::net::{TcpListener, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::Arc;
 
+use actix_session::storage::CookieSessionStore;
+use actix_session::SessionMiddleware;
+use actix_web::cookie::Key;
 use actix_web::dev::Server;
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
 use sqlx::postgres::PgPoolOptions;
+use tokio::net::TcpSocket;
 use tracing_actix_web::TracingLogger;
 
+use secrecy::ExposeSecret;
 use sqlx::PgPool;
 
+use crate::access_log::{log_access, AccessLog};
+use crate::authentication::reject_anonymous_users;
+use crate::client_ip::TrustedProxies;
+use crate::clock::{Clock, SystemClock};
+use crate::concurrency_limit::{limit_concurrent_subscriptions, track_in_flight_requests};
 use crate::configuration::{DatabaseSettings, Settings};
+use crate::cors::cors;
+use crate::dns::DnsResolver;
 use crate::email_client::EmailClient;
+use crate::email_worker::run_worker_until_stopped;
+use crate::error::{
+    downgrade_validation_errors, form_error_handler, json_error_handler,
+    method_not_allowed_as_json, not_found, LegacyValidationStatus,
+};
+use crate::events::EventSink;
+use crate::feature_flags::{run_refresh_until_stopped, FeatureFlags};
+use crate::maintenance::{reject_during_maintenance, MaintenanceMode};
+use crate::metrics::Metrics;
+use crate::migrations::{pending_migrations, run_directory_migrations, MIGRATOR};
+use crate::rate_limit::{limit_logins, limit_subscriptions};
 use crate::routes::*;
+use crate::sender_domain::{check_sender_domain, SenderDomainProblems};
+use crate::task_supervisor::TaskSupervisor;
+use crate::telemetry::AppRootSpanBuilder;
+use crate::token_cache::TokenCache;
+use crate::token_sweeper::run_sweeper_until_stopped;
+use crate::topics::set_default_topic;
+
+/// How long background tasks get to wind down once the server has stopped.
+const TASK_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
 
 pub struct Application {
     port: u16,
     server: Server,
+    supervisor: TaskSupervisor,
 }
 
 impl Application {
     pub async fn build(config: &Settings) -> Result<Self, std::io::Error> {
-        let connection_pool = PgPoolOptions::new()
-            .acquire_timeout(std::time::Duration::from_secs(2))
-            .connect_lazy_with(config.database.with_db());
+        Self::build_with_clock(config, Arc::new(SystemClock)).await
+    }
+
+    /// Build the application with `clock` as its source of "now", e.g. a
+    /// `MockClock` in tests.
+    pub async fn build_with_clock(
+        config: &Settings,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, std::io::Error> {
+        let connection_pool = get_connection_pool(&config.database);
+        // Hotfix SQL builds on the embedded schema: apply both, in order.
+        if config.database.migrate_on_start || config.database.migrations_path.is_some() {
+            MIGRATOR
+                .run(&connection_pool)
+                .await
+                .map_err(std::io::Error::other)?;
+        }
+        if let Some(path) = &config.database.migrations_path {
+            run_directory_migrations(&connection_pool, Path::new(path))
+                .await
+                .map_err(std::io::Error::other)?;
+        }
+        warn_about_pending_migrations(&connection_pool).await;
+        if config.database.warmup {
+            match warm_up_pool(&connection_pool, config.database.min_connections).await {
+                Ok(probes) => tracing::info!(probes, "Warmed up the connection pool"),
+                Err(e) if config.database.warmup_strict => {
+                    return Err(std::io::Error::other(e));
+                }
+                Err(e) => tracing::warn!(error = %e, "Failed to warm up the connection pool"),
+            }
+        }
 
-        let timeout = config.email_client.timeout();
+        let default_topic = config
+            .application
+            .default_topic()
+            .map_err(std::io::Error::other)?;
+        if let Err(e) = set_default_topic(&connection_pool, &default_topic).await {
+            tracing::warn!(error = %e, "Failed to set the default topic");
+        }
 
-        let email_client = EmailClient::new(
-            config.email_client.base_url.clone(),
-            config.email_client.sender().expect("Invalid sender email"),
-            config.email_client.authorization_token.clone(),
-            timeout,
+        let sender_domain_problems = check_sender_domain_on_start(config).await;
+
+        let metrics = Metrics::new();
+        let email_client = config
+            .email_client
+            .client()
+            .map_err(std::io::Error::other)?;
+        let send_duration = metrics
+            .email_send_duration
+            .with_label_values(&[email_client.provider()]);
+        let email_client = Arc::new(
+            email_client
+                .with_send_duration(send_duration)
+                .with_pii_logging(config.application.pii_logging),
         );
 
-        let address = format!("{}:{}", config.application.host, config.application.port);
-        let listener = TcpListener::bind(address).expect("Failed to bind port");
-        let port = listener.local_addr().unwrap().port();
-        let server = run(listener, connection_pool, email_client)?;
+        let listener = match &config.application.socket_path {
+            #[cfg(unix)]
+            Some(socket_path) => Listener::Unix(bind_unix_socket(socket_path)?),
+            #[cfg(not(unix))]
+            Some(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "Unix domain sockets are not supported on this platform",
+                ))
+            }
+            None => {
+                let address = format!("{}:{}", config.application.host, config.application.port);
+                Listener::Tcp(
+                    bind_tcp(&address, config.application.backlog).expect("Failed to bind port"),
+                )
+            }
+        };
+        let port = match &listener {
+            Listener::Tcp(listener) => listener.local_addr().unwrap().port(),
+            #[cfg(unix)]
+            Listener::Unix(_) => 0,
+        };
+        let mut supervisor = TaskSupervisor::new();
+        if config.email_queue.worker_enabled {
+            let pool = connection_pool.clone();
+            let email_client = email_client.clone();
+            let settings = config.email_queue.clone();
+            let base_url = config.application.links_base_url();
+            let batch_pause_duration = metrics.email_batch_pause_duration.clone();
+            supervisor.spawn("email_worker", |token| {
+                run_worker_until_stopped(
+                    pool,
+                    email_client,
+                    settings,
+                    base_url,
+                    batch_pause_duration,
+                    token,
+                )
+            });
+        }
+        let feature_flags = Arc::new(FeatureFlags::new());
+        if let Err(e) = feature_flags.refresh(&connection_pool).await {
+            tracing::warn!(error = %e, "Failed to load feature flags");
+        }
+        {
+            let pool = connection_pool.clone();
+            let feature_flags = feature_flags.clone();
+            let interval = config.feature_flags.refresh_interval();
+            supervisor.spawn("feature_flags", |token| {
+                run_refresh_until_stopped(pool, feature_flags, interval, token)
+            });
+        }
+        log_effective_configuration(config, port, email_client.provider(), &feature_flags);
+        {
+            let pool = connection_pool.clone();
+            let interval = config.application.token_sweep_interval();
+            supervisor.spawn("token_sweeper", |token| {
+                run_sweeper_until_stopped(pool, interval, token)
+            });
+        }
+        let server = run(
+            listener,
+            connection_pool,
+            email_client,
+            feature_flags,
+            metrics,
+            sender_domain_problems,
+            clock,
+            config,
+        )?;
 
-        Ok(Self { server, port })
+        Ok(Self {
+            server,
+            port,
+            supervisor,
+        })
     }
 
+    /// The TCP port the server listens on; `0` when bound to a unix socket.
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// Serve requests until the server is shut down, then stop the
+    /// background tasks.
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let result = self.server.await;
+        self.supervisor.shutdown(TASK_SHUTDOWN_TIMEOUT).await;
+        result
+    }
+}
+
+/// Queries against an outdated schema fail at runtime, so say so early;
+/// `/ready` keeps reporting it until the migrations are applied.
+async fn warn_about_pending_migrations(pool: &PgPool) {
+    match pending_migrations(pool).await {
+        Ok(pending) if pending.is_empty() => {}
+        Ok(pending) => tracing::warn!(?pending, "The database is missing migrations"),
+        Err(e) => tracing::warn!(error = %e, "Failed to check for pending migrations"),
     }
 }
 
 pub fn get_connection_pool(config: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
         .acquire_timeout(std::time::Duration::from_secs(2))
+        .min_connections(config.min_connections)
         .connect_lazy_with(config.with_db())
 }
 
+/// Open `connections` connections, probing each with a `SELECT 1`; returns
+/// the number of probes issued.
+#[tracing::instrument(name = "Warming up the connection pool", skip(pool))]
+pub async fn warm_up_pool(pool: &PgPool, connections: u32) -> Result<u32, sqlx::Error> {
+    // Hold on to every connection until the end: released ones would be
+    // handed out again instead of new ones being opened.
+    let mut opened = Vec::with_capacity(connections as usize);
+    for _ in 0..connections {
+        let mut connection = pool.acquire().await?;
+        sqlx::query("SELECT 1").execute(&mut connection).await?;
+        opened.push(connection);
+    }
+    Ok(opened.len() as u32)
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// Log the settings that took effect once the configuration files and the
+/// environment are layered, along with the flags enabled at startup. Secrets
+/// are left out, as are URLs that may embed credentials.
+fn log_effective_configuration(
+    config: &Settings,
+    port: u16,
+    email_provider: &str,
+    feature_flags: &FeatureFlags,
+) {
+    tracing::info!(
+        host = %config.application.host,
+        port,
+        socket_path = ?config.application.socket_path,
+        base_url = %config.application.base_url,
+        base_path = %config.application.base_path,
+        database_host = %config.database.host,
+        database_port = config.database.port,
+        database_name = %config.database.database_name,
+        database_require_ssl = config.database.require_ssl,
+        email_provider,
+        sender_email = %config.email_client.sender_email,
+        email_worker_enabled = config.email_queue.worker_enabled,
+        maintenance_mode = config.application.maintenance_mode,
+        feature_flags = ?feature_flags.enabled(),
+        "Effective configuration"
+    );
+}
+
+/// Bind `address` with room for `backlog` connections waiting to be accepted,
+/// or the standard library's default when unset. `SO_REUSEADDR` is set
+/// either way, so that a restart does not wait for the previous process'
+/// connections to leave `TIME_WAIT`.
+///
+/// Must be called from within the Tokio runtime.
+fn bind_tcp(address: &str, backlog: Option<u32>) -> Result<TcpListener, std::io::Error> {
+    let Some(backlog) = backlog else {
+        // The standard library sets `SO_REUSEADDR` itself on unix.
+        return TcpListener::bind(address);
+    };
+    let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} does not resolve to an address", address),
+        )
+    })?;
+    let socket = if address.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    socket.bind(address)?;
+    socket.listen(backlog)?.into_std()
+}
+
+/// Bind `socket_path`, replacing any stale socket left behind by a previous
+/// run, and let the socket owner's group (e.g. a sidecar) connect to it.
+#[cfg(unix)]
+fn bind_unix_socket(socket_path: &str) -> Result<UnixListener, std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match std::fs::remove_file(socket_path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let listener = UnixListener::bind(socket_path)?;
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o660))?;
+    Ok(listener)
+}
+
+/// The public URL of the application, used to build links in emails.
+pub struct ApplicationBaseUrl(pub String);
+
+/// The prefix every route is mounted under, e.g. `/api/v1`; empty when the
+/// routes live at the root.
+pub struct ApplicationBasePath(pub String);
+
+/// Look up the sender domain's records when the check is enabled; the missing
+/// ones only fail readiness when configured to.
+async fn check_sender_domain_on_start(config: &Settings) -> SenderDomainProblems {
+    let settings = &config.email_client.sender_domain_check;
+    if !settings.enabled {
+        return SenderDomainProblems::default();
+    }
+    let Some(domain) = config.email_client.sender_domain() else {
+        return SenderDomainProblems::default();
+    };
+    let resolver = match DnsResolver::from_system_conf() {
+        Ok(resolver) => resolver,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to set up a DNS resolver for the sender domain");
+            return SenderDomainProblems::default();
+        }
+    };
+    let missing = check_sender_domain(&resolver, domain, settings.dkim_selector.as_deref()).await;
+    if settings.fail_readiness {
+        SenderDomainProblems(missing)
+    } else {
+        SenderDomainProblems::default()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn run(
-    listener: TcpListener,
+    listener: Listener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<EmailClient>,
+    feature_flags: Arc<FeatureFlags>,
+    metrics: Metrics,
+    sender_domain_problems: SenderDomainProblems,
+    clock: Arc<dyn Clock>,
+    config: &Settings,
 ) -> Result<Server, std::io::Error> {
     let db_pool = web::Data::new(db_pool);
-    let email_client = web::Data::new(email_client);
+    let email_client = web::Data::from(email_client);
+    let feature_flags = web::Data::from(feature_flags);
+    let metrics = web::Data::new(metrics);
+    let sender_domain_problems = web::Data::new(sender_domain_problems);
+    let base_path = config.application.base_path();
+    let base_url = web::Data::new(ApplicationBaseUrl(config.application.links_base_url()));
+    let application_base_path = web::Data::new(ApplicationBasePath(base_path.clone()));
+    let health_check_path = config
+        .application
+        .health_check_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let confirmation_redirect = web::Data::new(ConfirmationRedirect(
+        config
+            .application
+            .confirmation_redirect()
+            .unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "Ignoring the confirmation redirect");
+                None
+            }),
+    ));
+    let maintenance_mode = web::Data::new(MaintenanceMode(config.application.maintenance_mode));
+    let trusted_proxies =
+        web::Data::new(TrustedProxies(config.application.trusted_proxies.clone()));
+    let postmark_webhook_credentials = web::Data::new(config.webhooks.postmark.clone());
+    let trusted_source = web::Data::new(config.trusted_sources.clone());
+    let subscriber_quota = web::Data::new(SubscriberQuota(config.application.max_subscribers));
+    let token_attempts = web::Data::new(TokenGenerationAttempts(
+        config.application.token_generation_attempts,
+    ));
+    let secret_key = Key::from(config.application.hmac_secret.expose_secret().as_bytes());
+    let cors_settings = config.cors.clone();
+    let session_timeouts = web::Data::new(config.application.session_timeouts());
+    let pii_logging = web::Data::new(config.application.pii_logging);
+    let login_lockout = web::Data::new(config.application.login_lockout());
+    let password_policy = web::Data::new(config.password_policy.clone());
+    let name_policy = web::Data::new(config.name_policy.clone());
+    let text_body_check = web::Data::new(config.text_body_check);
+    let rate_limiters = web::Data::new(config.rate_limits.limiters());
+    let email_queue = web::Data::new(config.email_queue.clone());
+    let concurrency_limiters = web::Data::new(config.concurrency_limits.limiters());
+    let access_log = web::Data::new(AccessLog::stdout(config.application.access_log_format));
+    let legacy_validation_status = web::Data::new(LegacyValidationStatus(
+        config.application.legacy_validation_status,
+    ));
+    let token_cache = web::Data::new(TokenCache::new(
+        config.application.confirmation_token_cache_capacity,
+        config.application.confirmation_token_ttl(),
+        clock.clone(),
+    ));
+    let clock = web::Data::<dyn Clock>::from(clock);
+    let event_sink = web::Data::<dyn EventSink>::from(config.analytics.event_sink());
+    let welcome_email = web::Data::new(WelcomeEmail(config.welcome_email.template()));
+    let json_subscribe_response = web::Data::new(JsonSubscribeResponse(
+        config.application.json_subscribe_response,
+    ));
+    let email_domain_check =
+        web::Data::new(EmailDomainCheck::new(config.application.email_mx_check));
+    let confirmation_token_ttl = web::Data::new(ConfirmationTokenTtl(
+        config.application.confirmation_token_ttl(),
+    ));
     let server = HttpServer::new(move || {
         App::new()
-            .wrap(TracingLogger::default())
-            .route("/health_check", web::get().to(health_checker))
-            .route("/subscriptions", web::post().to(subscribe))
+            .wrap(from_fn(method_not_allowed_as_json))
+            .wrap(from_fn(downgrade_validation_errors))
+            .wrap(SessionMiddleware::new(
+                CookieSessionStore::default(),
+                secret_key.clone(),
+            ))
+            .wrap(cors(&cors_settings))
+            .wrap(from_fn(track_in_flight_requests))
+            .wrap(TracingLogger::<AppRootSpanBuilder>::new())
+            .wrap(from_fn(log_access))
+            .service(
+                web::scope(&base_path)
+                    .service(
+                        web::resource(health_check_path.as_str())
+                            .route(web::get().to(health_checker)),
+                    )
+                    .service(web::resource("/ready").route(web::get().to(readiness)))
+                    .service(web::resource("/metrics").route(web::get().to(get_metrics)))
+                    .service(
+                        web::resource("/api-docs/openapi.json")
+                            .route(web::get().to(openapi_document)),
+                    )
+                    .service(
+                        web::resource("/login")
+                            .wrap(from_fn(limit_logins))
+                            .route(web::post().to(login)),
+                    )
+                    .service(
+                        web::resource("/subscriptions")
+                            .wrap(from_fn(limit_concurrent_subscriptions))
+                            .wrap(from_fn(reject_during_maintenance))
+                            .wrap(from_fn(limit_subscriptions))
+                            .route(web::post().to(subscribe)),
+                    )
+                    .service(web::resource("/subscriptions/confirm").route(web::get().to(confirm)))
+                    .service(
+                        web::resource("/subscriptions/confirm/{subscription_token}")
+                            .route(web::get().to(confirm)),
+                    )
+                    .service(
+                        web::resource("/subscriptions/status")
+                            .route(web::get().to(subscription_status)),
+                    )
+                    .service(
+                        web::resource("/subscriptions/resend")
+                            .wrap(from_fn(reject_during_maintenance))
+                            .route(web::post().to(resend_confirmation)),
+                    )
+                    .service(
+                        web::resource("/subscriptions/trusted")
+                            .wrap(from_fn(reject_during_maintenance))
+                            .route(web::post().to(subscribe_trusted)),
+                    )
+                    .service(
+                        web::resource("/newsletters")
+                            .wrap(from_fn(reject_during_maintenance))
+                            .wrap(from_fn(reject_anonymous_users))
+                            .route(web::post().to(publish_newsletter)),
+                    )
+                    .service(
+                        web::scope("/admin")
+                            .wrap(from_fn(reject_anonymous_users))
+                            .service(
+                                web::resource("/audit_log").route(web::get().to(get_audit_log)),
+                            )
+                            .service(
+                                web::resource("/feature_flags")
+                                    .route(web::get().to(get_feature_flags)),
+                            )
+                            .service(
+                                web::resource("/feature_flags/{name}")
+                                    .route(web::put().to(update_feature_flag)),
+                            )
+                            .service(
+                                web::resource("/newsletters")
+                                    .route(web::get().to(get_newsletter_history)),
+                            )
+                            .service(
+                                web::resource("/newsletters/{issue_id}/deliveries")
+                                    .route(web::get().to(get_deliveries)),
+                            )
+                            .service(
+                                web::resource("/email/preview").route(web::get().to(preview_email)),
+                            )
+                            .service(
+                                web::resource("/password").route(web::post().to(change_password)),
+                            )
+                            .service(
+                                web::resource("/subscriptions")
+                                    .route(web::get().to(list_subscriptions)),
+                            )
+                            .service(
+                                web::resource("/subscriptions/search")
+                                    .route(web::get().to(search_subscriptions)),
+                            )
+                            .service(
+                                web::resource("/subscriptions/funnel")
+                                    .route(web::get().to(get_subscription_funnel)),
+                            )
+                            .service(
+                                web::resource("/subscriptions/resend-pending")
+                                    .route(web::post().to(resend_pending_confirmations)),
+                            )
+                            .service(
+                                web::resource("/subscriptions/{subscriber_id}")
+                                    .route(web::delete().to(delete_subscriber)),
+                            )
+                            .service(
+                                web::resource("/suppressions")
+                                    .route(web::get().to(get_suppressions))
+                                    .route(web::post().to(add_suppression)),
+                            )
+                            .service(
+                                web::resource("/suppressions/{email}")
+                                    .route(web::delete().to(remove_suppression)),
+                            ),
+                    )
+                    .service(
+                        web::resource("/webhooks/postmark").route(web::post().to(postmark_webhook)),
+                    ),
+            )
+            .default_service(web::to(not_found))
+            .app_data(web::FormConfig::default().error_handler(form_error_handler))
+            .app_data(web::JsonConfig::default().error_handler(json_error_handler))
             .app_data(db_pool.clone())
+            .app_data(base_url.clone())
+            .app_data(application_base_path.clone())
             .app_data(email_client.clone())
+            .app_data(feature_flags.clone())
+            .app_data(metrics.clone())
+            .app_data(maintenance_mode.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(postmark_webhook_credentials.clone())
+            .app_data(trusted_source.clone())
+            .app_data(token_attempts.clone())
+            .app_data(subscriber_quota.clone())
+            .app_data(session_timeouts.clone())
+            .app_data(pii_logging.clone())
+            .app_data(login_lockout.clone())
+            .app_data(password_policy.clone())
+            .app_data(name_policy.clone())
+            .app_data(text_body_check.clone())
+            .app_data(rate_limiters.clone())
+            .app_data(email_queue.clone())
+            .app_data(confirmation_redirect.clone())
+            .app_data(confirmation_token_ttl.clone())
+            .app_data(token_cache.clone())
+            .app_data(email_domain_check.clone())
+            .app_data(json_subscribe_response.clone())
+            .app_data(concurrency_limiters.clone())
+            .app_data(access_log.clone())
+            .app_data(clock.clone())
+            .app_data(event_sink.clone())
+            .app_data(welcome_email.clone())
+            .app_data(sender_domain_problems.clone())
+            .app_data(legacy_validation_status.clone())
     })
-    .listen(listener)?
+    .keep_alive(config.application.keep_alive())
+    .client_request_timeout(config.application.client_request_timeout())
+    .client_disconnect_timeout(config.application.client_disconnect_timeout())
+    .shutdown_timeout(config.application.shutdown_timeout().as_secs());
+    let server = match listener {
+        Listener::Tcp(listener) => server.listen(listener)?,
+        #[cfg(unix)]
+        Listener::Unix(listener) => server.listen_uds(listener)?,
+    }
     .run();
 
     Ok(server)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use secrecy::Secret;
+    use tokio::net::TcpStream;
+
+    use super::{bind_tcp, log_effective_configuration};
+    use crate::configuration::{get_configuration, EmailProviderSettings};
+    use crate::feature_flags::FeatureFlags;
+    use crate::telemetry::{get_subscriber, CapturedLogs};
+
+    #[test]
+    fn the_effective_configuration_is_logged_without_secrets() {
+        let mut config = get_configuration().unwrap();
+        let secrets = [
+            "hmac-secret-value",
+            "database-password-value",
+            "postmark-token-value",
+            "webhook-password-value",
+            "trusted-api-key-value",
+        ];
+        config.application.hmac_secret = Secret::new(secrets[0].into());
+        config.database.password = Secret::new(secrets[1].into());
+        config.email_client.provider = EmailProviderSettings::Postmark {
+            base_url: "https://api.postmarkapp.com".into(),
+            authorization_token: Secret::new(secrets[2].into()),
+            transactional_stream: None,
+            broadcast_stream: None,
+        };
+        config.webhooks.postmark.password = Secret::new(secrets[3].into());
+        config.trusted_sources.api_key = Some(Secret::new(secrets[4].into()));
+        let captured = CapturedLogs::default();
+        let _guard = tracing::subscriber::set_default(get_subscriber(
+            "test".into(),
+            "info".into(),
+            captured.clone(),
+        ));
+
+        log_effective_configuration(&config, 8000, "postmark", &FeatureFlags::new());
+
+        let logs = captured.contents();
+        assert!(logs.contains("Effective configuration"), "{}", logs);
+        assert!(logs.contains(r#""database_name":"newsletter""#), "{}", logs);
+        assert!(logs.contains(r#""email_provider":"postmark""#), "{}", logs);
+        for secret in secrets {
+            assert!(!logs.contains(secret), "{}", logs);
+        }
+    }
+
+    /// Whether a connection to `address` completes its handshake, which the
+    /// kernel does on the listener's behalf while its queue has room.
+    async fn connects(address: std::net::SocketAddr) -> Option<TcpStream> {
+        tokio::time::timeout(Duration::from_millis(200), TcpStream::connect(address))
+            .await
+            .ok()
+            .map(Result::unwrap)
+    }
+
+    // Linux queues one connection more than the backlog.
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn connections_past_the_configured_backlog_wait() {
+        let listener = bind_tcp("127.0.0.1:0", Some(1)).unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let first = connects(address).await;
+        let second = connects(address).await;
+        let third = connects(address).await;
+
+        assert!(first.is_some() && second.is_some());
+        assert!(third.is_none());
+    }
+
+    #[tokio::test]
+    async fn the_default_backlog_queues_more_connections() {
+        let listener = bind_tcp("127.0.0.1:0", None).unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let mut connections = Vec::new();
+        for _ in 0..3 {
+            connections.push(connects(address).await);
+        }
+
+        assert!(connections.iter().all(Option::is_some));
+    }
+}
@@ -0,0 +1,114 @@
+//! Addresses we must never email again, e.g. after a hard bounce or a spam
+//! complaint. Every sender checks this list before delivering.
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+
+/// Reasons the address itself earned, e.g. by bouncing: unlike a manual
+/// suppression, signing up again does not lift them.
+const PERMANENT_REASONS: [&str; 3] = ["hard_bounce", "spam_complaint", "inactive_recipient"];
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Suppression {
+    pub email: String,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Checking the suppression list", skip(executor, email))]
+pub async fn is_suppressed(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!("SELECT email FROM suppressions WHERE email = $1", email)
+        .fetch_optional(executor)
+        .await?;
+    Ok(row.is_some())
+}
+
+/// Why `email` is on the suppression list, if it is; the row stays locked
+/// until the end of the transaction.
+#[tracing::instrument(name = "Get the reason of a suppression", skip(executor, email))]
+pub async fn suppression_reason(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT reason FROM suppressions WHERE email = $1 FOR UPDATE",
+        email
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.map(|r| r.reason))
+}
+
+/// Whether a suppression for `reason` outlives a new signup.
+pub fn is_permanent(reason: &str) -> bool {
+    PERMANENT_REASONS.contains(&reason)
+}
+
+/// Add `email` to the suppression list; suppressing an address twice keeps
+/// the original reason.
+#[tracing::instrument(name = "Suppressing an address", skip(executor, email))]
+pub async fn suppress(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email, reason, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        email,
+        reason
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Stop sending newsletters to the subscriber with this address, if any.
+#[tracing::instrument(name = "Suppressing a subscriber", skip(transaction, email))]
+pub async fn suppress_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'suppressed' WHERE email = $1",
+        email
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Returns `false` if `email` was not suppressed in the first place.
+pub async fn unsuppress(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    Ok(lift_suppression(pool, email).await?.is_some())
+}
+
+/// Remove `email` from the suppression list, returning why it was on it.
+#[tracing::instrument(name = "Removing a suppression", skip(executor, email))]
+pub async fn lift_suppression(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        "DELETE FROM suppressions WHERE email = $1 RETURNING reason",
+        email
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.map(|r| r.reason))
+}
+
+#[tracing::instrument(name = "Listing suppressions", skip(pool))]
+pub async fn list_suppressions(pool: &PgPool) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        "SELECT email, reason, created_at FROM suppressions ORDER BY created_at DESC, email"
+    )
+    .fetch_all(pool)
+    .await
+}
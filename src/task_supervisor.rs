@@ -0,0 +1,97 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Owns the lifecycle of the background workers running next to the HTTP
+/// server.
+///
+/// Every task receives a child of the supervisor's [`CancellationToken`] and
+/// is expected to wind down when it is cancelled. On shutdown the supervisor
+/// cancels the token and waits for the tasks, aborting the stragglers once the
+/// timeout elapses.
+#[derive(Default)]
+pub struct TaskSupervisor {
+    token: CancellationToken,
+    tasks: Vec<(String, JoinHandle<()>)>,
+}
+
+impl TaskSupervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn<F, Fut>(&mut self, name: &str, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        tracing::info!(task = name, "Starting background task");
+        let handle = tokio::spawn(task(self.token.child_token()));
+        self.tasks.push((name.to_owned(), handle));
+    }
+
+    /// Cancel every task and wait up to `timeout` for all of them to finish.
+    pub async fn shutdown(self, timeout: Duration) {
+        self.token.cancel();
+        let deadline = tokio::time::Instant::now() + timeout;
+        for (name, mut handle) in self.tasks {
+            match tokio::time::timeout_at(deadline, &mut handle).await {
+                Ok(Ok(())) => tracing::info!(task = %name, "Background task stopped"),
+                Ok(Err(e)) => tracing::error!(
+                    task = %name,
+                    error.cause_chain = ?e,
+                    "Background task failed"
+                ),
+                Err(_) => {
+                    tracing::warn!(task = %name, "Background task did not stop in time, aborting");
+                    handle.abort();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TaskSupervisor;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn tasks_are_cancelled_on_shutdown() {
+        let mut supervisor = TaskSupervisor::new();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = cancelled.clone();
+        supervisor.spawn("dummy", |token| async move {
+            token.cancelled().await;
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            supervisor.shutdown(Duration::from_secs(5)),
+        )
+        .await
+        .expect("The supervisor did not return");
+
+        assert!(cancelled.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn tasks_ignoring_cancellation_are_aborted_after_the_timeout() {
+        let mut supervisor = TaskSupervisor::new();
+        supervisor.spawn("stubborn", |_token| async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            supervisor.shutdown(Duration::from_millis(50)),
+        )
+        .await
+        .expect("The supervisor did not return");
+    }
+}
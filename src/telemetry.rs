@@ -1,4 +1,8 @@
-use tracing::{subscriber::set_global_default, Subscriber};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use tracing::{subscriber::set_global_default, Span, Subscriber};
+use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
 use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, EnvFilter, Registry};
@@ -26,3 +30,146 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     LogTracer::init().expect("Failed to set tracing logger");
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
+
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> tokio::task::JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
+
+/// The root span of every request: the `tracing_actix_web` defaults plus
+/// fields filled in later by our middleware (e.g. `user_id` once the admin
+/// has been authenticated).
+pub struct AppRootSpanBuilder;
+
+impl RootSpanBuilder for AppRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let span = tracing_actix_web::root_span!(request, user_id = tracing::field::Empty);
+        // Confirmation links carry their token in the query string.
+        if let Some(target) = redact_subscription_token(request.uri()) {
+            span.record("http.target", target.as_str());
+        }
+        span
+    }
+
+    fn on_request_end<B>(span: Span, outcome: &Result<ServiceResponse<B>, actix_web::Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+/// The request target with the value of its `subscription_token` query
+/// parameter hidden; `None` when there is no such parameter.
+pub(crate) fn redact_subscription_token(uri: &actix_web::http::Uri) -> Option<String> {
+    let query = uri.query()?;
+    if !query
+        .split('&')
+        .any(|pair| pair.starts_with("subscription_token="))
+    {
+        return None;
+    }
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some(("subscription_token", _)) => "subscription_token=[redacted]",
+            _ => pair,
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    Some(format!("{}?{}", uri.path(), query))
+}
+
+/// Lets one in every `rate` calls through, so that repetitive info logs
+/// (e.g. one per email during a newsletter blast) do not swamp the log
+/// backend. A rate of 0 or 1 lets everything through.
+pub struct LogSampler {
+    rate: u64,
+    counter: AtomicU64,
+}
+
+impl LogSampler {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether this occurrence of the event should be logged.
+    pub fn sample(&self) -> bool {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        self.rate <= 1 || n.is_multiple_of(self.rate)
+    }
+}
+
+/// A log sink tests can read back.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl CapturedLogs {
+    /// Everything logged so far.
+    pub(crate) fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact_subscription_token, LogSampler};
+
+    #[test]
+    fn the_subscription_token_is_redacted_from_the_target() {
+        let uri = "/subscriptions/confirm?subscription_token=s3cr3t&utm=x"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            redact_subscription_token(&uri).unwrap(),
+            "/subscriptions/confirm?subscription_token=[redacted]&utm=x"
+        );
+        assert!(redact_subscription_token(&"/health_check?a=b".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn one_in_n_events_is_sampled() {
+        let sampler = LogSampler::new(10);
+
+        let sampled = (0..100).filter(|_| sampler.sample()).count();
+
+        assert_eq!(sampled, 10);
+    }
+
+    #[test]
+    fn a_rate_of_one_or_less_samples_everything() {
+        for rate in [0, 1] {
+            let sampler = LogSampler::new(rate);
+
+            assert!((0..10).all(|_| sampler.sample()));
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Finding and dropping the databases the test suite creates, one per test,
+//! which pile up on the development server across runs.
+use sqlx::{Executor, PgConnection};
+use uuid::Uuid;
+
+/// Every test database is named after this prefix and a UUID.
+pub const TEST_DATABASE_PREFIX: &str = "test_subscriptions_";
+
+/// Whether `name` is that of a database created by the test suite.
+pub fn is_test_database(name: &str) -> bool {
+    name.strip_prefix(TEST_DATABASE_PREFIX)
+        .is_some_and(|id| Uuid::parse_str(id).is_ok())
+}
+
+/// The test databases on the server, by name.
+#[tracing::instrument(name = "List the test databases", skip(connection))]
+pub async fn list_test_databases(
+    connection: &mut PgConnection,
+) -> Result<Vec<String>, sqlx::Error> {
+    let rows = sqlx::query!(r#"SELECT datname AS "name!" FROM pg_database ORDER BY datname"#)
+        .fetch_all(&mut *connection)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| r.name)
+        .filter(|name| is_test_database(name))
+        .collect())
+}
+
+/// Drop the test database `name`, terminating its connections first: a test
+/// that panicked may have left some open.
+#[tracing::instrument(name = "Drop a test database", skip(connection))]
+pub async fn drop_test_database(
+    connection: &mut PgConnection,
+    name: &str,
+) -> Result<(), anyhow::Error> {
+    if !is_test_database(name) {
+        anyhow::bail!("Refusing to drop `{}`, which is not a test database.", name);
+    }
+    sqlx::query!(
+        r#"
+        SELECT pg_terminate_backend(pid) FROM pg_stat_activity
+        WHERE datname = $1 AND pid <> pg_backend_pid()
+        "#,
+        name
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    // Identifiers cannot be bound; the name is checked above.
+    connection
+        .execute(format!(r#"DROP DATABASE IF EXISTS "{}";"#, name).as_str())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_test_database;
+
+    #[test]
+    fn databases_named_by_the_test_suite_are_test_databases() {
+        assert!(is_test_database(
+            "test_subscriptions_5f0d2b53-8b1f-4bb0-9d0a-3c8a3f8f3c1e"
+        ));
+    }
+
+    #[test]
+    fn other_databases_are_not() {
+        for name in [
+            "newsletter",
+            "postgres",
+            "test_subscriptions_",
+            "test_subscriptions_backup",
+            "my_test_subscriptions_5f0d2b53-8b1f-4bb0-9d0a-3c8a3f8f3c1e",
+        ] {
+            assert!(!is_test_database(name), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Recently issued confirmation tokens, kept in memory so that confirming
+//! right after subscribing skips the token lookup. The database stays the
+//! source of truth: a token the cache does not hold is looked up there.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::clock::Clock;
+
+struct Entry {
+    subscriber_id: Uuid,
+    issued_at: DateTime<Utc>,
+    /// When the entry was last inserted or read, in ticks of `Entries::uses`.
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_token: HashMap<String, Entry>,
+    uses: u64,
+}
+
+/// A token the cache holds.
+pub struct CachedToken {
+    pub subscriber_id: Uuid,
+    /// Older than the TTL, going by the cache's clock.
+    pub expired: bool,
+}
+
+/// A size-bounded cache of token → subscriber, evicting the least recently
+/// used token when full. Entries expire along with their token.
+pub struct TokenCache {
+    capacity: usize,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+    entries: Mutex<Entries>,
+}
+
+impl TokenCache {
+    /// A `capacity` of `0` caches nothing.
+    pub fn new(capacity: usize, ttl: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            capacity,
+            ttl,
+            clock,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn insert(&self, token: &str, subscriber_id: Uuid) {
+        if self.capacity == 0 {
+            return;
+        }
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.by_token.len() >= self.capacity && !entries.by_token.contains_key(token) {
+            entries
+                .by_token
+                .retain(|_, entry| now - entry.issued_at <= self.ttl);
+        }
+        if entries.by_token.len() >= self.capacity && !entries.by_token.contains_key(token) {
+            // A linear scan, which is cheap enough at the sizes this cache
+            // is configured with.
+            let least_recently_used = entries
+                .by_token
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(token, _)| token.clone());
+            if let Some(token) = least_recently_used {
+                entries.by_token.remove(&token);
+            }
+        }
+        entries.uses += 1;
+        let last_used = entries.uses;
+        entries.by_token.insert(
+            token.to_owned(),
+            Entry {
+                subscriber_id,
+                issued_at: now,
+                last_used,
+            },
+        );
+    }
+
+    /// The subscriber `token` was issued to and whether the token has
+    /// expired, unless it is not cached. Expiry is measured on the cache's own
+    /// clock, from when the token was inserted, so that it does not depend on
+    /// the database's. An expired token stays cached, and keeps being reported
+    /// as such, until it is evicted to make room.
+    pub fn get(&self, token: &str) -> Option<CachedToken> {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.uses += 1;
+        let uses = entries.uses;
+        let entry = entries.by_token.get_mut(token)?;
+        entry.last_used = uses;
+        Some(CachedToken {
+            subscriber_id: entry.subscriber_id,
+            expired: now - entry.issued_at > self.ttl,
+        })
+    }
+
+    pub fn remove(&self, token: &str) {
+        self.entries.lock().unwrap().by_token.remove(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use super::TokenCache;
+    use crate::clock::MockClock;
+
+    fn cache(capacity: usize) -> (TokenCache, Arc<MockClock>) {
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        (
+            TokenCache::new(capacity, Duration::hours(1), clock.clone()),
+            clock,
+        )
+    }
+
+    #[test]
+    fn a_cached_token_returns_its_subscriber() {
+        let (cache, _) = cache(2);
+        let subscriber_id = Uuid::new_v4();
+        cache.insert("token", subscriber_id);
+
+        let cached = cache.get("token").unwrap();
+        assert_eq!(cached.subscriber_id, subscriber_id);
+        assert!(!cached.expired);
+        assert!(cache.get("other").is_none());
+    }
+
+    #[test]
+    fn the_least_recently_used_token_is_evicted_when_full() {
+        let (cache, _) = cache(2);
+        cache.insert("first", Uuid::new_v4());
+        cache.insert("second", Uuid::new_v4());
+        // Reading the first token makes the second one the oldest.
+        assert!(cache.get("first").is_some());
+
+        cache.insert("third", Uuid::new_v4());
+
+        assert!(cache.get("first").is_some());
+        assert!(cache.get("second").is_none());
+        assert!(cache.get("third").is_some());
+    }
+
+    #[test]
+    fn entries_expire_with_their_token() {
+        let (cache, clock) = cache(2);
+        cache.insert("token", Uuid::new_v4());
+
+        clock.advance(Duration::hours(1));
+        assert!(!cache.get("token").unwrap().expired);
+        clock.advance(Duration::seconds(1));
+        assert!(cache.get("token").unwrap().expired);
+    }
+
+    #[test]
+    fn a_zero_capacity_caches_nothing() {
+        let (cache, _) = cache(0);
+        cache.insert("token", Uuid::new_v4());
+
+        assert!(cache.get("token").is_none());
+    }
+}
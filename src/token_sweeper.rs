@@ -0,0 +1,39 @@
+//! Confirming a subscription consumes its token; this sweep catches the
+//! tokens that were left behind anyway, e.g. those of subscribers confirmed
+//! before tokens were deleted on confirmation.
+use sqlx::PgPool;
+use tokio_util::sync::CancellationToken;
+
+/// Delete the tokens of subscribers who are no longer pending confirmation;
+/// returns how many were deleted.
+#[tracing::instrument(name = "Sweeping orphaned subscription tokens", skip(pool))]
+pub async fn sweep_orphaned_tokens(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens t
+        USING subscriptions s
+        WHERE t.subscriber_id = s.id AND s.status <> 'pending_confirmation'
+        "#
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn run_sweeper_until_stopped(
+    pool: PgPool,
+    interval: std::time::Duration,
+    token: CancellationToken,
+) {
+    while !token.is_cancelled() {
+        match sweep_orphaned_tokens(&pool).await {
+            Ok(0) => {}
+            Ok(deleted) => tracing::info!(deleted, "Deleted orphaned subscription tokens"),
+            Err(e) => tracing::warn!(error = %e, "Failed to sweep subscription tokens"),
+        }
+        tokio::select! {
+            _ = token.cancelled() => {}
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! The newsletters subscribers pick from, e.g. `weekly` and `product`; an
+//! issue published to a topic only reaches its subscribers.
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::domain::TopicName;
+
+/// The names in `topics` that are not known topics.
+#[tracing::instrument(name = "Looking up unknown topics", skip(executor))]
+pub async fn unknown_topics(
+    executor: impl PgExecutor<'_>,
+    topics: &[TopicName],
+) -> Result<Vec<String>, sqlx::Error> {
+    let names: Vec<String> = topics.iter().map(|t| t.as_ref().to_owned()).collect();
+    sqlx::query_scalar!(
+        r#"
+        SELECT name AS "name!"
+        FROM unnest($1::text[]) AS name
+        WHERE name NOT IN (SELECT name FROM topics)
+        "#,
+        &names[..]
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Subscribe `subscriber_id` to `topics`, keeping the topics they already
+/// follow.
+#[tracing::instrument(name = "Enrolling a subscriber in topics", skip(executor))]
+pub async fn enroll_in_topics(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    topics: &[TopicName],
+) -> Result<(), sqlx::Error> {
+    let names: Vec<String> = topics.iter().map(|t| t.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_topics (subscriber_id, topic)
+        SELECT $1, unnest($2::text[])
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        &names[..]
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Subscribe `subscriber_id` to the default topic, for subscriptions that do
+/// not pick any.
+#[tracing::instrument(name = "Enrolling a subscriber in the default topic", skip(executor))]
+pub async fn enroll_in_default_topic(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_topics (subscriber_id, topic)
+        SELECT $1, name FROM topics WHERE is_default
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Make `topic` the default topic, creating it if it does not exist yet, and
+/// enroll the confirmed subscribers without any topic, e.g. those who joined
+/// before topics existed, into it.
+#[tracing::instrument(name = "Setting the default topic", skip(pool))]
+pub async fn set_default_topic(pool: &PgPool, topic: &TopicName) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        "UPDATE topics SET is_default = FALSE WHERE is_default AND name <> $1",
+        topic.as_ref()
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO topics (name, is_default) VALUES ($1, TRUE)
+        ON CONFLICT (name) DO UPDATE SET is_default = TRUE
+        "#,
+        topic.as_ref()
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_topics (subscriber_id, topic)
+        SELECT id, $1 FROM subscriptions
+        WHERE status = 'confirmed'
+        AND NOT EXISTS (
+            SELECT 1 FROM subscriber_topics WHERE subscriber_topics.subscriber_id = subscriptions.id
+        )
+        "#,
+        topic.as_ref()
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await
+}
@@ -0,0 +1,202 @@
+//! Verifying that inbound webhooks come from who they claim to: the sender
+//! signs the raw body with a secret we share, and we recompute the signature.
+use std::future::{ready, Future};
+use std::pin::Pin;
+
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+use crate::error::{error_chain_fmt, internal_error, json_error};
+
+/// The header carrying the hex-encoded HMAC-SHA256 of the request body.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// The secret webhook signatures are checked against, shared as app data.
+#[derive(Clone)]
+pub struct WebhookSigningSecret(pub Secret<String>);
+
+/// Whether `provided` is the hex-encoded HMAC-SHA256 of `body` under `secret`.
+/// The comparison takes the same time wherever the signatures differ, so that
+/// it does not leak how much of a forged signature is right.
+pub fn verify_signature(secret: &Secret<String>, body: &[u8], provided: &str) -> bool {
+    let Ok(provided) = hex::decode(provided.trim()) else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&provided).is_ok()
+}
+
+#[derive(thiserror::Error)]
+pub enum SignatureError {
+    #[error("The '{}' header was missing", SIGNATURE_HEADER)]
+    Missing,
+    #[error("The webhook signature does not match its body")]
+    Invalid,
+    #[error("No webhook signing secret is configured")]
+    NoSecret,
+    #[error("Failed to read the webhook body")]
+    Body(#[source] actix_web::Error),
+}
+
+impl std::fmt::Debug for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SignatureError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SignatureError::Body(e) => e.error_response(),
+            SignatureError::NoSecret => internal_error(),
+            _ => json_error(
+                StatusCode::UNAUTHORIZED,
+                "invalid_signature",
+                "The webhook signature is missing or invalid.",
+            ),
+        }
+    }
+}
+
+/// The body of a webhook whose signature checked out. Taking it as a handler
+/// argument rejects unsigned and forged requests with a 401 before the
+/// handler runs.
+pub struct SignedBody(pub web::Bytes);
+
+/// The secret and the signature to check the body against, looked up before
+/// reading the body: a request without them is not worth buffering.
+fn signature_inputs(
+    req: &HttpRequest,
+) -> Result<(web::Data<WebhookSigningSecret>, String), SignatureError> {
+    let Some(secret) = req.app_data::<web::Data<WebhookSigningSecret>>() else {
+        // A misconfiguration, not the sender's fault.
+        tracing::error!("No webhook signing secret is configured, rejecting a webhook");
+        return Err(SignatureError::NoSecret);
+    };
+    let provided = req
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .ok_or(SignatureError::Missing)?
+        .to_str()
+        .map_err(|_| SignatureError::Invalid)?;
+    Ok((secret.clone(), provided.to_owned()))
+}
+
+impl FromRequest for SignedBody {
+    type Error = SignatureError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let (secret, provided) = match signature_inputs(req) {
+            Ok(inputs) => inputs,
+            Err(e) => return Box::pin(ready(Err(e))),
+        };
+        let body = web::Bytes::from_request(req, payload);
+        Box::pin(async move {
+            let body = body.await.map_err(SignatureError::Body)?;
+            if !verify_signature(&secret.0, &body, &provided) {
+                return Err(SignatureError::Invalid);
+            }
+            Ok(Self(body))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test as actix_test, web, App, HttpResponse};
+    use hmac::{Hmac, Mac};
+    use secrecy::Secret;
+    use sha2::Sha256;
+
+    use super::{verify_signature, SignedBody, WebhookSigningSecret, SIGNATURE_HEADER};
+
+    const BODY: &[u8] = br#"{"RecordType":"Bounce"}"#;
+
+    fn secret() -> Secret<String> {
+        Secret::new("webhook-secret".into())
+    }
+
+    fn sign(body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"webhook-secret").unwrap();
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    async fn status_of(signature: Option<&str>) -> u16 {
+        status_of_request(Some(secret()), signature, BODY.to_vec()).await
+    }
+
+    async fn status_of_request(
+        secret: Option<Secret<String>>,
+        signature: Option<&str>,
+        body: Vec<u8>,
+    ) -> u16 {
+        let mut app = App::new();
+        if let Some(secret) = secret {
+            app = app.app_data(web::Data::new(WebhookSigningSecret(secret)));
+        }
+        let app = actix_test::init_service(app.route(
+            "/webhook",
+            web::post().to(|_: SignedBody| async { HttpResponse::Ok().finish() }),
+        ))
+        .await;
+        let mut request = actix_test::TestRequest::post()
+            .uri("/webhook")
+            .set_payload(body);
+        if let Some(signature) = signature {
+            request = request.insert_header((SIGNATURE_HEADER, signature));
+        }
+        actix_test::call_service(&app, request.to_request())
+            .await
+            .status()
+            .as_u16()
+    }
+
+    #[test]
+    fn a_valid_signature_is_accepted() {
+        assert!(verify_signature(&secret(), BODY, &sign(BODY)));
+    }
+
+    #[test]
+    fn a_signature_of_another_body_is_rejected() {
+        assert!(!verify_signature(&secret(), BODY, &sign(b"{}")));
+        assert!(!verify_signature(&secret(), BODY, "not-hex"));
+    }
+
+    #[actix_web::test]
+    async fn a_signed_request_reaches_the_handler() {
+        assert_eq!(status_of(Some(&sign(BODY))).await, 200);
+    }
+
+    #[actix_web::test]
+    async fn an_invalid_signature_is_rejected_with_a_401() {
+        assert_eq!(status_of(Some(&sign(b"{}"))).await, 401);
+    }
+
+    #[actix_web::test]
+    async fn a_missing_signature_is_rejected_with_a_401() {
+        assert_eq!(status_of(None).await, 401);
+    }
+
+    #[actix_web::test]
+    async fn an_unsigned_request_is_rejected_before_its_body_is_read() {
+        // Past the default payload limit: reading it would fail with a 413.
+        let body = vec![b'a'; 300 * 1024];
+
+        assert_eq!(status_of_request(Some(secret()), None, body).await, 401);
+    }
+
+    #[actix_web::test]
+    async fn a_missing_secret_is_a_server_error() {
+        let status = status_of_request(None, Some(&sign(BODY)), BODY.to_vec()).await;
+
+        assert_eq!(status, 500);
+    }
+}
@@ -0,0 +1,94 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::audit::AuditEntry;
+use zero2prod::routes::{PublishedIssue, SubscriberRow};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, strong_password, TestApp};
+
+async fn audit_log(app: &TestApp) -> Vec<AuditEntry> {
+    let response = app.get_audit_log().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_read_the_audit_log() {
+    let app = spawn_app().await;
+
+    let response = app.get_audit_log().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn publishing_a_newsletter_is_audited() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let issue: PublishedIssue = response.json().await.unwrap();
+
+    let entries = audit_log(&app).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, "newsletter.publish");
+    assert_eq!(entries[0].user_id, Some(app.test_user.user_id));
+    assert_eq!(entries[0].target, issue.issue_id.to_string());
+    assert_eq!(entries[0].metadata["title"], "Newsletter title");
+}
+
+#[tokio::test]
+async fn changing_the_password_is_audited() {
+    let app = spawn_app().await;
+    app.login().await;
+    let new_password = strong_password();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let entries = audit_log(&app).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, "password.change");
+    assert_eq!(entries[0].user_id, Some(app.test_user.user_id));
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_is_audited() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    let subscribers: Vec<SubscriberRow> = app
+        .get_admin_subscriptions(None)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    let response = app.delete_subscriber(&subscribers[0].id.to_string()).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let entries = audit_log(&app).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, "subscriber.delete");
+    assert_eq!(entries[0].target, subscribers[0].id.to_string());
+    assert_eq!(entries[0].metadata["email"], "ursula_le_guin@gmail.com");
+}
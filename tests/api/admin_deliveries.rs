@@ -0,0 +1,117 @@
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::deliveries::{DeliveryCounts, DeliveryStatus};
+use zero2prod::routes::{DeliveryReport, PublishedIssue};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+async fn publish_newsletter(app: &TestApp) -> String {
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let issue: PublishedIssue = response.json().await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    issue.issue_id.to_string()
+}
+
+async fn get_report(app: &TestApp, issue_id: &str, query: &str) -> DeliveryReport {
+    let response = app.get_deliveries(issue_id, query).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_deliveries() {
+    let app = spawn_app().await;
+
+    let response = app
+        .get_deliveries(&uuid::Uuid::new_v4().to_string(), "")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn deliveries_can_be_filtered_by_status() {
+    // Record the failure straight away rather than retrying it.
+    let app = spawn_app_with(|c| c.email_queue.max_retries = 1).await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("butler", "octavia_butler@gmail.com")
+        .await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({ "To": "octavia_butler@gmail.com" }),
+        ))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_newsletter(&app).await;
+
+    let report = get_report(&app, &issue_id, "status=failed").await;
+
+    assert_eq!(report.deliveries.len(), 1);
+    assert_eq!(
+        report.deliveries[0].subscriber_email,
+        "octavia_butler@gmail.com"
+    );
+    assert_eq!(report.deliveries[0].status, DeliveryStatus::Failed);
+    assert_eq!(
+        report.counts,
+        DeliveryCounts {
+            pending: 0,
+            delivered: 1,
+            failed: 1
+        }
+    );
+}
+
+#[tokio::test]
+async fn deliveries_are_paginated() {
+    let app = spawn_app().await;
+    for name in ["a", "b", "c"] {
+        app.create_confirmed_subscriber(name, &format!("{}@example.com", name))
+            .await;
+    }
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_newsletter(&app).await;
+
+    let test_cases = [
+        ("limit=2", vec!["a@example.com", "b@example.com"]),
+        ("limit=2&offset=2", vec!["c@example.com"]),
+        ("offset=3", vec![]),
+        // Out-of-range values are clamped rather than rejected.
+        ("limit=0", vec!["a@example.com"]),
+        (
+            "limit=100000&offset=-1",
+            vec!["a@example.com", "b@example.com", "c@example.com"],
+        ),
+    ];
+    for (query, expected) in test_cases {
+        let report = get_report(&app, &issue_id, query).await;
+
+        let emails: Vec<_> = report
+            .deliveries
+            .iter()
+            .map(|d| d.subscriber_email.as_str())
+            .collect();
+        assert_eq!(emails, expected, "Unexpected page for {}", query);
+        assert_eq!(report.counts.delivered, 3);
+    }
+}
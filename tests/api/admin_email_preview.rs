@@ -0,0 +1,49 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_preview_an_email() {
+    let app = spawn_app().await;
+
+    let response = app.get_email_preview("confirmation").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_confirmation_preview_contains_a_sample_link() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.get_email_preview("confirmation").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/html; charset=utf-8"
+    );
+    let html = response.text().await.unwrap();
+    assert!(html.contains("Welcome to our newsletter!"));
+    assert!(html.contains("/subscriptions/confirm?subscription_token=SAMPLE_TOKEN"));
+}
+
+#[tokio::test]
+async fn the_newsletter_preview_contains_sample_content() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.get_email_preview("newsletter").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<h1>Sample issue</h1>"));
+}
+
+#[tokio::test]
+async fn an_unknown_email_type_is_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.get_email_preview("invoice").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
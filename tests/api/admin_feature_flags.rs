@@ -0,0 +1,81 @@
+use zero2prod::error::ErrorBody;
+use zero2prod::feature_flags::{set_flag, FeatureFlag, FeatureFlags, SUBSCRIPTIONS_PAUSED};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_flip_a_flag() {
+    let app = spawn_app().await;
+
+    let response = app.put_feature_flag(SUBSCRIPTIONS_PAUSED, true).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_cached_value_changes_after_a_refresh() {
+    let app = spawn_app().await;
+    let flags = FeatureFlags::new();
+    flags.refresh(&app.db_pool).await.unwrap();
+    assert!(!flags.is_enabled("new_subscribe_flow"));
+
+    set_flag(&app.db_pool, "new_subscribe_flow", true)
+        .await
+        .unwrap();
+    // The cache only changes on refresh.
+    assert!(!flags.is_enabled("new_subscribe_flow"));
+    flags.refresh(&app.db_pool).await.unwrap();
+    assert!(flags.is_enabled("new_subscribe_flow"));
+
+    set_flag(&app.db_pool, "new_subscribe_flow", false)
+        .await
+        .unwrap();
+    flags.refresh(&app.db_pool).await.unwrap();
+    assert!(!flags.is_enabled("new_subscribe_flow"));
+}
+
+#[tokio::test]
+async fn subscribe_respects_the_subscriptions_paused_flag() {
+    let app = spawn_app().await;
+    app.login().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let response = app.put_feature_flag(SUBSCRIPTIONS_PAUSED, true).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 503);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "subscriptions_paused");
+
+    app.put_feature_flag(SUBSCRIPTIONS_PAUSED, false).await;
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn flags_are_listed() {
+    let app = spawn_app().await;
+    app.login().await;
+    app.put_feature_flag("flow_v2", true).await;
+    app.put_feature_flag(SUBSCRIPTIONS_PAUSED, false).await;
+
+    let flags: Vec<FeatureFlag> = app.get_feature_flags().await.json().await.unwrap();
+
+    let flags: Vec<(&str, bool)> = flags.iter().map(|f| (f.name.as_str(), f.enabled)).collect();
+    assert_eq!(
+        flags,
+        vec![("flow_v2", true), (SUBSCRIPTIONS_PAUSED, false)]
+    );
+}
+
+#[tokio::test]
+async fn invalid_flag_names_are_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.put_feature_flag("Not%20A%20Flag", true).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_flag");
+}
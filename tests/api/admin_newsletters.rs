@@ -0,0 +1,81 @@
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::deliveries::DeliveryCounts;
+use zero2prod::newsletter_issues::IssueSummary;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+async fn publish_newsletter(app: &TestApp, title: &str) {
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": title,
+            "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_newsletter_history() {
+    let app = spawn_app().await;
+
+    let response = app.get_newsletter_history().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_history_lists_published_issues_with_their_delivery_counts() {
+    // Record the failure straight away rather than retrying it.
+    let app = spawn_app_with(|c| c.email_queue.max_retries = 1).await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("butler", "octavia_butler@gmail.com")
+        .await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({
+            "To": "octavia_butler@gmail.com",
+            "Subject": "Second issue"
+        })))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app, "First issue").await;
+    publish_newsletter(&app, "Second issue").await;
+    let response = app.get_newsletter_history().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let issues: Vec<IssueSummary> = response.json().await.unwrap();
+    assert_eq!(issues.len(), 2);
+    // Most recent first.
+    assert_eq!(issues[0].title, "Second issue");
+    assert_eq!(
+        issues[0].counts,
+        DeliveryCounts {
+            pending: 0,
+            delivered: 1,
+            failed: 1
+        }
+    );
+    assert_eq!(issues[1].title, "First issue");
+    assert_eq!(
+        issues[1].counts,
+        DeliveryCounts {
+            pending: 0,
+            delivered: 2,
+            failed: 0
+        }
+    );
+    assert!(issues
+        .iter()
+        .all(|issue| issue.published_by == app.test_user.user_id));
+}
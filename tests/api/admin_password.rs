@@ -0,0 +1,100 @@
+use uuid::Uuid;
+use zero2prod::error::ErrorBody;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, strong_password};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_change_your_password() {
+    let app = spawn_app().await;
+    let new_password = strong_password();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn new_password_fields_must_match() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": strong_password(),
+            "new_password_check": strong_password(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "password_mismatch");
+}
+
+#[tokio::test]
+async fn current_password_must_be_valid() {
+    let app = spawn_app().await;
+    app.login().await;
+    let new_password = strong_password();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_credentials");
+}
+
+#[tokio::test]
+async fn changing_password_works() {
+    let app = spawn_app().await;
+    app.login().await;
+    let new_password = strong_password();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = app
+        .post_login(&app.test_user.username, &app.test_user.password)
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_login(&app.test_user.username, &new_password).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn new_password_must_follow_the_password_policy() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": "all-lowercase-and-no-digits",
+            "new_password_check": "all-lowercase-and-no-digits",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_password");
+    assert!(error.message.contains("uppercase"));
+    assert!(error.message.contains("digit"));
+}
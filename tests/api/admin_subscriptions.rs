@@ -0,0 +1,282 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use zero2prod::routes::{Funnel, ResentConfirmations, SubscriberRow};
+
+async fn search(app: &TestApp, q: &str) -> Vec<String> {
+    let response = app.search_subscriptions(q).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers: Vec<SubscriberRow> = response.json().await.unwrap();
+    subscribers.into_iter().map(|s| s.email).collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_list_subscriptions() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_subscriptions(None).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscriptions_are_listed() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+
+    let response = app.get_admin_subscriptions(None).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers: Vec<SubscriberRow> = response.json().await.unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0].email, "ursula_le_guin@gmail.com");
+    assert_eq!(subscribers[0].status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn an_unchanged_listing_returns_304() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+
+    let response = app.get_admin_subscriptions(None).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+    assert!(etag.starts_with("W/"));
+
+    let response = app.get_admin_subscriptions(Some(&etag)).await;
+
+    assert_eq!(response.status().as_u16(), 304);
+    assert_eq!(response.headers()["ETag"], etag.as_str());
+}
+
+#[tokio::test]
+async fn a_change_invalidates_the_etag() {
+    let app = spawn_app().await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    let response = app.get_admin_subscriptions(None).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let response = app.get_admin_subscriptions(Some(&etag)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(response.headers()["ETag"], etag.as_str());
+}
+
+#[tokio::test]
+async fn deleting_an_unknown_subscriber_returns_404() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .delete_subscriber(&uuid::Uuid::new_v4().to_string())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_deleted_subscriber_is_no_longer_listed() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    let subscribers: Vec<SubscriberRow> = app
+        .get_admin_subscriptions(None)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    let response = app.delete_subscriber(&subscribers[0].id.to_string()).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let subscribers: Vec<SubscriberRow> = app
+        .get_admin_subscriptions(None)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_search_subscriptions() {
+    let app = spawn_app().await;
+
+    let response = app.search_subscriptions("ursula").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_found_by_a_case_insensitive_substring() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_unconfirmed_subscriber("octavia butler", "octavia@example.com")
+        .await;
+    app.login().await;
+
+    assert_eq!(
+        search(&app, "LE_GUIN").await,
+        vec!["ursula_le_guin@gmail.com"]
+    );
+    // Names match too.
+    assert_eq!(search(&app, "Butler").await, vec!["octavia@example.com"]);
+}
+
+#[tokio::test]
+async fn a_search_without_matches_returns_an_empty_list() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+
+    assert!(search(&app, "tolkien").await.is_empty());
+}
+
+#[tokio::test]
+async fn the_search_query_is_bound_rather_than_interpolated() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+
+    for q in ["' OR '1'='1", "%' OR 1=1 --", "%"] {
+        assert!(search(&app, q).await.is_empty(), "{} matched", q);
+    }
+    // Wildcards are literal: `_` only matches an underscore.
+    assert_eq!(search(&app, "a_l").await, vec!["ursula_le_guin@gmail.com"]);
+    assert!(search(&app, "u_s").await.is_empty());
+}
+
+#[tokio::test]
+async fn an_empty_search_query_is_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.search_subscriptions("  ").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_resend_pending_confirmations() {
+    let app = spawn_app().await;
+
+    let response = app.post_resend_pending().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn confirmations_are_resent_to_pending_subscribers_only() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_unconfirmed_subscriber("butler", "octavia_butler@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("jemisin", "nk_jemisin@gmail.com")
+        .await;
+    app.login().await;
+
+    let response = app.post_resend_pending().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let resent: ResentConfirmations = response.json().await.unwrap();
+    assert_eq!(resent.queued, 2);
+    let mut recipients: Vec<String> =
+        sqlx::query_scalar("SELECT recipient FROM email_queue ORDER BY execute_after")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    recipients.sort();
+    assert_eq!(
+        recipients,
+        vec!["octavia_butler@gmail.com", "ursula_le_guin@gmail.com"]
+    );
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_funnel() {
+    let app = spawn_app().await;
+
+    let response = app
+        .get_subscription_funnel("2023-03-01", "2023-03-31")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_funnel_is_the_share_of_subscribers_in_the_range_who_confirmed() {
+    let app = spawn_app().await;
+    let subscribers = [
+        (
+            "2023-03-01T09:00:00Z",
+            "confirmed",
+            Some("2023-03-01T10:00:00Z"),
+        ),
+        ("2023-03-10T09:00:00Z", "pending_confirmation", None),
+        ("2023-03-20T09:00:00Z", "pending_confirmation", None),
+        // Confirmed, then suppressed: still a conversion.
+        (
+            "2023-03-31T23:00:00Z",
+            "suppressed",
+            Some("2023-04-01T08:00:00Z"),
+        ),
+        // Outside of the range.
+        (
+            "2023-04-01T00:00:00Z",
+            "confirmed",
+            Some("2023-04-01T01:00:00Z"),
+        ),
+    ];
+    for (i, (subscribed_at, status, confirmed_at)) in subscribers.into_iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO subscriptions (id, email, name, subscribed_at, status, confirmed_at) \
+            VALUES ($1, $2, 'name', $3::timestamptz, $4, $5::timestamptz)",
+        )
+        .bind(Uuid::new_v4())
+        .bind(format!("subscriber{}@example.com", i))
+        .bind(subscribed_at)
+        .bind(status)
+        .bind(confirmed_at)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    app.login().await;
+
+    let response = app
+        .get_subscription_funnel("2023-03-01", "2023-03-31")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let funnel: Funnel = response.json().await.unwrap();
+    assert_eq!(funnel.subscribed, 4);
+    assert_eq!(funnel.confirmed, 2);
+    assert_eq!(funnel.conversion_rate, 0.5);
+}
+
+#[tokio::test]
+async fn an_inverted_funnel_range_is_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .get_subscription_funnel("2023-03-31", "2023-03-01")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
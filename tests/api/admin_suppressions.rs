@@ -0,0 +1,58 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use zero2prod::suppressions::Suppression;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_suppression_list() {
+    let app = spawn_app().await;
+
+    let response = app.get_suppressions().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn suppressions_can_be_added_listed_and_removed() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .post_suppressions(&serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "reason": "requested by support",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let suppressions: Vec<Suppression> = app.get_suppressions().await.json().await.unwrap();
+    assert_eq!(suppressions.len(), 1);
+    assert_eq!(suppressions[0].email, "ursula_le_guin@gmail.com");
+    assert_eq!(suppressions[0].reason, "requested by support");
+
+    let response = app.delete_suppression("ursula_le_guin@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let suppressions: Vec<Suppression> = app.get_suppressions().await.json().await.unwrap();
+    assert!(suppressions.is_empty());
+}
+
+#[tokio::test]
+async fn removing_an_unknown_suppression_returns_404() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.delete_suppression("ursula_le_guin@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn invalid_emails_cannot_be_suppressed() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .post_suppressions(&serde_json::json!({ "email": "not-an-email" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
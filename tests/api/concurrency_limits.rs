@@ -0,0 +1,97 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use zero2prod::concurrency_limit::ConcurrencyLimit;
+use zero2prod::error::ErrorBody;
+
+#[tokio::test]
+async fn subscriptions_beyond_the_in_flight_and_queued_limits_get_a_503() {
+    let app = spawn_app_with(|c| {
+        c.concurrency_limits.subscribe = Some(ConcurrencyLimit {
+            max_in_flight: 1,
+            max_queued: 1,
+        })
+    })
+    .await;
+    // Hold the subscriptions back until all three requests have arrived.
+    let mut lock = app.db_pool.begin().await.unwrap();
+    sqlx::query("LOCK TABLE subscriptions IN ACCESS EXCLUSIVE MODE")
+        .execute(&mut lock)
+        .await
+        .unwrap();
+
+    let (a, b, c, ()) = tokio::join!(
+        app.post_subscriptions("name=a&email=a%40example.com".into()),
+        app.post_subscriptions("name=b&email=b%40example.com".into()),
+        app.post_subscriptions("name=c&email=c%40example.com".into()),
+        async {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            lock.commit().await.unwrap();
+        }
+    );
+
+    let mut statuses: Vec<u16> = [&a, &b, &c].iter().map(|r| r.status().as_u16()).collect();
+    statuses.sort();
+    assert_eq!(statuses, vec![200, 200, 503]);
+    let shed = [a, b, c]
+        .into_iter()
+        .find(|r| r.status().as_u16() == 503)
+        .unwrap();
+    let error: ErrorBody = shed.json().await.unwrap();
+    assert_eq!(error.code, "overloaded");
+}
+
+/// Keeps subscriptions waiting on the database until `release` completes.
+async fn hold_subscriptions_while<T>(app: &TestApp, release: impl Future<Output = T>) -> T {
+    let mut lock = app.db_pool.begin().await.unwrap();
+    sqlx::query("LOCK TABLE subscriptions IN ACCESS EXCLUSIVE MODE")
+        .execute(&mut lock)
+        .await
+        .unwrap();
+    let (response, released) = tokio::join!(
+        app.post_subscriptions("name=a&email=a%40example.com".into()),
+        async {
+            // Give the subscription time to reach the lock.
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let released = release.await;
+            lock.commit().await.unwrap();
+            released
+        }
+    );
+    assert_eq!(response.status().as_u16(), 200);
+    released
+}
+
+fn in_flight(metrics: &str) -> i64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("http_requests_in_flight "))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_in_flight_gauge_counts_requests_being_handled() {
+    let app = spawn_app().await;
+
+    let metrics = hold_subscriptions_while(&app, app.get_metrics()).await;
+
+    // The pending subscription, and the metrics request itself.
+    assert_eq!(in_flight(&metrics), 2);
+    assert_eq!(in_flight(&app.get_metrics().await), 1);
+}
+
+#[tokio::test]
+async fn requests_beyond_the_process_wide_cap_get_a_503() {
+    let app = spawn_app_with(|c| c.concurrency_limits.max_in_flight_requests = Some(1)).await;
+
+    let shed = hold_subscriptions_while(&app, app.get_health_check()).await;
+
+    assert_eq!(shed.status().as_u16(), 503);
+    let error: ErrorBody = shed.json().await.unwrap();
+    assert_eq!(error.code, "overloaded");
+    let response = app.get_health_check().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,146 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+const SPA_ORIGIN: &str = "https://app.example.com";
+
+async fn preflight(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("{}{}", &app.address, path),
+        )
+        .header("Origin", SPA_ORIGIN)
+        .header("Access-Control-Request-Method", "POST")
+        .send()
+        .await
+        .expect("Request failed")
+}
+
+#[tokio::test]
+async fn preflight_allows_credentials_when_configured() {
+    let app = spawn_app_with(|c| {
+        c.cors.allowed_origins = vec![SPA_ORIGIN.into()];
+        c.cors.allow_credentials = true;
+        c.cors.max_age_seconds = Some(600);
+    })
+    .await;
+
+    let response = preflight(&app, "/newsletters").await;
+
+    assert_eq!(200, response.status().as_u16());
+    let headers = response.headers();
+    assert_eq!(headers["Access-Control-Allow-Origin"], SPA_ORIGIN);
+    assert_eq!(headers["Access-Control-Allow-Credentials"], "true");
+    assert_eq!(headers["Access-Control-Max-Age"], "600");
+}
+
+#[tokio::test]
+async fn preflight_allows_feature_flag_updates_and_idempotent_subscriptions() {
+    let app = spawn_app_with(|c| c.cors.allowed_origins = vec![SPA_ORIGIN.into()]).await;
+
+    let response = app
+        .api_client
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("{}/admin/feature_flags/x", &app.address),
+        )
+        .header("Origin", SPA_ORIGIN)
+        .header("Access-Control-Request-Method", "PUT")
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(200, response.status().as_u16());
+
+    let response = app
+        .api_client
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("{}/subscriptions", &app.address),
+        )
+        .header("Origin", SPA_ORIGIN)
+        .header("Access-Control-Request-Method", "POST")
+        .header("Access-Control-Request-Headers", "idempotency-key")
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(200, response.status().as_u16());
+    let allowed = response.headers()["Access-Control-Allow-Headers"]
+        .to_str()
+        .unwrap()
+        .to_lowercase();
+    assert!(allowed.contains("idempotency-key"));
+}
+
+#[tokio::test]
+async fn credentials_are_not_allowed_by_default() {
+    let app = spawn_app_with(|c| {
+        c.cors.allowed_origins = vec![SPA_ORIGIN.into()];
+    })
+    .await;
+
+    let response = preflight(&app, "/newsletters").await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Credentials")
+        .is_none());
+}
+
+#[tokio::test]
+async fn unknown_origins_are_refused() {
+    let app = spawn_app().await;
+
+    let response = preflight(&app, "/newsletters").await;
+
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
+
+async fn cross_origin_get(app: &TestApp) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/health_check", &app.address))
+        .header("Origin", SPA_ORIGIN)
+        .send()
+        .await
+        .expect("Request failed")
+}
+
+/// The header names of `Access-Control-Expose-Headers`, lowercased.
+fn exposed_headers(response: &reqwest::Response) -> Vec<String> {
+    let mut exposed: Vec<_> = response.headers()["Access-Control-Expose-Headers"]
+        .to_str()
+        .unwrap()
+        .split(',')
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    exposed.sort();
+    exposed
+}
+
+#[tokio::test]
+async fn the_configured_headers_are_exposed() {
+    let app = spawn_app_with(|c| {
+        c.cors.allowed_origins = vec![SPA_ORIGIN.into()];
+        c.cors.expose_headers = vec!["X-Request-Id".into(), "Location".into()];
+    })
+    .await;
+
+    let response = cross_origin_get(&app).await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(exposed_headers(&response), ["location", "x-request-id"]);
+}
+
+#[tokio::test]
+async fn the_request_id_is_exposed_by_default() {
+    let app = spawn_app_with(|c| {
+        c.cors.allowed_origins = vec![SPA_ORIGIN.into()];
+    })
+    .await;
+
+    let response = cross_origin_get(&app).await;
+
+    assert_eq!(exposed_headers(&response), ["x-request-id"]);
+}
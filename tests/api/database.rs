@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+use sqlx::postgres::PgPoolOptions;
+use uuid::Uuid;
+use zero2prod::configuration::{get_configuration, Settings};
+use zero2prod::error::{is_statement_timeout, unexpected_error};
+use zero2prod::migrations::run_directory_migrations;
+use zero2prod::startup::{warm_up_pool, Application};
+
+use crate::helpers::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn slow_queries_are_aborted_after_the_statement_timeout() {
+    let app = spawn_app_with(|c| c.database.statement_timeout_millis = 200).await;
+
+    let start = Instant::now();
+    let error = sqlx::query("SELECT pg_sleep(5)")
+        .execute(&app.db_pool)
+        .await
+        .unwrap_err();
+
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200));
+    assert!(elapsed < Duration::from_secs(2), "Took {:?}", elapsed);
+    assert!(is_statement_timeout(&error));
+    let response = unexpected_error(&anyhow::Error::from(error));
+    assert_eq!(response.status().as_u16(), 503);
+}
+
+#[tokio::test]
+async fn connections_carry_the_configured_application_name() {
+    let app = spawn_app_with(|c| c.database.application_name = "zero2prod-canary".into()).await;
+
+    let (application_name,): (String,) = sqlx::query_as(
+        "SELECT application_name::text FROM pg_stat_activity WHERE pid = pg_backend_pid()",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(application_name, "zero2prod-canary");
+}
+
+#[tokio::test]
+async fn warmup_probes_one_connection_per_requested_connection() {
+    let app = spawn_app().await;
+    let pool = PgPoolOptions::new().connect_lazy_with(app.db_pool.connect_options().clone());
+    assert_eq!(pool.size(), 0);
+
+    let probes = warm_up_pool(&pool, 3).await.unwrap();
+
+    assert_eq!(probes, 3);
+    assert_eq!(pool.size(), 3);
+}
+
+fn unreachable_database_config(strict: bool) -> Settings {
+    let mut config = get_configuration().unwrap();
+    config.application.port = 0;
+    config.email_queue.worker_enabled = false;
+    // Nothing listens on port 1.
+    config.database.port = 1;
+    config.database.warmup = true;
+    config.database.warmup_strict = strict;
+    config
+}
+
+#[tokio::test]
+async fn a_failed_warmup_is_only_a_warning_by_default() {
+    let config = unreachable_database_config(false);
+
+    assert!(Application::build(&config).await.is_ok());
+}
+
+#[tokio::test]
+async fn a_failed_strict_warmup_stops_the_application_from_starting() {
+    let config = unreachable_database_config(true);
+
+    assert!(Application::build(&config).await.is_err());
+}
+
+#[tokio::test]
+async fn sql_files_from_the_migrations_path_are_applied_on_start_in_order() {
+    let directory = std::env::temp_dir().join(format!("migrations_{}", Uuid::new_v4()));
+    std::fs::create_dir(&directory).unwrap();
+    std::fs::write(
+        directory.join("002_seed_hotfix.sql"),
+        "INSERT INTO hotfix (note) VALUES ('applied after the table');",
+    )
+    .unwrap();
+    std::fs::write(
+        directory.join("001_create_hotfix.sql"),
+        "CREATE TABLE hotfix (note TEXT NOT NULL); COMMENT ON TABLE hotfix IS 'hotfix';",
+    )
+    .unwrap();
+    std::fs::write(
+        directory.join("001_create_hotfix.down.sql"),
+        "DROP TABLE hotfix;",
+    )
+    .unwrap();
+    std::fs::write(directory.join("README.md"), "Not a migration").unwrap();
+    let path = directory.to_str().unwrap().to_owned();
+
+    // The database starts out empty: the embedded migrations come first.
+    let app = spawn_app_with(|c| c.database.migrations_path = Some(path)).await;
+
+    assert_eq!(app.get_ready().await.status().as_u16(), 200);
+
+    let notes: Vec<String> = sqlx::query_scalar("SELECT note FROM hotfix")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(notes, vec!["applied after the table"]);
+    let applied: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM _runtime_migrations ORDER BY name")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        applied,
+        vec!["001_create_hotfix.sql", "002_seed_hotfix.sql"]
+    );
+    // Already applied files are skipped the next time around.
+    let reapplied = run_directory_migrations(&app.db_pool, &directory)
+        .await
+        .unwrap();
+    assert!(reapplied.is_empty());
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,248 @@
+use std::time::{Duration, Instant};
+
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::EmailProviderSettings;
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+#[tokio::test]
+async fn the_worker_pauses_between_batches() {
+    let app = spawn_app_with(|c| {
+        c.email_queue.worker_enabled = true;
+        c.email_queue.poll_interval_millis = 10;
+        c.email_queue.batch_size = 1;
+        c.email_queue.inter_batch_delay_millis = 500;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let start = Instant::now();
+    for email in ["ursula%40gmail.com", "octavia%40gmail.com"] {
+        let response = app
+            .post_subscriptions(format!("name=writer&email={}", email))
+            .await;
+        assert_eq!(200, response.status().as_u16());
+    }
+    while app.email_server.received_requests().await.unwrap().len() < 2 {
+        assert!(start.elapsed() < Duration::from_secs(10), "Emails not sent");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert!(start.elapsed() >= Duration::from_millis(500));
+    let metrics = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let paused: f64 = metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("email_queue_batch_pause_seconds_total "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(paused >= 0.5);
+}
+
+async fn spawn_app_with_streams() -> TestApp {
+    spawn_app_with(|c| {
+        if let EmailProviderSettings::Postmark {
+            transactional_stream,
+            broadcast_stream,
+            ..
+        } = &mut c.email_client.provider
+        {
+            *transactional_stream = Some("confirmations".into());
+            *broadcast_stream = Some("newsletters".into());
+        }
+    })
+    .await
+}
+
+#[tokio::test]
+async fn confirmations_go_through_the_transactional_stream() {
+    let app = spawn_app_with_streams().await;
+    Mock::given(path("/email"))
+        .and(body_partial_json(
+            serde_json::json!({ "MessageStream": "confirmations" }),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn newsletters_go_through_the_broadcast_stream() {
+    let app = spawn_app_with_streams().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(body_partial_json(
+            serde_json::json!({ "MessageStream": "newsletters" }),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_inactive_recipient_is_suppressed_rather_than_retried() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 406,
+            "Message": "You tried to send to a recipient that has been marked as inactive."
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+    let (reason,): (String,) =
+        sqlx::query_as("SELECT reason FROM suppressions WHERE email = 'ursula_le_guin@gmail.com'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(reason, "inactive_recipient");
+    let (status,): (String,) = sqlx::query_as("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "suppressed");
+}
+
+#[tokio::test]
+async fn an_issue_is_not_sent_to_a_subscriber_past_the_send_cap() {
+    let app = spawn_app_with(|c| c.email_queue.max_sends_per_subscriber = 2).await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    app.dispatch_all_pending_emails().await;
+
+    // Something keeps queueing the same delivery.
+    for _ in 0..3 {
+        sqlx::query(
+            "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id) \
+            SELECT newsletter_issue_id, (SELECT id FROM subscriptions) FROM newsletter_issues",
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+        app.dispatch_all_pending_emails().await;
+    }
+
+    let (status, send_attempts): (String, i32) =
+        sqlx::query_as("SELECT status, send_attempts FROM newsletter_deliveries")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(status, "failed");
+    assert_eq!(send_attempts, 4);
+}
+
+#[tokio::test]
+async fn queued_emails_to_an_address_suppressed_since_are_not_sent() {
+    let app = spawn_app().await;
+    app.login().await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    app.post_suppressions(&serde_json::json!({ "email": "ursula_le_guin@gmail.com" }))
+        .await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let (queued,): (i64,) = sqlx::query_as("SELECT count(*) FROM email_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+}
+
+#[tokio::test]
+async fn an_issue_is_not_delivered_to_an_address_suppressed_since_it_was_queued() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    app.post_suppressions(&serde_json::json!({ "email": "ursula_le_guin@gmail.com" }))
+        .await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let (status,): (String,) = sqlx::query_as("SELECT status FROM newsletter_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "failed");
+}
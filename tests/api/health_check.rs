@@ -1,4 +1,11 @@
-use crate::helpers::spawn_app;
+use actix_web::{test as actix_test, web, App};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use zero2prod::configuration::get_configuration;
+use zero2prod::error::ErrorBody;
+use zero2prod::metrics::Metrics;
+use zero2prod::routes::{health_checker, HealthReport, HealthStatus};
+use zero2prod::startup::get_connection_pool;
 
 #[tokio::test]
 async fn health_check_works() {
@@ -7,7 +14,7 @@ async fn health_check_works() {
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("{}/health_check", &app.address))
+        .get(format!("{}/health_check", &app.address))
         .send()
         .await
         .expect("Failed to send request");
@@ -15,3 +22,181 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[tokio::test]
+async fn the_server_listens_with_a_configured_backlog() {
+    let app = spawn_app_with(|c| c.application.backlog = Some(16)).await;
+
+    let response = app.get_health_check().await;
+
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn the_health_check_is_served_at_the_configured_path() {
+    let app = spawn_app_with(|c| c.application.health_check_path = "/healthz".into()).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/healthz", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_health_check().await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn verbose_health_check_details_the_database() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/health_check?verbose=true", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["checks"]["database"]["latency_ms"].is_u64());
+    let report: HealthReport = serde_json::from_value(body).unwrap();
+    assert_eq!(report.status, HealthStatus::Ok);
+    assert_eq!(report.checks["database"].status, HealthStatus::Ok);
+}
+
+#[tokio::test]
+async fn connections_are_closed_when_keep_alive_is_disabled() {
+    let app = spawn_app_with(|c| c.application.keep_alive_seconds = 0).await;
+
+    let response = app.get_health_check().await;
+
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["Connection"], "close");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn health_check_works_over_a_unix_socket() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let socket_path = std::env::temp_dir().join(format!("zero2prod-{}.sock", uuid::Uuid::new_v4()));
+    let path = socket_path.to_str().unwrap().to_owned();
+    let _app = spawn_app_with(|c| c.application.socket_path = Some(path)).await;
+
+    let mut stream = tokio::net::UnixStream::connect(&socket_path)
+        .await
+        .expect("Failed to connect to the unix socket");
+    stream
+        .write_all(b"GET /health_check HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    std::fs::remove_file(socket_path).unwrap();
+}
+
+#[tokio::test]
+async fn ready_when_every_migration_is_applied() {
+    let app = spawn_app().await;
+
+    let response = app.get_ready().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn not_ready_when_a_migration_is_missing() {
+    let app = spawn_app().await;
+    let latest: i64 = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+        .bind(latest)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.get_ready().await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "pending_migrations");
+    assert!(error.message.contains(&latest.to_string()));
+}
+
+#[tokio::test]
+async fn the_application_can_migrate_a_fresh_database_on_start() {
+    let app = spawn_app_with(|c| c.database.migrate_on_start = true).await;
+
+    let response = app.get_ready().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriptions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The schema was not applied");
+    assert_eq!(subscriptions, 0);
+}
+
+#[tokio::test]
+async fn routes_are_mounted_under_the_base_path() {
+    let app = spawn_app_with(|c| c.application.base_path = "/api/v1".into()).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_health_check().await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn anonymous_users_are_redirected_to_the_prefixed_login() {
+    let app = spawn_app_with(|c| c.application.base_path = "/api/v1".into()).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/admin/subscriptions", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_is_redirect_to(&response, "/api/v1/login");
+}
+
+#[tokio::test]
+async fn an_unreachable_database_is_reported_without_the_details() {
+    let mut config = get_configuration().unwrap();
+    // Nothing listens on port 1.
+    config.database.port = 1;
+    let service = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(get_connection_pool(&config.database)))
+            .app_data(web::Data::new(Metrics::new()))
+            .route("/health_check", web::get().to(health_checker)),
+    )
+    .await;
+
+    let request = actix_test::TestRequest::get()
+        .uri("/health_check?verbose=true")
+        .to_request();
+    let response = actix_test::call_service(&service, request).await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let report: HealthReport = actix_test::read_body_json(response).await;
+    assert_eq!(report.status, HealthStatus::Error);
+    assert_eq!(
+        report.checks["database"].error.as_deref(),
+        Some("unreachable")
+    );
+}
@@ -1,10 +1,26 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
 use once_cell::sync::Lazy;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use tracing_subscriber::fmt::MakeWriter;
 use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::authentication::compute_password_hash;
+use zero2prod::clock::MockClock;
+use zero2prod::configuration::{
+    get_configuration, DatabaseSettings, EmailProviderSettings, EmailQueueSettings, Settings,
+    WebhookCredentials,
+};
+use zero2prod::email_client::EmailClient;
+use zero2prod::email_worker::{try_execute_task, ExecutionOutcome};
 use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::test_databases::TEST_DATABASE_PREFIX;
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
@@ -20,49 +36,525 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     }
 });
 
+/// A log sink tests can read back, for handlers called in-process.
+#[derive(Clone, Default)]
+pub struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Confirmation links embedded in the request to the email API.
+pub struct ConfirmationLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        let password_hash = compute_password_hash(Secret::new(self.password.clone())).unwrap();
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash.expose_secret(),
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
+}
+
 pub struct TestApp {
     pub address: String,
+    pub port: u16,
     pub db_pool: PgPool,
+    pub email_server: MockServer,
+    pub test_user: TestUser,
+    pub api_client: reqwest::Client,
+    pub postmark_webhook_credentials: WebhookCredentials,
+    pub email_client: EmailClient,
+    pub email_queue: EmailQueueSettings,
+    /// The base of the links in emails.
+    pub base_url: String,
+    /// The application's clock: it only moves when advanced.
+    pub clock: Arc<MockClock>,
 }
 
 impl TestApp {
+    /// The background worker is disabled in tests: send the queued emails
+    /// now.
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.email_queue,
+                &self.base_url,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
-        reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    /// Subscribe as a client asking for JSON and retrying with `key`.
+    pub async fn post_subscriptions_with_idempotency_key(
+        &self,
+        body: String,
+        key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept", "application/json")
+            .header("Idempotency-Key", key)
             .body(body)
             .send()
             .await
             .expect("Request failed")
     }
+
+    pub async fn post_resend_confirmation(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/resend", &self.address))
+            .form(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn get_subscription_status(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscriptions/status", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn post_login(&self, username: &str, password: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(&serde_json::json!({
+                "username": username,
+                "password": password,
+            }))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    /// Log in as the test user; subsequent requests carry the session cookie.
+    pub async fn login(&self) {
+        let response = self
+            .post_login(&self.test_user.username, &self.test_user.password)
+            .await;
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    pub async fn post_newsletters(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/newsletters", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn get_admin_subscriptions(&self, etag: Option<&str>) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .get(format!("{}/admin/subscriptions", &self.address));
+        if let Some(etag) = etag {
+            request = request.header("If-None-Match", etag);
+        }
+        request.send().await.expect("Request failed")
+    }
+
+    pub async fn search_subscriptions(&self, q: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscriptions/search", &self.address))
+            .query(&[("q", q)])
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn delete_subscriber(&self, subscriber_id: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!(
+                "{}/admin/subscriptions/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn post_resend_pending(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscriptions/resend-pending",
+                &self.address
+            ))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn get_subscription_funnel(&self, from: &str, to: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscriptions/funnel", &self.address))
+            .query(&[("from", from), ("to", to)])
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn get_newsletter_history(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters", &self.address))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn get_deliveries(&self, issue_id: &str, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/{}/deliveries?{}",
+                &self.address, issue_id, query
+            ))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn post_change_password(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn get_audit_log(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit_log", &self.address))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn get_email_preview(&self, email_type: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/email/preview?type={}",
+                &self.address, email_type
+            ))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn get_feature_flags(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/feature_flags", &self.address))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn put_feature_flag(&self, name: &str, enabled: bool) -> reqwest::Response {
+        self.api_client
+            .put(format!("{}/admin/feature_flags/{}", &self.address, name))
+            .json(&serde_json::json!({ "enabled": enabled }))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn get_suppressions(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn post_suppressions(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/suppressions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn delete_suppression(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!("{}/admin/suppressions/{}", &self.address, email))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn post_postmark_webhook(&self, event: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                &self.postmark_webhook_credentials.username,
+                Some(self.postmark_webhook_credentials.password.expose_secret()),
+            )
+            .json(event)
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    pub async fn get_health_check(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/health_check", &self.address))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    /// The metrics in Prometheus' text format.
+    pub async fn get_metrics(&self) -> String {
+        let response = self
+            .api_client
+            .get(format!("{}/metrics", &self.address))
+            .send()
+            .await
+            .expect("Request failed");
+        assert_eq!(response.status().as_u16(), 200);
+        response.text().await.unwrap()
+    }
+
+    pub async fn get_ready(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/ready", &self.address))
+            .send()
+            .await
+            .expect("Request failed")
+    }
+
+    /// Subscribe `email` without clicking the confirmation link.
+    pub async fn create_unconfirmed_subscriber(
+        &self,
+        name: &str,
+        email: &str,
+    ) -> ConfirmationLinks {
+        self.create_unconfirmed_subscriber_with(serde_json::json!({
+            "name": name,
+            "email": email,
+        }))
+        .await
+    }
+
+    /// Submit the subscription `form` without clicking the confirmation link.
+    pub async fn create_unconfirmed_subscriber_with(
+        &self,
+        form: serde_json::Value,
+    ) -> ConfirmationLinks {
+        let body = serde_urlencoded::to_string(form).unwrap();
+
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .named("Create unconfirmed subscriber")
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+        self.post_subscriptions(body)
+            .await
+            .error_for_status()
+            .unwrap();
+        self.dispatch_all_pending_emails().await;
+
+        let email_request = &self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        self.get_confirmation_links(email_request)
+    }
+
+    pub async fn create_confirmed_subscriber(&self, name: &str, email: &str) {
+        self.create_confirmed_subscriber_with(serde_json::json!({
+            "name": name,
+            "email": email,
+        }))
+        .await
+    }
+
+    pub async fn create_confirmed_subscriber_with(&self, form: serde_json::Value) {
+        let confirmation_link = self.create_unconfirmed_subscriber_with(form).await;
+        reqwest::get(confirmation_link.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    /// Extract the confirmation links embedded in the request to the email API.
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links(email_request, "/subscriptions/confirm")
+    }
+
+    /// The links to manage the subscription, in the footer of every email.
+    pub fn get_manage_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links(email_request, "/subscriptions/status")
+    }
+
+    fn get_links(&self, email_request: &wiremock::Request, path: &str) -> ConfirmationLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+        let get_link = |s: &str| {
+            let links: Vec<_> = linkify::LinkFinder::new()
+                .links(s)
+                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .map(|l| reqwest::Url::parse(l.as_str()).unwrap())
+                .filter(|l| l.path().ends_with(path))
+                .collect();
+            assert_eq!(links.len(), 1);
+            let mut link = links[0].clone();
+            // Make sure we don't call random APIs on the web
+            assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+            link.set_port(Some(self.port)).unwrap();
+            link
+        };
+
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
+        ConfirmationLinks { html, plain_text }
+    }
+}
+
+/// A random password satisfying the default password policy.
+pub fn strong_password() -> String {
+    format!("Aa1!{}", Uuid::new_v4())
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application after letting the caller tweak its configuration.
+pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
+    let email_server = MockServer::start().await;
+
     let config = {
         let mut c = get_configuration().expect("Failed to read config");
-        c.database.database_name = format!("test_subscriptions_{}", Uuid::new_v4().to_string());
+        c.database.database_name = format!("{}{}", TEST_DATABASE_PREFIX, Uuid::new_v4());
         c.application.port = 0;
+        c.email_client.provider = EmailProviderSettings::Postmark {
+            base_url: email_server.uri(),
+            authorization_token: Secret::new("my-secret-token".into()),
+            transactional_stream: None,
+            broadcast_stream: None,
+        };
+        c.email_queue.worker_enabled = false;
+        // Tests hammer the endpoints; those exercising the limits opt in.
+        c.rate_limits.subscribe = None;
+        c.rate_limits.login = None;
+        c.rate_limits.confirmation_resend = None;
+        customise(&mut c);
         c
     };
 
-    configure_database(&config.database).await;
+    // With `migrate_on_start` or a `migrations_path` the application migrates
+    // its own database.
+    let migrate = !config.database.migrate_on_start && config.database.migrations_path.is_none();
+    configure_database(&config.database, migrate).await;
 
-    let application = Application::build(&config)
+    let clock = Arc::new(MockClock::new(Utc::now()));
+    let application = Application::build_with_clock(&config, clock.clone())
         .await
         .expect("Failed to build test server");
 
-    let address = format!("http://127.0.0.1:{}", application.port());
-    let _ = tokio::spawn(application.run_until_stopped());
+    let port = application.port();
+    let address = format!("http://127.0.0.1:{}", port);
+    tokio::spawn(application.run_until_stopped());
 
-    TestApp {
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+
+    let test_app = TestApp {
         db_pool: get_connection_pool(&config.database),
         address,
-    }
+        port,
+        email_server,
+        test_user: TestUser::generate(),
+        api_client,
+        postmark_webhook_credentials: config.webhooks.postmark,
+        email_client: config.email_client.client().unwrap(),
+        email_queue: config.email_queue,
+        base_url: config.application.links_base_url(),
+        clock,
+    };
+    test_app.test_user.store(&test_app.db_pool).await;
+    test_app
 }
 
-async fn configure_database(config: &DatabaseSettings) -> PgPool {
+async fn configure_database(config: &DatabaseSettings, migrate: bool) -> PgPool {
     // Create database
     let mut connection = PgConnection::connect_with(&config.without_db())
         .await
@@ -75,9 +567,16 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
     let connection_pool = PgPool::connect_with(config.with_db())
         .await
         .expect("Failed to connect to Postgres.");
-    sqlx::migrate!("./migrations")
-        .run(&connection_pool)
-        .await
-        .expect("Failed to migrate the database");
+    if migrate {
+        sqlx::migrate!("./migrations")
+            .run(&connection_pool)
+            .await
+            .expect("Failed to migrate the database");
+    }
     connection_pool
 }
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
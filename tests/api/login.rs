@@ -0,0 +1,76 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+use zero2prod::error::ErrorBody;
+
+#[tokio::test]
+async fn invalid_credentials_are_rejected_with_a_401() {
+    let app = spawn_app().await;
+
+    let response = app.post_login("random-username", "random-password").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_credentials");
+}
+
+#[tokio::test]
+async fn a_wrong_password_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_login(&app.test_user.username, "wrong-password")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn valid_credentials_open_an_admin_session() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_login(&app.test_user.username, &app.test_user.password)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_suppressions().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_account_is_locked_after_too_many_failed_logins() {
+    let app = spawn_app_with(|c| c.application.login_max_failed_attempts = 3).await;
+
+    for _ in 0..3 {
+        let response = app
+            .post_login(&app.test_user.username, "wrong-password")
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Even the right password is rejected while the account is locked.
+    let response = app
+        .post_login(&app.test_user.username, &app.test_user.password)
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "account_locked");
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failed_login_count() {
+    let app = spawn_app_with(|c| c.application.login_max_failed_attempts = 3).await;
+
+    for _ in 0..2 {
+        app.post_login(&app.test_user.username, "wrong-password")
+            .await;
+    }
+    app.login().await;
+    for _ in 0..2 {
+        let response = app
+            .post_login(&app.test_user.username, "wrong-password")
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    app.login().await;
+}
@@ -1,3 +1,29 @@
+mod admin_audit_log;
+mod admin_deliveries;
+mod admin_email_preview;
+mod admin_feature_flags;
+mod admin_newsletters;
+mod admin_password;
+mod admin_subscriptions;
+mod admin_suppressions;
+mod concurrency_limits;
+mod cors;
+mod database;
+mod email_worker;
 mod health_check;
 mod helpers;
+mod login;
+mod maintenance;
+mod metrics;
+mod newsletters;
+mod openapi;
+mod rate_limits;
+mod routing;
+mod seed_admin;
 mod subscriptions;
+mod subscriptions_confirm;
+mod subscriptions_resend;
+mod subscriptions_status;
+mod test_databases;
+mod topics;
+mod webhooks;
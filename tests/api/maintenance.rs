@@ -0,0 +1,38 @@
+use crate::helpers::spawn_app_with;
+use zero2prod::error::ErrorBody;
+
+#[tokio::test]
+async fn subscribe_returns_503_while_health_check_stays_up_in_maintenance_mode() {
+    let app = spawn_app_with(|c| c.application.maintenance_mode = true).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(503, response.status().as_u16());
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "maintenance");
+
+    let saved = sqlx::query!("SELECT id FROM subscriptions;")
+        .fetch_optional(&app.db_pool)
+        .await
+        .expect("Could not exec query");
+    assert!(saved.is_none());
+
+    let response = app.get_health_check().await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn publishing_a_newsletter_returns_503_in_maintenance_mode() {
+    let app = spawn_app_with(|c| c.application.maintenance_mode = true).await;
+    app.login().await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+
+    assert_eq!(503, response.status().as_u16());
+}
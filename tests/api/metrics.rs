@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, spawn_app_with};
+
+/// The value of the sample called `name` in Prometheus' text format.
+fn sample(metrics: &str, name: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or_else(|| panic!("No {} sample in:\n{}", name, metrics))
+}
+
+#[tokio::test]
+async fn email_send_latency_is_recorded() {
+    // Only the application's own worker reports to its metrics.
+    let app = spawn_app_with(|c| {
+        c.email_queue.worker_enabled = true;
+        c.email_queue.poll_interval_millis = 10;
+    })
+    .await;
+    sqlx::query(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+        VALUES ($1, 'ursula_le_guin@gmail.com', 'le guin', now(), 'confirmed')",
+    )
+    .bind(Uuid::new_v4())
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(300)))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let start = Instant::now();
+    while sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        > 0
+    {
+        assert!(start.elapsed() < Duration::from_secs(10), "Not delivered");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let metrics = app.get_metrics().await;
+    let sends = sample(
+        &metrics,
+        r#"email_send_duration_seconds_count{provider="postmark"}"#,
+    );
+    let fast_sends = sample(
+        &metrics,
+        r#"email_send_duration_seconds_bucket{provider="postmark",le="0.25"}"#,
+    );
+    // At least the newsletter took longer than the mock's 300ms delay.
+    assert!(sends - fast_sends >= 1.0);
+}
+
+#[tokio::test]
+async fn the_signup_funnel_is_counted() {
+    let app = spawn_app().await;
+
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_unconfirmed_subscriber("butler", "octavia_butler@gmail.com")
+        .await;
+
+    let metrics = app.get_metrics().await;
+    assert_eq!(sample(&metrics, "subscriptions_created_total"), 2.0);
+    assert_eq!(sample(&metrics, "confirmations_completed_total"), 1.0);
+}
+
+#[tokio::test]
+async fn connection_acquisition_latency_is_recorded() {
+    let app = spawn_app().await;
+
+    for (name, email) in [
+        ("le guin", "ursula_le_guin@gmail.com"),
+        ("butler", "octavia_butler@gmail.com"),
+        ("jemisin", "nk_jemisin@gmail.com"),
+    ] {
+        app.create_confirmed_subscriber(name, email).await;
+    }
+
+    let metrics = app.get_metrics().await;
+    // One transaction to subscribe and one to confirm, per subscriber.
+    assert_eq!(
+        sample(&metrics, "db_pool_acquire_duration_seconds_count"),
+        6.0
+    );
+    for quantile in ["0.5", "0.95", "0.99"] {
+        let estimate = sample(
+            &metrics,
+            &format!(
+                r#"db_pool_acquire_duration_seconds_quantile{{quantile="{}"}}"#,
+                quantile
+            ),
+        );
+        assert!(estimate >= 0.0, "{}", metrics);
+    }
+
+    // Reads outside of a transaction are timed too.
+    app.get_health_check().await;
+    let metrics = app.get_metrics().await;
+    assert_eq!(
+        sample(&metrics, "db_pool_acquire_duration_seconds_count"),
+        7.0
+    );
+}
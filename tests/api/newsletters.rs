@@ -0,0 +1,291 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::error::ErrorBody;
+use zero2prod::newsletter_content::TextBodyCheckMode;
+use zero2prod::routes::PublishedIssue;
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn newsletters_skip_suppressed_subscribers() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("octavia", "octavia_butler@gmail.com")
+        .await;
+    app.login().await;
+    app.post_suppressions(&serde_json::json!({ "email": "ursula_le_guin@gmail.com" }))
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = &app.email_server.received_requests().await.unwrap()[2];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "octavia_butler@gmail.com");
+}
+
+#[tokio::test]
+async fn publishing_reports_how_many_deliveries_were_queued() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("octavia", "octavia_butler@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("n. k.", "nk_jemisin@gmail.com")
+        .await;
+    app.create_unconfirmed_subscriber("ted", "ted_chiang@gmail.com")
+        .await;
+    app.login().await;
+    app.post_suppressions(&serde_json::json!({ "email": "ursula_le_guin@gmail.com" }))
+        .await;
+
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let issue: PublishedIssue = response.json().await.unwrap();
+    assert_eq!(issue.queued, 2);
+}
+
+#[tokio::test]
+async fn newsletter_emails_are_tagged_with_the_issue_and_the_subscriber() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let issue: PublishedIssue = app
+        .post_newsletters(&newsletter_request_body())
+        .await
+        .json()
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(body["Metadata"]["issue_id"], issue.issue_id.to_string());
+    assert!(body["Metadata"]["subscriber_id"].is_string());
+}
+
+#[tokio::test]
+async fn deliveries_are_queued_by_reference_and_rendered_at_send_time() {
+    let app = spawn_app().await;
+    for name in ["a", "b", "c"] {
+        app.create_confirmed_subscriber(name, &format!("{}@example.com", name))
+            .await;
+    }
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let issue: PublishedIssue = response.json().await.unwrap();
+
+    let issues: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues, 1);
+    let queued: Vec<Uuid> =
+        sqlx::query_scalar("SELECT newsletter_issue_id FROM issue_delivery_queue")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(queued, vec![issue.issue_id; 3]);
+
+    app.dispatch_all_pending_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    // The first three requests are the confirmation emails.
+    for request in &requests[3..] {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["Subject"], "Newsletter title");
+        // Followed by the link to manage the subscription.
+        assert!(body["HtmlBody"]
+            .as_str()
+            .unwrap()
+            .starts_with("<p>Newsletter body as HTML</p>"));
+        assert!(body["TextBody"]
+            .as_str()
+            .unwrap()
+            .starts_with("Newsletter body as plain text"));
+    }
+    let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+}
+
+#[tokio::test]
+async fn publishing_rejects_an_invalid_title() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    for title in ["", " \n ", &"a".repeat(201)] {
+        let response = app
+            .post_newsletters(&serde_json::json!({
+                "title": title,
+                "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+            }))
+            .await;
+
+        assert_eq!(response.status().as_u16(), 422, "Accepted {:?}", title);
+        let body: ErrorBody = response.json().await.unwrap();
+        assert_eq!(body.code, "invalid_newsletter");
+    }
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_publish_a_newsletter() {
+    let app = spawn_app().await;
+
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn publishing_explains_which_field_is_missing() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "content": { "text": "Plain text", "html": "<p>HTML</p>" }
+        }))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_body");
+    assert!(
+        error.message.contains("missing field `title`"),
+        "Unexpected message: {}",
+        error.message
+    );
+}
+
+#[tokio::test]
+async fn newsletters_are_sent_from_the_requested_sender() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let mut body = newsletter_request_body();
+    body["sender"] = "editor@example.com".into();
+    let response = app.post_newsletters(&body).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["From"], "editor@example.com");
+}
+
+#[tokio::test]
+async fn publishing_rejects_an_invalid_sender() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let mut body = newsletter_request_body();
+    body["sender"] = "not-an-email".into();
+    let response = app.post_newsletters(&body).await;
+
+    assert_eq!(response.status().as_u16(), 422);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_newsletter");
+}
+
+#[tokio::test]
+async fn an_out_of_date_text_body_is_only_rejected_when_configured_to() {
+    let mut body = newsletter_request_body();
+    body["content"]["text"] = "".into();
+
+    let app = spawn_app().await;
+    app.login().await;
+    let response = app.post_newsletters(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let app = spawn_app_with(|c| c.text_body_check.mode = TextBodyCheckMode::Reject).await;
+    app.login().await;
+    let response = app.post_newsletters(&body).await;
+    assert_eq!(response.status().as_u16(), 422);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_newsletter");
+}
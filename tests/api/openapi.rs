@@ -0,0 +1,52 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+
+async fn get_openapi_document(address: &str) -> serde_json::Value {
+    let response = reqwest::get(format!("{}/api-docs/openapi.json", address))
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn the_document_describes_posting_to_subscriptions() {
+    let app = spawn_app().await;
+
+    let document = get_openapi_document(&app.address).await;
+
+    let post = &document["paths"]["/subscriptions"]["post"];
+    assert!(post.is_object());
+    assert!(
+        post["requestBody"]["content"]["application/x-www-form-urlencoded"]["schema"].is_object()
+    );
+    for status in ["200", "400", "409", "422"] {
+        assert!(post["responses"][status].is_object(), "Missing {}", status);
+    }
+}
+
+#[tokio::test]
+async fn the_document_describes_confirm_and_newsletters() {
+    let app = spawn_app().await;
+
+    let document = get_openapi_document(&app.address).await;
+
+    assert!(document["paths"]["/subscriptions/confirm"]["get"].is_object());
+    assert!(document["paths"]["/subscriptions/confirm/{subscription_token}"]["get"].is_object());
+    assert!(document["paths"]["/subscriptions/resend"]["post"].is_object());
+    assert!(document["paths"]["/subscriptions/status"]["get"].is_object());
+    let publish = &document["paths"]["/newsletters"]["post"];
+    assert_eq!(
+        publish["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/PublishedIssue"
+    );
+    assert!(document["components"]["schemas"]["PublishedIssue"].is_object());
+}
+
+#[tokio::test]
+async fn the_document_points_at_the_base_path() {
+    let app = spawn_app_with(|c| c.application.base_path = "/api/v1".into()).await;
+
+    let document = get_openapi_document(&format!("{}/api/v1", app.address)).await;
+
+    assert_eq!(document["servers"][0]["url"], "/api/v1");
+}
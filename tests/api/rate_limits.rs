@@ -0,0 +1,80 @@
+use crate::helpers::spawn_app_with;
+use zero2prod::error::ErrorBody;
+use zero2prod::rate_limit::RateLimit;
+
+fn limit(requests: u32) -> Option<RateLimit> {
+    Some(RateLimit {
+        requests,
+        window_seconds: 60,
+    })
+}
+
+#[tokio::test]
+async fn login_trips_its_own_limit_independently_of_subscribe() {
+    let app = spawn_app_with(|c| {
+        c.rate_limits.login = limit(2);
+        c.rate_limits.subscribe = limit(5);
+    })
+    .await;
+
+    for _ in 0..2 {
+        let response = app.post_login("random-username", "random-password").await;
+        assert_eq!(401, response.status().as_u16());
+    }
+    let response = app.post_login("random-username", "random-password").await;
+
+    assert_eq!(429, response.status().as_u16());
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "rate_limited");
+
+    // Subscribing from the same address is counted separately.
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_trips_its_own_limit_independently_of_login() {
+    let app = spawn_app_with(|c| {
+        c.rate_limits.login = limit(5);
+        c.rate_limits.subscribe = limit(1);
+    })
+    .await;
+
+    let response = app.post_subscriptions("name=".into()).await;
+    assert_eq!(400, response.status().as_u16());
+    let response = app.post_subscriptions("name=".into()).await;
+    assert_eq!(429, response.status().as_u16());
+    assert!(response.headers().contains_key("Retry-After"));
+
+    app.login().await;
+}
+
+#[tokio::test]
+async fn login_attempts_are_counted_per_username() {
+    let app = spawn_app_with(|c| c.rate_limits.login = limit(1)).await;
+
+    let response = app.post_login("random-username", "random-password").await;
+    assert_eq!(401, response.status().as_u16());
+    let response = app.post_login("random-username", "random-password").await;
+    assert_eq!(429, response.status().as_u16());
+
+    // Somebody else behind the same address can still log in.
+    app.login().await;
+}
+
+#[tokio::test]
+async fn the_health_check_is_not_limited() {
+    let app = spawn_app_with(|c| c.rate_limits.subscribe = limit(1)).await;
+
+    for _ in 0..5 {
+        let response = app.get_health_check().await;
+        assert_eq!(200, response.status().as_u16());
+    }
+}
@@ -0,0 +1,71 @@
+use zero2prod::error::ErrorBody;
+
+use crate::helpers::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn unknown_paths_return_a_json_404() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/does/not/exist", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 404);
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!(body.code, "not_found");
+}
+
+#[tokio::test]
+async fn unknown_paths_under_the_base_path_return_a_json_404() {
+    let app = spawn_app_with(|c| c.application.base_path = "/api/v1".into()).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/does/not/exist", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 404);
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!(body.code, "not_found");
+}
+
+#[tokio::test]
+async fn a_disallowed_method_returns_405_with_an_allow_header() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 405);
+    assert_eq!(response.headers()["Allow"], "GET");
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!(body.code, "method_not_allowed");
+}
+
+#[tokio::test]
+async fn the_allow_header_lists_every_method_of_the_route() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .api_client
+        .put(format!("{}/admin/suppressions", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 405);
+    let allow = response.headers()["Allow"].to_str().unwrap();
+    let mut methods: Vec<_> = allow.split(", ").collect();
+    methods.sort();
+    assert_eq!(methods, vec!["GET", "POST"]);
+}
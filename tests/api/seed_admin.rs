@@ -0,0 +1,87 @@
+use secrecy::Secret;
+use zero2prod::authentication::{
+    seed_admin, validate_credentials, Credentials, PasswordPolicy, SeedAdminError,
+};
+use zero2prod::metrics::Metrics;
+
+use crate::helpers::{spawn_app, strong_password};
+
+#[tokio::test]
+async fn seeding_an_admin_stores_a_hash_that_validates() {
+    let app = spawn_app().await;
+    let password = strong_password();
+
+    let user_id = seed_admin(
+        "admin",
+        Secret::new(password.clone()),
+        &PasswordPolicy::default(),
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    let stored = sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(stored.password_hash.starts_with("$argon2id$"));
+    assert!(!stored.password_hash.contains(&password));
+    let credentials = Credentials {
+        username: "admin".into(),
+        password: Secret::new(password.clone()),
+    };
+    assert_eq!(
+        validate_credentials(credentials, &app.db_pool, &Metrics::new())
+            .await
+            .unwrap(),
+        user_id
+    );
+    let response = app.post_login("admin", &password).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn seeding_an_existing_admin_resets_their_password() {
+    let app = spawn_app().await;
+    let new_password = strong_password();
+
+    let user_id = seed_admin(
+        &app.test_user.username,
+        Secret::new(new_password.clone()),
+        &PasswordPolicy::default(),
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(user_id, app.test_user.user_id);
+    let response = app
+        .post_login(&app.test_user.username, &app.test_user.password)
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_login(&app.test_user.username, &new_password).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn seeding_an_admin_with_a_weak_password_is_rejected() {
+    let app = spawn_app().await;
+
+    let result = seed_admin(
+        "admin",
+        Secret::new("password".into()),
+        &PasswordPolicy::default(),
+        &app.db_pool,
+    )
+    .await;
+
+    assert!(matches!(result, Err(SeedAdminError::WeakPassword(_))));
+    let user = sqlx::query!("SELECT user_id FROM users WHERE username = 'admin'")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(user.is_none());
+}
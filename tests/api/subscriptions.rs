@@ -1,21 +1,94 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::error::ErrorBody;
+use zero2prod::routes::{store_new_token, SubscribeResponse, TokenKind};
 
 #[tokio::test]
 async fn subscribe_returns_200_for_valid_form_data() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.to_string()).await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_answers_with_an_empty_body_by_default() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
     let response = app.post_subscriptions(body.to_string()).await;
 
     assert_eq!(200, response.status().as_u16());
+    assert!(response.bytes().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn subscribe_answers_with_the_subscriber_when_asked_for_json() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept", "application/json")
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    let body: SubscribeResponse = response.json().await.unwrap();
+    assert_eq!(body.status, "pending_confirmation");
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(body.id, saved.id);
+}
+
+#[tokio::test]
+async fn subscribe_answers_with_the_subscriber_when_configured_to() {
+    let app = spawn_app_with(|c| c.application.json_subscribe_response = true).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let response = app.post_subscriptions(body.to_string()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let body: SubscribeResponse = response.json().await.unwrap();
+    assert_eq!(body.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribe_persists_the_new_subscriber() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.to_string()).await;
 
-    let saved = sqlx::query!("SELECT email, name FROM subscriptions;")
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions;")
         .fetch_one(&app.db_pool)
         .await
         .expect("Could not exec query");
 
     assert_eq!(saved.email, "ursula_le_guin@gmail.com");
     assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.status, "pending_confirmation");
 }
 
 #[tokio::test]
@@ -41,7 +114,35 @@ async fn subscribe_returns_a_400_when_data_is_missing() {
 }
 
 #[tokio::test]
-async fn subscribe_returns_a_400_when_data_is_present_but_empty() {
+async fn subscribe_rejects_a_malformed_body_with_a_malformed_body_code() {
+    let app = spawn_app().await;
+
+    let test_cases = vec![
+        (
+            "name=le%zzguin&email=ursula_le_guin%40gmail.com",
+            "invalid percent-encoding",
+        ),
+        (
+            "name=le%2&email=ursula_le_guin%40gmail.com",
+            "truncated percent-encoding",
+        ),
+        (
+            "name=le%FFguin&email=ursula_le_guin%40gmail.com",
+            "not UTF-8 once decoded",
+        ),
+    ];
+
+    for (malformed_body, description) in test_cases {
+        let response = app.post_subscriptions(malformed_body.to_string()).await;
+
+        assert_eq!(400, response.status().as_u16(), "{}", description);
+        let error: ErrorBody = response.json().await.unwrap();
+        assert_eq!(error.code, "malformed_body", "{}", description);
+    }
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_422_when_data_is_present_but_empty() {
     let app = spawn_app().await;
 
     let test_cases = vec![
@@ -57,10 +158,694 @@ async fn subscribe_returns_a_400_when_data_is_present_but_empty() {
         let response = app.post_subscriptions(invalid_body.to_string()).await;
 
         assert_eq!(
-            400,
+            422,
             response.status().as_u16(),
-            "The API did not fail with 400 Bad Request when the payload was {}.",
+            "The API did not fail with 422 Unprocessable Entity when the payload was {}.",
             error_message
         );
     }
 }
+
+#[tokio::test]
+async fn subscribe_returns_a_422_for_an_invalid_email() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=definitely-not-an-email".into())
+        .await;
+
+    assert_eq!(422, response.status().as_u16());
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_subscriber");
+}
+
+#[tokio::test]
+async fn subscribe_reports_every_invalid_field() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=&email=definitely-not-an-email".into())
+        .await;
+
+    assert_eq!(422, response.status().as_u16());
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_subscriber");
+    let fields: Vec<_> = error
+        .errors
+        .iter()
+        .map(|e| (e.field.as_str(), e.code.as_str()))
+        .collect();
+    assert_eq!(fields, vec![("name", "invalid"), ("email", "invalid")]);
+}
+
+#[tokio::test]
+async fn the_legacy_validation_status_answers_an_invalid_email_with_a_400() {
+    let app = spawn_app_with(|c| c.application.legacy_validation_status = true).await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=definitely-not-an-email".into())
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_subscriber");
+}
+
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_with_a_link() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+}
+
+#[tokio::test]
+async fn confirmation_links_use_the_configured_base_url_whatever_the_request() {
+    let app =
+        spawn_app_with(|c| c.application.base_url = "https://zero2prod.example.com".into()).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Host", "internal.example.com")
+        .header("X-Forwarded-Proto", "http")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    for field in ["HtmlBody", "TextBody"] {
+        let content = body[field].as_str().unwrap();
+        assert!(
+            content.contains("https://zero2prod.example.com/subscriptions/confirm?"),
+            "{}",
+            content
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_does_not_send_a_confirmation_email_to_a_suppressed_address() {
+    let app = spawn_app().await;
+    app.login().await;
+    app.post_suppressions(&serde_json::json!({ "email": "ursula_le_guin@gmail.com" }))
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_succeeds_and_queues_the_email_when_the_provider_is_down() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "pending_confirmation");
+    let queued = sqlx::query!("SELECT recipient, n_retries FROM email_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the queued email.");
+    assert_eq!(queued.recipient, "ursula_le_guin@gmail.com");
+    assert_eq!(queued.n_retries, 1);
+}
+
+#[tokio::test]
+async fn subscribe_explains_which_field_is_missing() {
+    let app = spawn_app().await;
+
+    let response = app.post_subscriptions("name=le%20guin".to_string()).await;
+
+    assert_eq!(400, response.status().as_u16());
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_body");
+    assert!(
+        error.message.contains("missing field `email`"),
+        "Unexpected message: {}",
+        error.message
+    );
+}
+
+#[tokio::test]
+async fn subscribe_ignores_submissions_with_a_filled_in_honeypot() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.example";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.to_string()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .expect("Could not exec query");
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn subscribe_accepts_a_blank_honeypot() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&website=";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.to_string()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Could not exec query");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+const TRUSTED_SOURCE_API_KEY: &str = "partner-api-key";
+
+async fn post_trusted_subscription(app: &TestApp, api_key: Option<&str>) -> reqwest::Response {
+    let mut request = app
+        .api_client
+        .post(format!("{}/subscriptions/trusted", &app.address))
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+        }));
+    if let Some(api_key) = api_key {
+        request = request.bearer_auth(api_key);
+    }
+    request.send().await.expect("Request failed")
+}
+
+#[tokio::test]
+async fn a_trusted_source_creates_confirmed_subscribers_without_an_email() {
+    let app = spawn_app_with(|c| {
+        c.trusted_sources.api_key = Some(Secret::new(TRUSTED_SOURCE_API_KEY.into()));
+    })
+    .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_trusted_subscription(&app, Some(TRUSTED_SOURCE_API_KEY)).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Could not exec query");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn an_untrusted_source_is_rejected() {
+    let app = spawn_app_with(|c| {
+        c.trusted_sources.api_key = Some(Secret::new(TRUSTED_SOURCE_API_KEY.into()));
+    })
+    .await;
+
+    for api_key in [None, Some("not-the-api-key")] {
+        let response = post_trusted_subscription(&app, api_key).await;
+
+        assert_eq!(401, response.status().as_u16());
+        assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    }
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .expect("Could not exec query");
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn trusted_subscriptions_are_disabled_without_an_api_key() {
+    let app = spawn_app().await;
+
+    let response = post_trusted_subscription(&app, Some("")).await;
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_resends_the_same_link() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app.post_subscriptions(body.into()).await;
+    let second = app
+        .post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+    let subscribers: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers, 1);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_links = app.get_confirmation_links(&email_requests[0]);
+    let second_links = app.get_confirmation_links(&email_requests[1]);
+    assert_eq!(first_links.html, second_links.html);
+}
+
+#[tokio::test]
+async fn concurrent_double_submits_both_succeed() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let (first, second) = tokio::join!(
+        app.post_subscriptions(body.into()),
+        app.post_subscriptions(body.into())
+    );
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+    let subscribers: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers, 1);
+}
+
+#[tokio::test]
+async fn subscribing_a_confirmed_email_returns_409() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(409, response.status().as_u16());
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "already_subscribed");
+}
+
+async fn insert_pending_subscriber(app: &TestApp, email: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) \
+        VALUES ($1, $2, 'name', now(), 'pending_confirmation')",
+    )
+    .bind(subscriber_id)
+    .bind(email)
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+#[tokio::test]
+async fn a_retry_with_the_same_idempotency_key_replays_the_first_response() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let response = app
+            .post_subscriptions_with_idempotency_key(body.into(), "retry-1")
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+        let subscribed: SubscribeResponse = response.json().await.unwrap();
+        ids.push(subscribed.id);
+    }
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(ids[0], ids[1]);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+}
+
+#[tokio::test]
+async fn distinct_idempotency_keys_create_distinct_subscribers() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let first: SubscribeResponse = app
+        .post_subscriptions_with_idempotency_key(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com".into(),
+            "key-1",
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    let second: SubscribeResponse = app
+        .post_subscriptions_with_idempotency_key(
+            "name=octavia&email=octavia_butler%40gmail.com".into(),
+            "key-2",
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    assert_ne!(first.id, second.id);
+}
+
+#[tokio::test]
+async fn an_empty_idempotency_key_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions_with_idempotency_key(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com".into(),
+            "",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_idempotency_key");
+}
+
+#[tokio::test]
+async fn a_colliding_token_is_regenerated() {
+    let app = spawn_app().await;
+    let first = insert_pending_subscriber(&app, "first@example.com").await;
+    let second = insert_pending_subscriber(&app, "second@example.com").await;
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    store_new_token(&mut transaction, TokenKind::Confirmation, first, 3, || {
+        "colliding".into()
+    })
+    .await
+    .unwrap();
+
+    let mut tokens = vec!["fresh", "colliding"];
+    let token = store_new_token(&mut transaction, TokenKind::Confirmation, second, 3, || {
+        tokens.pop().unwrap().into()
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(token, "fresh");
+    transaction.commit().await.unwrap();
+    let owner: Uuid = sqlx::query_scalar(
+        "SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = 'fresh'",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(owner, second);
+}
+
+#[tokio::test]
+async fn token_generation_gives_up_after_the_configured_attempts() {
+    let app = spawn_app().await;
+    let first = insert_pending_subscriber(&app, "first@example.com").await;
+    let second = insert_pending_subscriber(&app, "second@example.com").await;
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    store_new_token(&mut transaction, TokenKind::Confirmation, first, 3, || {
+        "colliding".into()
+    })
+    .await
+    .unwrap();
+
+    let mut attempts = 0;
+    let outcome = store_new_token(&mut transaction, TokenKind::Confirmation, second, 3, || {
+        attempts += 1;
+        "colliding".into()
+    })
+    .await;
+
+    assert!(outcome.is_err());
+    assert_eq!(attempts, 3);
+}
+
+#[tokio::test]
+async fn subscribing_is_rejected_once_the_subscriber_quota_is_reached() {
+    let app = spawn_app_with(|c| c.application.max_subscribers = Some(2)).await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_unconfirmed_subscriber("butler", "octavia_butler@gmail.com")
+        .await;
+
+    let response = app
+        .post_subscriptions("name=jemisin&email=nk_jemisin%40gmail.com".into())
+        .await;
+    assert_eq!(403, response.status().as_u16());
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "subscriber_quota_reached");
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+
+    // Signing up again while pending does not count twice.
+    let response = app
+        .post_subscriptions("name=butler&email=octavia_butler%40gmail.com".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    app.login().await;
+    let subscriber_id: Uuid =
+        sqlx::query_scalar("SELECT id FROM subscriptions WHERE email = 'ursula_le_guin@gmail.com'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    let response = app.delete_subscriber(&subscriber_id.to_string()).await;
+    assert_eq!(204, response.status().as_u16());
+
+    let response = app
+        .post_subscriptions("name=jemisin&email=nk_jemisin%40gmail.com".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+/// Suppress the subscriber by hand, as an admin would at their request.
+async fn suppress_manually(app: &TestApp, email: &str) {
+    app.login().await;
+    app.post_suppressions(&serde_json::json!({ "email": email }))
+        .await;
+    sqlx::query("UPDATE subscriptions SET status = 'suppressed' WHERE email = $1")
+        .bind(email)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn a_suppressed_subscriber_can_resubscribe_and_must_confirm_again() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    suppress_manually(&app, "ursula_le_guin@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "ursula");
+    assert_eq!(saved.status, "pending_confirmation");
+    let suppressions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppressions, 0);
+    let audit = sqlx::query!(
+        "SELECT user_id, action, metadata FROM audit_log WHERE action = 'subscriber.resubscribe'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(audit.user_id, None);
+    assert_eq!(audit.metadata["suppression_reason"], "manual");
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let status: String = sqlx::query_scalar("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn a_link_sent_before_the_suppression_cannot_confirm_a_resubscription() {
+    let app = spawn_app().await;
+    let old_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    suppress_manually(&app, "ursula_le_guin@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    let response = reqwest::get(old_links.html).await.unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+    let status: String = sqlx::query_scalar("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn signing_up_again_does_not_lift_a_permanent_suppression() {
+    for record in [
+        serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "HardBounce",
+            "Email": "ursula_le_guin@gmail.com",
+        }),
+        serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "Email": "ursula_le_guin@gmail.com",
+        }),
+    ] {
+        let app = spawn_app().await;
+        app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+            .await;
+        app.post_postmark_webhook(&record).await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&app.email_server)
+            .await;
+
+        let response = app
+            .post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into())
+            .await;
+        app.dispatch_all_pending_emails().await;
+
+        assert_eq!(200, response.status().as_u16(), "{}", record);
+        let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(saved.name, "le guin");
+        assert_eq!(saved.status, "suppressed");
+        let suppressions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM suppressions")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(suppressions, 1);
+    }
+}
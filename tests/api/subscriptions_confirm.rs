@@ -0,0 +1,681 @@
+use std::sync::{Arc, Mutex};
+
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::{test as actix_test, web, App};
+use chrono::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, CapturedLogs, TestApp};
+use zero2prod::clock::{Clock, SystemClock};
+use zero2prod::error::ErrorBody;
+use zero2prod::events::{ConfirmedEvent, EventFuture, EventSink, NoopEventSink};
+use zero2prod::metrics::Metrics;
+use zero2prod::routes::{confirm, ConfirmationRedirect, ConfirmationTokenTtl, WelcomeEmail};
+use zero2prod::startup::ApplicationBaseUrl;
+use zero2prod::telemetry::get_subscriber;
+use zero2prod::token_cache::TokenCache;
+use zero2prod::token_sweeper::sweep_orphaned_tokens;
+
+#[tokio::test]
+async fn confirmations_without_token_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(&format!("{}/subscriptions/confirm", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn confirmations_with_an_unknown_token_are_rejected_with_a_401() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=unknown",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn confirmations_with_an_expired_token_are_rejected_with_a_410() {
+    let app = spawn_app_with(|c| {
+        c.application.confirmation_token_ttl_seconds = 3600;
+        c.application.confirmation_token_cache_capacity = 0;
+    })
+    .await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    age_tokens(&app, "2 hours").await;
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "expired_token");
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn a_token_is_still_valid_just_before_it_expires() {
+    let app = spawn_app_with(|c| {
+        c.application.confirmation_token_ttl_seconds = 3600;
+        c.application.confirmation_token_cache_capacity = 0;
+    })
+    .await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    age_tokens(&app, "3590 seconds").await;
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_cached_token_expires_on_the_application_clock() {
+    let app = spawn_app_with(|c| c.application.confirmation_token_ttl_seconds = 3600).await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    // Only the cache still knows the token: a lookup would find nothing.
+    sqlx::query("DELETE FROM subscription_tokens")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.clock.advance(Duration::hours(2));
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "expired_token");
+}
+
+#[tokio::test]
+async fn a_cached_token_is_not_expired_by_the_database_clock() {
+    let app = spawn_app_with(|c| c.application.confirmation_token_ttl_seconds = 3600).await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    // Still fresh on the application's clock, which the cache goes by.
+    age_tokens(&app, "2 hours").await;
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_token_is_not_expired_by_clock_skew() {
+    let app = spawn_app_with(|c| {
+        c.application.confirmation_token_ttl_seconds = 1;
+        c.application.confirmation_token_cache_capacity = 0;
+    })
+    .await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    // The application's clock runs well ahead of the database's.
+    app.clock.advance(Duration::hours(1));
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+/// Move the issuance of every token `interval` into the past.
+async fn age_tokens(app: &TestApp, interval: &str) {
+    sqlx::query("UPDATE subscription_tokens SET created_at = created_at - $1::interval")
+        .bind(interval)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn subscribing_again_after_the_token_expired_sends_a_link_that_confirms() {
+    let app = spawn_app_with(|c| {
+        c.application.confirmation_token_ttl_seconds = 3600;
+        c.application.confirmation_token_cache_capacity = 0;
+    })
+    .await;
+    let expired_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    age_tokens(&app, "2 hours").await;
+
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    assert_ne!(confirmation_links.html, expired_links.html);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let status: String = sqlx::query_scalar("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn resending_pending_confirmations_replaces_expired_tokens() {
+    let app = spawn_app_with(|c| {
+        c.application.confirmation_token_ttl_seconds = 3600;
+        c.application.confirmation_token_cache_capacity = 0;
+    })
+    .await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    age_tokens(&app, "2 hours").await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_resend_pending().await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+    let app = spawn_app().await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn a_suppressed_subscriber_is_not_confirmed_by_an_old_link() {
+    let app = spawn_app().await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    // E.g. the confirmation email hard bounced.
+    sqlx::query!("UPDATE subscriptions SET status = 'suppressed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "suppressed");
+}
+
+#[tokio::test]
+async fn a_cached_token_confirms_without_looking_it_up() {
+    let app = spawn_app().await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    // Looking the token up would now come back empty.
+    sqlx::query!("DELETE FROM subscription_tokens")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn a_token_missing_from_the_cache_is_looked_up_in_the_database() {
+    let app = spawn_app_with(|c| c.application.confirmation_token_cache_capacity = 0).await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_redirected_to_an_allowed_url() {
+    let app = spawn_app_with(|c| {
+        c.application.confirmation_redirect_url = Some("https://example.com/thanks".into());
+        c.application.confirmation_redirect_allowlist = vec!["https://example.com".into()];
+    })
+    .await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let response = app
+        .api_client
+        .get(confirmation_links.html)
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "https://example.com/thanks");
+}
+
+#[tokio::test]
+async fn a_redirect_outside_the_allowlist_is_not_followed() {
+    let app = spawn_app_with(|c| {
+        c.application.confirmation_redirect_url = Some("https://evil.example.net/".into());
+        c.application.confirmation_redirect_allowlist = vec!["https://example.com".into()];
+    })
+    .await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let response = app
+        .api_client
+        .get(confirmation_links.html)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("Location").is_none());
+}
+
+#[tokio::test]
+async fn confirming_a_subscriber_deletes_their_token() {
+    let app = spawn_app().await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(tokens.is_empty());
+}
+
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_twice_succeeds_both_times() {
+    let app = spawn_app().await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let first = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    let second = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_welcome_email_is_sent_on_the_first_confirmation_only() {
+    let app = spawn_app_with(|c| c.welcome_email.enabled = true).await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..2 {
+        let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        app.dispatch_all_pending_emails().await;
+    }
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["Subject"], "You're in!");
+}
+
+#[tokio::test]
+async fn no_welcome_email_is_sent_when_disabled() {
+    let app = spawn_app().await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn the_sweep_deletes_tokens_of_confirmed_subscribers_only() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_unconfirmed_subscriber("butler", "octavia_butler@gmail.com")
+        .await;
+    // A subscriber confirmed before tokens were deleted on confirmation.
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'confirmed' WHERE email = 'ursula_le_guin@gmail.com'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let deleted = sweep_orphaned_tokens(&app.db_pool).await.unwrap();
+
+    assert_eq!(deleted, 1);
+    let remaining = sqlx::query!(
+        r#"
+        SELECT s.email FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].email, "octavia_butler@gmail.com");
+}
+
+/// The confirmation endpoint on its own, called in-process so that a test
+/// can capture its spans or pick its token cache and event sink.
+fn confirm_app(
+    app: &TestApp,
+    token_cache: TokenCache,
+    sink: Arc<dyn EventSink>,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .app_data(web::Data::new(app.db_pool.clone()))
+        .app_data(web::Data::new(ConfirmationRedirect(None)))
+        .app_data(web::Data::new(Metrics::new()))
+        .app_data(web::Data::<dyn Clock>::from(
+            Arc::new(SystemClock) as Arc<dyn Clock>
+        ))
+        .app_data(web::Data::new(ConfirmationTokenTtl(Duration::hours(1))))
+        .app_data(web::Data::new(WelcomeEmail(None)))
+        .app_data(web::Data::new(ApplicationBaseUrl(app.base_url.clone())))
+        .app_data(web::Data::<dyn EventSink>::from(sink))
+        .app_data(web::Data::new(token_cache))
+        .route("/subscriptions/confirm", web::get().to(confirm))
+}
+
+#[tokio::test]
+async fn the_confirmation_span_records_the_outcome_but_not_the_token() {
+    let app = spawn_app().await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let token = token_of(&confirmation_links.html);
+    // Call the handler in-process, so that its spans reach our subscriber.
+    let logs = CapturedLogs::default();
+    let _guard = tracing::subscriber::set_default(get_subscriber(
+        "test".into(),
+        "info".into(),
+        logs.clone(),
+    ));
+    let service = actix_test::init_service(confirm_app(
+        &app,
+        TokenCache::new(0, Duration::hours(1), Arc::new(SystemClock)),
+        Arc::new(NoopEventSink),
+    ))
+    .await;
+
+    for (token, expected_status) in [(&token[..], 200), (&token[..], 200), ("unknown", 401)] {
+        let request = actix_test::TestRequest::get()
+            .uri(&format!(
+                "/subscriptions/confirm?subscription_token={}",
+                token
+            ))
+            .to_request();
+        let response = actix_test::call_service(&service, request).await;
+        assert_eq!(response.status().as_u16(), expected_status);
+    }
+
+    let logs = logs.contents();
+    assert!(logs.contains(r#""outcome":"confirmed""#), "{}", logs);
+    assert!(
+        logs.contains(r#""outcome":"already_confirmed""#),
+        "{}",
+        logs
+    );
+    assert!(logs.contains(r#""outcome":"unknown""#), "{}", logs);
+    assert!(logs.contains(r#""token_hash":""#), "{}", logs);
+    assert!(!logs.contains(&token), "{}", logs);
+}
+
+#[tokio::test]
+async fn a_confirmation_that_updates_no_rows_logs_a_warning() {
+    let app = spawn_app().await;
+    // A token cached for a subscriber who has since been deleted.
+    let token_cache = TokenCache::new(1, Duration::hours(1), Arc::new(SystemClock));
+    token_cache.insert("deleted-subscriber-token", Uuid::new_v4());
+    let logs = CapturedLogs::default();
+    let _guard = tracing::subscriber::set_default(get_subscriber(
+        "test".into(),
+        "info".into(),
+        logs.clone(),
+    ));
+    let service =
+        actix_test::init_service(confirm_app(&app, token_cache, Arc::new(NoopEventSink))).await;
+
+    let request = actix_test::TestRequest::get()
+        .uri("/subscriptions/confirm?subscription_token=deleted-subscriber-token")
+        .to_request();
+    let response = actix_test::call_service(&service, request).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let logs = logs.contents();
+    assert!(
+        logs.contains("Confirming the subscriber updated no rows."),
+        "{}",
+        logs
+    );
+    assert!(logs.contains(r#""rows_affected":0"#), "{}", logs);
+}
+
+/// An event sink tests can read back.
+#[derive(Clone, Default)]
+struct RecordingSink(Arc<Mutex<Vec<ConfirmedEvent>>>);
+
+impl EventSink for RecordingSink {
+    fn subscriber_confirmed<'a>(&'a self, event: &'a ConfirmedEvent) -> EventFuture<'a> {
+        self.0.lock().unwrap().push(event.clone());
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[tokio::test]
+async fn confirming_a_subscriber_reports_the_confirmed_event() {
+    let app = spawn_app().await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let sink = RecordingSink::default();
+    let service = actix_test::init_service(confirm_app(
+        &app,
+        TokenCache::new(0, Duration::hours(1), Arc::new(SystemClock)),
+        Arc::new(sink.clone()),
+    ))
+    .await;
+    let uri = format!(
+        "{}?{}",
+        confirmation_links.html.path(),
+        confirmation_links.html.query().unwrap()
+    );
+
+    // Following the link again confirms nothing new.
+    for _ in 0..2 {
+        let request = actix_test::TestRequest::get().uri(&uri).to_request();
+        let response = actix_test::call_service(&service, request).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let subscriber_id: Uuid = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let events = sink.0.lock().unwrap().clone();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].subscriber_id, subscriber_id);
+}
+
+#[tokio::test]
+async fn a_failing_event_sink_does_not_fail_the_confirmation() {
+    let app = spawn_app_with(|c| {
+        // Nothing listens there.
+        c.analytics.event_sink_url = Some("http://127.0.0.1:9/events".into());
+    })
+    .await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+/// The token of a confirmation link, to confirm with it in the path.
+fn token_of(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(key, _)| key == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+#[tokio::test]
+async fn the_token_can_be_passed_in_the_path_or_the_query() {
+    let app = spawn_app().await;
+    for (name, email, in_path) in [
+        ("le guin", "ursula_le_guin@gmail.com", true),
+        ("butler", "octavia_butler@gmail.com", false),
+    ] {
+        let confirmation_links = app.create_unconfirmed_subscriber(name, email).await;
+        let url = if in_path {
+            format!(
+                "{}/subscriptions/confirm/{}",
+                app.address,
+                token_of(&confirmation_links.html)
+            )
+        } else {
+            confirmation_links.html.to_string()
+        };
+
+        // Following the link again still succeeds.
+        for _ in 0..2 {
+            let response = reqwest::get(&url).await.unwrap();
+            assert_eq!(response.status().as_u16(), 200, "{}", url);
+        }
+        let (status,): (String,) =
+            sqlx::query_as("SELECT status FROM subscriptions WHERE email = $1")
+                .bind(email)
+                .fetch_one(&app.db_pool)
+                .await
+                .unwrap();
+        assert_eq!(status, "confirmed");
+    }
+}
+
+#[tokio::test]
+async fn unknown_and_expired_tokens_are_rejected_in_the_path_as_in_the_query() {
+    let app = spawn_app_with(|c| c.application.confirmation_token_cache_capacity = 0).await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let token = token_of(&confirmation_links.html);
+    age_tokens(&app, "1 year").await;
+
+    for (token, expected_status) in [("unknown", 401), (&token[..], 410)] {
+        let in_path = reqwest::get(format!("{}/subscriptions/confirm/{}", app.address, token))
+            .await
+            .unwrap();
+        let in_query = reqwest::get(format!(
+            "{}/subscriptions/confirm?subscription_token={}",
+            app.address, token
+        ))
+        .await
+        .unwrap();
+
+        assert_eq!(in_path.status().as_u16(), expected_status);
+        assert_eq!(in_query.status().as_u16(), expected_status);
+    }
+}
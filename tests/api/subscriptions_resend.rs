@@ -0,0 +1,105 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app_with, TestApp};
+use zero2prod::error::ErrorBody;
+use zero2prod::rate_limit::RateLimit;
+
+async fn spawn_app_with_cooldown() -> TestApp {
+    spawn_app_with(|c| {
+        c.rate_limits.confirmation_resend = Some(RateLimit {
+            requests: 1,
+            window_seconds: 60,
+        })
+    })
+    .await
+}
+
+#[tokio::test]
+async fn a_resend_emails_a_fresh_link_that_confirms_the_subscription() {
+    let app = spawn_app_with_cooldown().await;
+    let first_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_resend_confirmation("ursula_le_guin@gmail.com")
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, response.status().as_u16());
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_confirmation_links(&email_request);
+    assert_ne!(links.html, first_links.html);
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let status: String = sqlx::query_scalar("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn a_resend_within_the_cooldown_is_rejected() {
+    let app = spawn_app_with_cooldown().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app
+        .post_resend_confirmation("ursula_le_guin@gmail.com")
+        .await;
+    // Changing the case of the address does not get around the cooldown.
+    let second = app
+        .post_resend_confirmation("Ursula_Le_Guin@gmail.com")
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(429, second.status().as_u16());
+    let retry_after: u64 = second.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+    let error: ErrorBody = second.json().await.unwrap();
+    assert_eq!(error.code, "rate_limited");
+}
+
+#[tokio::test]
+async fn unknown_addresses_get_the_same_answers_as_pending_ones() {
+    let app = spawn_app_with_cooldown().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app.post_resend_confirmation("nobody@example.com").await;
+    let second = app.post_resend_confirmation("nobody@example.com").await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(0, first.bytes().await.unwrap().len());
+    assert_eq!(429, second.status().as_u16());
+}
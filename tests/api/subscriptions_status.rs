@@ -0,0 +1,155 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::error::ErrorBody;
+use zero2prod::routes::SubscriptionStatus;
+
+async fn management_token(app: &TestApp) -> String {
+    sqlx::query_scalar("SELECT management_token FROM management_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn a_management_token_returns_the_status_of_its_subscription() {
+    let app = spawn_app().await;
+    let confirmation_links = app
+        .create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let token = management_token(&app).await;
+
+    let response = app.get_subscription_status(&token).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let status: SubscriptionStatus = response.json().await.unwrap();
+    assert_eq!(status.email, "ursula_le_guin@gmail.com");
+    assert_eq!(status.status, "pending_confirmation");
+    assert_eq!(status.topics, ["weekly"]);
+
+    // The token outlives the confirmation.
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let status: SubscriptionStatus = app
+        .get_subscription_status(&token)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status.status, "confirmed");
+}
+
+/// Follow the link to manage the subscription out of the last email sent.
+async fn follow_manage_link(app: &TestApp) -> SubscriptionStatus {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let manage_links = app.get_manage_links(&email_request);
+    assert_eq!(manage_links.html, manage_links.plain_text);
+    reqwest::get(manage_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_confirmation_email_links_to_the_status_of_the_subscription() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let status = follow_manage_link(&app).await;
+
+    assert_eq!(status.email, "ursula_le_guin@gmail.com");
+    assert_eq!(status.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn the_welcome_email_links_to_the_status_of_the_subscription() {
+    let app = spawn_app_with(|c| c.welcome_email.enabled = true).await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let status = follow_manage_link(&app).await;
+
+    assert_eq!(status.email, "ursula_le_guin@gmail.com");
+    assert_eq!(status.status, "confirmed");
+}
+
+#[tokio::test]
+async fn newsletters_link_to_the_status_of_the_subscription() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let status = follow_manage_link(&app).await;
+
+    assert_eq!(status.email, "ursula_le_guin@gmail.com");
+    assert_eq!(status.status, "confirmed");
+}
+
+#[tokio::test]
+async fn an_unknown_management_token_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let response = app.get_subscription_status("not-a-token").await;
+
+    assert_eq!(401, response.status().as_u16());
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "unknown_token");
+}
+
+#[tokio::test]
+async fn a_confirmation_token_is_not_a_management_token() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let confirmation_token: String =
+        sqlx::query_scalar("SELECT subscription_token FROM subscription_tokens")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+
+    let response = app.get_subscription_status(&confirmation_token).await;
+
+    assert_eq!(401, response.status().as_u16());
+}
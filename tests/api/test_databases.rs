@@ -0,0 +1,64 @@
+use sqlx::{Connection, Executor, PgConnection};
+use uuid::Uuid;
+use zero2prod::configuration::get_configuration;
+use zero2prod::test_databases::{drop_test_database, list_test_databases, TEST_DATABASE_PREFIX};
+
+async fn server_connection() -> PgConnection {
+    let config = get_configuration().unwrap();
+    PgConnection::connect_with(&config.database.without_db())
+        .await
+        .unwrap()
+}
+
+async fn create_database(connection: &mut PgConnection, name: &str) {
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, name).as_str())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn only_test_databases_are_listed() {
+    let mut connection = server_connection().await;
+    let test_database = format!("{}{}", TEST_DATABASE_PREFIX, Uuid::new_v4());
+    let other_database = format!("maint_{}", Uuid::new_v4());
+    create_database(&mut connection, &test_database).await;
+    create_database(&mut connection, &other_database).await;
+
+    let listed = list_test_databases(&mut connection).await.unwrap();
+
+    assert!(listed.contains(&test_database));
+    assert!(!listed.contains(&other_database));
+    drop_test_database(&mut connection, &test_database)
+        .await
+        .unwrap();
+    connection
+        .execute(format!(r#"DROP DATABASE "{}";"#, other_database).as_str())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn a_test_database_is_dropped_despite_open_connections() {
+    let mut connection = server_connection().await;
+    let name = format!("{}{}", TEST_DATABASE_PREFIX, Uuid::new_v4());
+    create_database(&mut connection, &name).await;
+    let config = get_configuration().unwrap();
+    let _left_open = PgConnection::connect_with(&config.database.without_db().database(&name))
+        .await
+        .unwrap();
+
+    drop_test_database(&mut connection, &name).await.unwrap();
+
+    let listed = list_test_databases(&mut connection).await.unwrap();
+    assert!(!listed.contains(&name));
+}
+
+#[tokio::test]
+async fn other_databases_are_never_dropped() {
+    let mut connection = server_connection().await;
+
+    let outcome = drop_test_database(&mut connection, "postgres").await;
+
+    assert!(outcome.is_err());
+}
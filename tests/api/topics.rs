@@ -0,0 +1,161 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::TopicName;
+use zero2prod::error::ErrorBody;
+use zero2prod::migrations::MIGRATOR;
+use zero2prod::topics::set_default_topic;
+
+/// The migration adding the default topic.
+const DEFAULT_TOPIC_MIGRATION: i64 = 20230201090000;
+
+fn newsletter_request_body(topic: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "topic": topic,
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn subscribers_are_enrolled_in_the_topics_they_pick() {
+    let app = spawn_app().await;
+
+    app.create_unconfirmed_subscriber_with(serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "topics": "weekly, Product,weekly",
+    }))
+    .await;
+
+    assert_eq!(enrolled_topics(&app).await, vec!["product", "weekly"]);
+}
+
+async fn enrolled_topics(app: &TestApp) -> Vec<String> {
+    sqlx::query_scalar("SELECT topic FROM subscriber_topics ORDER BY topic")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn subscribers_picking_no_topics_join_the_default_topic() {
+    let app = spawn_app().await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(enrolled_topics(&app).await, vec!["weekly"]);
+}
+
+#[tokio::test]
+async fn the_default_topic_is_configurable() {
+    let app = spawn_app_with(|c| c.application.default_topic = "digest".into()).await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(enrolled_topics(&app).await, vec!["digest"]);
+}
+
+#[tokio::test]
+async fn existing_subscribers_are_migrated_into_the_default_topic() {
+    let app = spawn_app_with(|c| c.application.default_topic = "digest".into()).await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    // Roll back to before the migration, with a subscriber who joined before
+    // topics existed.
+    MIGRATOR
+        .undo(&app.db_pool, DEFAULT_TOPIC_MIGRATION - 1)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM subscriber_topics")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    MIGRATOR.run(&app.db_pool).await.unwrap();
+    // What the application does on start.
+    let digest = TopicName::parse("digest".into()).unwrap();
+    set_default_topic(&app.db_pool, &digest).await.unwrap();
+
+    assert_eq!(enrolled_topics(&app).await, vec!["digest"]);
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(&newsletter_request_body("digest"))
+        .await;
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_topic_is_rejected() {
+    let app = spawn_app().await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&topics=weekly,gossip";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 422);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_subscriber");
+    assert!(error.message.contains("gossip"), "{}", error.message);
+}
+
+#[tokio::test]
+async fn a_topic_scoped_issue_only_reaches_the_subscribers_of_that_topic() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber_with(serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "topics": "weekly",
+    }))
+    .await;
+    app.create_confirmed_subscriber_with(serde_json::json!({
+        "name": "butler",
+        "email": "octavia_butler@gmail.com",
+        "topics": "product",
+    }))
+    .await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&newsletter_request_body("weekly"))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app.email_server.received_requests().await.unwrap().pop();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.unwrap().body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_topic_is_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .post_newsletters(&newsletter_request_body("gossip"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 422);
+    let error: ErrorBody = response.json().await.unwrap();
+    assert_eq!(error.code, "invalid_newsletter");
+}
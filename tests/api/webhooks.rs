@@ -0,0 +1,97 @@
+use crate::helpers::spawn_app;
+
+fn hard_bounce(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "TypeCode": 1,
+        "Email": email,
+        "Description": "The server was unable to deliver your message",
+    })
+}
+
+#[tokio::test]
+async fn a_hard_bounce_suppresses_the_subscriber() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let response = app
+        .post_postmark_webhook(&hard_bounce("ursula_le_guin@gmail.com"))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions;")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Could not exec query");
+    assert_eq!(saved.status, "suppressed");
+    let suppression = sqlx::query!("SELECT email, reason FROM suppressions;")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Could not exec query");
+    assert_eq!(suppression.email, "ursula_le_guin@gmail.com");
+    assert_eq!(suppression.reason, "hard_bounce");
+}
+
+#[tokio::test]
+async fn unknown_events_are_acknowledged() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Delivery",
+            "Recipient": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions;")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Could not exec query");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn an_invalid_secret_is_rejected_with_401() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .basic_auth("postmark", Some("not-the-secret"))
+        .json(&hard_bounce("ursula_le_guin@gmail.com"))
+        .send()
+        .await
+        .expect("Request failed");
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="postmark""#,
+        response.headers()["WWW-Authenticate"]
+    );
+    let saved = sqlx::query!("SELECT status FROM subscriptions;")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Could not exec query");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn an_unauthenticated_request_is_rejected_before_its_body_is_parsed() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark", &app.address))
+        .header("Content-Type", "application/json")
+        .body("not json at all")
+        .send()
+        .await
+        .expect("Request failed");
+
+    assert_eq!(401, response.status().as_u16());
+}